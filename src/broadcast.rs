@@ -0,0 +1,223 @@
+//! Broadcasts paired from livestream events.
+//!
+//! A livestream leaves several unconnected records behind: content when it
+//! starts and ends, presence observations while it is live and, often, a
+//! recording posted as content afterwards. Where a platform has a rule under
+//! `[broadcasts]` in the configuration file naming these types, they are
+//! paired into a single [`Broadcast`]:
+//! - each start event begins a broadcast.
+//! - the first end event after it, strictly before the next start event and
+//!   within `max_duration` seconds, ends it. Without one, the broadcast is
+//!   open.
+//! - presence observations of the presence type between the two belong to
+//!   it.
+//! - the recording is the first content of the recording type referring to
+//!   the start or end event by content ID, or failing that, the first created
+//!   while the broadcast was live or within `recording_window` seconds of it
+//!   ending.
+//!
+//! Events are placed at their creation time, or the time they were retrieved
+//! if they have none.
+//!
+//! Coverage is judged as in [`crate::session`]: anywhere more than the gap for
+//! the presence type passes between the start event, presence observations
+//! and end event is a gap in coverage, during which the broadcast may have
+//! been interrupted without anyone noticing.
+//!
+//! Broadcasts are returned per subject by
+//! [`crate::routes::broadcasts`].
+
+use crate::config::{BroadcastRule, SessionConfig};
+use crate::data::Data;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Used when a rule doesn't set them, in seconds.
+const MAX_DURATION: i64 = 86400;
+const RECORDING_WINDOW: i64 = 3600;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Broadcast {
+    pub platform: String,
+    pub platform_id: String,
+    pub started_at: DateTime<Utc>,
+    /// None means the broadcast hasn't been seen ending.
+    pub ended_at: Option<DateTime<Utc>>,
+    /// In seconds, once ended.
+    pub duration: Option<i64>,
+    /// Content IDs of the start and end events and the recording.
+    pub start_id: String,
+    pub end_id: Option<String>,
+    pub recording_id: Option<String>,
+    /// Presence observations made during the broadcast.
+    pub observations: u64,
+    pub gaps: Vec<CoverageGap>,
+}
+
+impl Broadcast {
+    /// Whether the broadcast could have been live at any point between `from`
+    /// and `to`. Either may be left open.
+    pub fn overlaps(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> bool {
+        from.map_or(true, |from| self.ended_at.map_or(true, |e| e >= from))
+            && to.map_or(true, |to| self.started_at <= to)
+    }
+}
+
+/// A stretch of a broadcast that no observation covers.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CoverageGap {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+// Content of one of the rule's types.
+struct Event<'a> {
+    content_id: &'a String,
+    at: DateTime<Utc>,
+    refers_to: HashSet<&'a String>,
+}
+
+fn events<'a>(content: &'a [Data], content_type: &str) -> Vec<Event<'a>> {
+    let mut events: Vec<Event> = content
+        .iter()
+        .filter_map(|c| match c {
+            Data::Content {
+                content_type: t,
+                content_id,
+                created_at,
+                retrieved_at,
+                references,
+                ..
+            } if t == content_type => Some(Event {
+                content_id,
+                at: created_at.unwrap_or(*retrieved_at),
+                refers_to: references.iter().flat_map(|r| r.values()).collect(),
+            }),
+            _ => None,
+        })
+        .collect();
+    events.sort_by_key(|e| e.at);
+    events
+}
+
+/// The broadcasts of a profile following `rule`, earliest first. `content`
+/// and `presence` are the profile's content and presence in any order.
+pub fn broadcasts(
+    platform_id: &str,
+    platform: &str,
+    content: &[Data],
+    presence: &[Data],
+    rule: &BroadcastRule,
+    sessions: &SessionConfig,
+) -> Vec<Broadcast> {
+    let starts = events(content, &rule.start);
+    let ends = events(content, &rule.end);
+    let recordings = rule
+        .recording
+        .as_ref()
+        .map_or(Vec::new(), |r| events(content, r));
+    let mut observations: Vec<DateTime<Utc>> = presence
+        .iter()
+        .filter_map(|p| match p {
+            Data::Presence {
+                presence_type,
+                retrieved_at,
+                ..
+            } if Some(presence_type) == rule.presence.as_ref() => {
+                Some(*retrieved_at)
+            }
+            _ => None,
+        })
+        .collect();
+    observations.sort();
+
+    let max_duration =
+        Duration::seconds(rule.max_duration.unwrap_or(MAX_DURATION));
+    let recording_window =
+        Duration::seconds(rule.recording_window.unwrap_or(RECORDING_WINDOW));
+    let gap = rule
+        .presence
+        .as_ref()
+        .map(|p| sessions.gap(p))
+        .unwrap_or(max_duration);
+
+    let mut used = HashSet::new();
+    let mut broadcasts = Vec::new();
+    for (i, start) in starts.iter().enumerate() {
+        // An end event at the same time as the next start event belongs to
+        // the next broadcast.
+        let next = starts.get(i + 1).map(|next| next.at);
+        let limit = next.map_or(start.at + max_duration, |next| {
+            next.min(start.at + max_duration)
+        });
+        let end = ends.iter().find(|e| {
+            e.at >= start.at
+                && e.at <= limit
+                && next.map_or(true, |next| e.at < next)
+        });
+        let until = end.map_or(limit, |e| e.at);
+
+        let during: Vec<DateTime<Utc>> = observations
+            .iter()
+            .filter(|t| **t >= start.at && **t <= until)
+            .copied()
+            .collect();
+
+        let refers = |r: &Event| {
+            r.refers_to.contains(start.content_id)
+                || end.map_or(false, |e| r.refers_to.contains(e.content_id))
+        };
+        let recording = recordings
+            .iter()
+            .filter(|r| !used.contains(r.content_id))
+            .find(|r| refers(r))
+            .or_else(|| {
+                recordings
+                    .iter()
+                    .filter(|r| !used.contains(r.content_id))
+                    .find(|r| {
+                        r.at >= start.at && r.at <= until + recording_window
+                    })
+            });
+        if let Some(recording) = recording {
+            used.insert(recording.content_id);
+        }
+
+        // Without an end event, coverage runs up to the last observation.
+        let mut points = vec![start.at];
+        points.extend(&during);
+        points.extend(end.map(|e| e.at));
+        let gaps = if rule.presence.is_some() {
+            points
+                .windows(2)
+                .filter(|w| w[1] - w[0] > gap)
+                .map(|w| CoverageGap {
+                    from: w[0],
+                    to: w[1],
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        broadcasts.push(Broadcast {
+            platform: platform.to_string(),
+            platform_id: platform_id.to_string(),
+            started_at: start.at,
+            ended_at: end.map(|e| e.at),
+            duration: end.map(|e| (e.at - start.at).num_seconds()),
+            start_id: start.content_id.clone(),
+            end_id: end.map(|e| e.content_id.clone()),
+            recording_id: recording.map(|r| r.content_id.clone()),
+            observations: during.len() as u64,
+            gaps,
+        });
+    }
+    broadcasts
+}
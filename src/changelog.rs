@@ -0,0 +1,78 @@
+//! Field-level changes to profile metadata.
+//!
+//! Every [`Data::Meta`] is stored whole. Changes are worked out when they are
+//! asked for, by comparing each profile's metadata with the metadata retrieved
+//! just before it, so they stay correct even when metadata is submitted out of
+//! order.
+//!
+//! As explained in [`crate::data`], a field missing from a later profile is
+//! treated as having been removed.
+
+use crate::data::Data;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const TRACKED_FIELDS: [&str; 7] = [
+    "username",
+    "display_name",
+    "bio",
+    "profile_picture",
+    "verified",
+    "private",
+    "suspended_or_banned",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MetaChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+    /// When the old value was last seen. The change happened between this and
+    /// `retrieved_at`.
+    pub previous_retrieved_at: DateTime<Utc>,
+    /// When the new value was first seen.
+    pub retrieved_at: DateTime<Utc>,
+    pub added_by: Option<String>,
+}
+
+/// Every change to a tracked field across `metas`, oldest first. Anything
+/// other than [`Data::Meta`] is ignored.
+pub fn meta_changes(metas: &[Data]) -> Vec<MetaChange> {
+    let mut metas: Vec<(DateTime<Utc>, &Option<String>, Value)> = metas
+        .iter()
+        .filter_map(|d| match d {
+            Data::Meta {
+                retrieved_at,
+                added_by,
+                ..
+            } => Some((
+                *retrieved_at,
+                added_by,
+                serde_json::to_value(d).unwrap(),
+            )),
+            _ => None,
+        })
+        .collect();
+    metas.sort_by_key(|(retrieved_at, _, _)| *retrieved_at);
+
+    let mut changes = Vec::new();
+    for pair in metas.windows(2) {
+        let (previous_retrieved_at, _, previous) = &pair[0];
+        let (retrieved_at, added_by, current) = &pair[1];
+        for field in TRACKED_FIELDS {
+            if previous[field] != current[field] {
+                changes.push(MetaChange {
+                    field: field.to_string(),
+                    from: previous[field].clone(),
+                    to: current[field].clone(),
+                    previous_retrieved_at: *previous_retrieved_at,
+                    retrieved_at: *retrieved_at,
+                    added_by: (*added_by).clone(),
+                });
+            }
+        }
+    }
+    changes
+}
@@ -1,24 +1,319 @@
 //! Functions for the configuration file.
 
+use crate::data;
+
 use axum::async_trait;
 use axum::extract::{FromRequest, RequestParts};
 use axum::response::Response;
-use serde::Deserialize;
+use chrono::Duration;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct IConfig {
-    pub mongodb: MDBIConfig,
+    pub mongodb: Option<MDBIConfig>,
+    pub postgres: Option<PGIConfig>,
     pub content_types: HashMap<String, Vec<String>>,
     pub presence_types: HashMap<String, Vec<String>>,
+    /// Relation types, such as following, by platform.
+    #[serde(default)]
+    pub relationship_types: HashMap<String, Vec<String>>,
+    /// Metric types, such as follower counts, by platform.
+    #[serde(default)]
+    pub metric_types: HashMap<String, Vec<String>>,
+    /// Rules for content types, by platform and then type.
+    #[serde(default)]
+    pub content_schemas: HashMap<String, HashMap<String, TypeSchema>>,
+    /// Rules for presence types, by platform and then type.
+    #[serde(default)]
+    pub presence_schemas: HashMap<String, HashMap<String, TypeSchema>>,
     pub settings: Settings,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub reputation: ReputationConfig,
+    #[serde(default)]
+    pub consensus: ConsensusConfig,
+    #[serde(default)]
+    pub sessions: SessionConfig,
+    /// Rules for pairing livestream events into broadcasts, by platform.
+    #[serde(default)]
+    pub broadcasts: HashMap<String, BroadcastRule>,
     pub network: NetworkConfig,
     pub tls: TLSConfig,
 }
 
+/// Rules that data of a content or presence type must follow, see
+/// [`crate::data::Data::verify`]. Anything left out isn't checked. Naming a
+/// field the kind of data doesn't have, or a rule that doesn't exist, is an
+/// error when the config is loaded.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypeSchema {
+    /// Optional fields that must be given. Presence has none, but any of the
+    /// fields it always has can be named.
+    pub required: Vec<String>,
+    /// Optional fields that may be given, along with those required.
+    pub optional: Option<Vec<String>>,
+    /// The keys allowed in `references`.
+    pub references: Option<Vec<String>>,
+    pub min_media: Option<usize>,
+    pub max_media: Option<usize>,
+    /// The format of profile IDs.
+    pub id: Option<Pattern>,
+    /// The format of content IDs.
+    pub content_id: Option<Pattern>,
+}
+
+/// A regular expression, written as a string in the configuration file.
+#[derive(Clone, Debug)]
+pub struct Pattern(pub Regex);
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl IConfig {
+    /// The rules for a content type on a platform, if it has any.
+    pub fn content_schema(
+        &self,
+        platform: &str,
+        content_type: &str,
+    ) -> Option<&TypeSchema> {
+        self.content_schemas.get(platform)?.get(content_type)
+    }
+
+    /// The rules for a presence type on a platform, if it has any.
+    pub fn presence_schema(
+        &self,
+        platform: &str,
+        presence_type: &str,
+    ) -> Option<&TypeSchema> {
+        self.presence_schemas.get(platform)?.get(presence_type)
+    }
+
+    /// Checks that every schema only names fields its kind of data has.
+    pub fn check_schemas(&self) -> Result<(), String> {
+        let content_fields =
+            [data::CONTENT_FIELDS, data::CONTENT_OPTIONAL_FIELDS].concat();
+        let kinds = [
+            ("content", &self.content_schemas, &content_fields[..]),
+            ("presence", &self.presence_schemas, data::PRESENCE_FIELDS),
+        ];
+        for (kind, schemas, fields) in kinds {
+            for (platform, types) in schemas {
+                for (data_type, schema) in types {
+                    let mut named = schema
+                        .required
+                        .iter()
+                        .chain(schema.optional.iter().flatten());
+                    if let Some(field) =
+                        named.find(|f| !fields.contains(&f.as_str()))
+                    {
+                        return Err(format!(
+                            "Unknown field `{field}` in the schema for {kind} \
+                            type {data_type} on {platform}."
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct Settings {
     pub log_level: Option<String>,
+    #[serde(default)]
+    pub backend: Backend,
+}
+
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    MongoDB,
+    PostgreSQL,
+    Memory,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::MongoDB
+    }
+}
+
+/// Scheduling of the queue, see [`crate::routes::queue`]. All durations are
+/// in seconds.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// Content created within this long of being added makes its profile hot.
+    pub promotion_window: i64,
+    /// How long a profile stays hot after its latest promotion.
+    pub hot_decay: i64,
+    /// How often hot profiles are due to be processed.
+    pub hot_interval: i64,
+    /// How often every other profile is due to be processed.
+    pub max_staleness: i64,
+    /// How long a data provider has to finish a job before it is handed out
+    /// again.
+    pub lease: i64,
+    /// Overrides `lease` for particular platforms.
+    pub leases: HashMap<String, i64>,
+    /// How often expired leases are cleared.
+    pub reap_interval: u64,
+    /// The most jobs that can be taken in one request.
+    pub max_batch: usize,
+    /// The longest a request to /queue can wait for a job to become
+    /// available.
+    pub max_wait: u64,
+    /// How long a job waits after its first failure. Doubles with each
+    /// failure in a row.
+    pub backoff: i64,
+    pub max_backoff: i64,
+    /// Failures in a row before a profile is quarantined.
+    pub quarantine_after: u32,
+    /// Whether a suspected deletion can only be confirmed by a different data
+    /// provider than the one that suspected it, see [`crate::tombstone`].
+    pub independent_confirmation: bool,
+}
+
+impl QueueConfig {
+    pub fn lease(&self, platform: &str) -> Duration {
+        Duration::seconds(*self.leases.get(platform).unwrap_or(&self.lease))
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            promotion_window: 86400,
+            hot_decay: 259200,
+            hot_interval: 900,
+            max_staleness: 21600,
+            lease: 30,
+            leases: HashMap::new(),
+            reap_interval: 5,
+            max_batch: 50,
+            max_wait: 30,
+            backoff: 60,
+            max_backoff: 86400,
+            quarantine_after: 5,
+            independent_confirmation: false,
+        }
+    }
+}
+
+/// Judging data providers, see [`crate::reputation`].
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ReputationConfig {
+    /// How many submissions are assessed before a data provider is judged.
+    pub min_assessed: u64,
+    /// The lowest score, from 0 to 1, of a trusted data provider.
+    pub threshold: f64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            min_assessed: 20,
+            threshold: 0.5,
+        }
+    }
+}
+
+/// Agreeing between data providers, see [`crate::consensus`].
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    /// How many data providers must agree on a value.
+    pub quorum: usize,
+    /// How long before the newest observation, in seconds, other observations
+    /// still count.
+    pub window: i64,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            quorum: 1,
+            window: 86400,
+        }
+    }
+}
+
+/// Joining presence into sessions, see [`crate::session`].
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// The longest time, in seconds, between observations of one session.
+    pub gap: i64,
+    /// Overrides `gap` for particular presence types.
+    pub gaps: HashMap<String, i64>,
+}
+
+impl SessionConfig {
+    pub fn gap(&self, presence_type: &str) -> Duration {
+        Duration::seconds(*self.gaps.get(presence_type).unwrap_or(&self.gap))
+    }
+
+    /// The longest gap of any presence type.
+    pub fn max_gap(&self) -> Duration {
+        Duration::seconds(self.gaps.values().fold(self.gap, |a, b| a.max(*b)))
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            gap: 900,
+            gaps: HashMap::new(),
+        }
+    }
+}
+
+/// The content and presence types making up a broadcast on a platform, see
+/// [`crate::broadcast`].
+#[derive(Clone, Deserialize, Debug)]
+pub struct BroadcastRule {
+    /// The content type of the event starting a broadcast.
+    pub start: String,
+    /// The content type of the event ending a broadcast.
+    pub end: String,
+    /// The presence type observed while live.
+    pub presence: Option<String>,
+    /// The content type of the recording posted afterwards.
+    pub recording: Option<String>,
+    /// The longest a broadcast can last, in seconds.
+    pub max_duration: Option<i64>,
+    /// How long after a broadcast ends its recording may be posted, in
+    /// seconds.
+    pub recording_window: Option<i64>,
 }
 
 #[derive(Clone, Deserialize, Debug)]
@@ -42,9 +337,23 @@ pub struct MDBIConfig {
     pub database: String,
 }
 
+#[derive(Clone, Deserialize, Debug)]
+pub struct PGIConfig {
+    pub user: String,
+    pub password: String,
+    pub hosts: String,
+    pub port: String,
+    pub database: String,
+    // Tables are created in the default schema if this is not set.
+    pub schema: Option<String>,
+    // The most connections kept open at once. Four per CPU if not set.
+    pub pool_size: Option<usize>,
+}
+
 pub fn open(config_path: &str) -> Result<IConfig, Box<dyn std::error::Error>> {
     let config_str = &std::fs::read_to_string(config_path)?;
     let config: IConfig = toml::from_str(config_str)?;
+    config.check_schemas()?;
     Ok(config)
 }
 
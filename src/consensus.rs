@@ -0,0 +1,203 @@
+//! Consensus between data providers.
+//!
+//! Data providers can disagree, through mistakes, stale caches, a platform
+//! serving different versions of a page or plain dishonesty. Rather than
+//! trusting whichever observation is newest, each data provider's latest
+//! observation of an entity counts as one vote:
+//! - for a profile, each tracked field (see [`crate::changelog`]) of the
+//!   latest [`Data::Meta`] the data provider retrieved.
+//! - for a piece of content, whether the data provider last saw it deleted or
+//!   live, recorded as a [`StatusClaim`]. Content retrieved without being
+//!   marked deleted is live.
+//!
+//! Only votes retrieved within `window` seconds of the newest vote are
+//! counted, so that a profile that has since changed isn't mistaken for a
+//! disagreement. The value with the most votes, or the most recently seen of
+//! those tied, is agreed on once at least `quorum` data providers vote for it.
+//! Both are set under `[consensus]` in the configuration file.
+//!
+//! Content stored before claims were recorded has none and keeps its merged
+//! status, see [`Data::merge`].
+
+use crate::changelog::TRACKED_FIELDS;
+use crate::config::ConsensusConfig;
+use crate::data::Data;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A data provider's latest claim about whether content is deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StatusClaim {
+    pub content_id: String,
+    pub platform: String,
+    pub content_type: String,
+    pub platform_id: String,
+    pub added_by: String, // UUID
+    pub deleted: bool,
+    pub retrieved_at: DateTime<Utc>,
+}
+
+impl StatusClaim {
+    /// The claim made by `content`. Data other than [`Data::Content`] and
+    /// content without a data provider make no claim.
+    pub fn new(content: &Data) -> Option<Self> {
+        match content {
+            Data::Content {
+                id,
+                platform,
+                content_type,
+                retrieved_at,
+                content_id,
+                deleted,
+                added_by: Some(added_by),
+                ..
+            } => Some(Self {
+                content_id: content_id.clone(),
+                platform: platform.clone(),
+                content_type: content_type.clone(),
+                platform_id: id.clone(),
+                added_by: added_by.clone(),
+                deleted: deleted.unwrap_or(false),
+                retrieved_at: *retrieved_at,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Consensus {
+    pub field: String,
+    /// The value with the most votes.
+    pub value: Value,
+    pub providers: Vec<Option<String>>,
+    /// Whether at least `quorum` data providers voted for `value`.
+    pub agreed: bool,
+    /// Every other value voted for, most votes first.
+    pub dissent: Vec<Dissent>,
+}
+
+impl Consensus {
+    /// Whether the data providers failed to agree or any of them dissented.
+    pub fn disputed(&self) -> bool {
+        !self.agreed || !self.dissent.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Dissent {
+    pub value: Value,
+    pub providers: Vec<Option<String>>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// The consensus on each tracked field of a profile across `metas`. Anything
+/// other than [`Data::Meta`] is ignored.
+pub fn meta_consensus(
+    metas: &[Data],
+    config: &ConsensusConfig,
+) -> Vec<Consensus> {
+    let mut latest: HashMap<&Option<String>, (DateTime<Utc>, Value)> =
+        HashMap::new();
+    for d in metas {
+        if let Data::Meta {
+            retrieved_at,
+            added_by,
+            ..
+        } = d
+        {
+            if latest
+                .get(added_by)
+                .map_or(true, |(at, _)| at < retrieved_at)
+            {
+                latest.insert(
+                    added_by,
+                    (*retrieved_at, serde_json::to_value(d).unwrap()),
+                );
+            }
+        }
+    }
+
+    TRACKED_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let votes = latest.iter().map(|(added_by, (at, meta))| {
+                let value = meta.get(field).cloned().unwrap_or(Value::Null);
+                ((*added_by).clone(), *at, value)
+            });
+            tally(field, votes.collect(), config)
+        })
+        .collect()
+}
+
+/// The consensus on whether content is deleted across the latest `claims` of
+/// each data provider about it.
+pub fn status_consensus(
+    claims: &[&StatusClaim],
+    config: &ConsensusConfig,
+) -> Option<Consensus> {
+    let votes = claims
+        .iter()
+        .map(|c| {
+            (
+                Some(c.added_by.clone()),
+                c.retrieved_at,
+                Value::Bool(c.deleted),
+            )
+        })
+        .collect();
+    tally("deleted", votes, config)
+}
+
+/// `meta` with every agreed field replaced by its agreed value.
+pub fn agreed_meta(meta: Data, consensus: &[Consensus]) -> Data {
+    let mut value = serde_json::to_value(&meta).unwrap();
+    for c in consensus.iter().filter(|c| c.agreed) {
+        value[c.field.as_str()] = c.value.clone();
+    }
+    serde_json::from_value(value).unwrap_or(meta)
+}
+
+fn tally(
+    field: &str,
+    mut votes: Vec<(Option<String>, DateTime<Utc>, Value)>,
+    config: &ConsensusConfig,
+) -> Option<Consensus> {
+    let newest = votes.iter().map(|(_, at, _)| *at).max()?;
+    let window = Duration::seconds(config.window);
+    votes.retain(|(_, at, _)| *at >= newest - window);
+
+    let mut tallied: Vec<Dissent> = Vec::new();
+    for (added_by, at, value) in votes {
+        match tallied.iter_mut().find(|t| t.value == value) {
+            Some(t) => {
+                t.providers.push(added_by);
+                t.last_seen = t.last_seen.max(at);
+            }
+            None => tallied.push(Dissent {
+                value,
+                providers: vec![added_by],
+                last_seen: at,
+            }),
+        }
+    }
+    for t in &mut tallied {
+        t.providers.sort();
+    }
+    tallied.sort_by(|a, b| {
+        (b.providers.len(), b.last_seen).cmp(&(a.providers.len(), a.last_seen))
+    });
+
+    let mut tallied = tallied.into_iter();
+    let winner = tallied.next()?;
+    Some(Consensus {
+        field: field.to_string(),
+        agreed: winner.providers.len() >= config.quorum,
+        value: winner.value,
+        providers: winner.providers,
+        dissent: tallied.collect(),
+    })
+}
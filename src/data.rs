@@ -1,4 +1,5 @@
-//! Data enums for content, presence and profile metadata.
+//! Data enums for content, presence, profile metadata, relationships and
+//! metrics.
 //!
 //! # A note on Data
 //!
@@ -9,10 +10,12 @@
 //! ability to determine that all the previous data was not removed by the user
 //! between posts. This also applies to content.
 //!
-//! In the future, profiles with lots of coverage could be used to sniff out
-//! lazy data providers automatically or through a reputation system. However,
-//! at this stage, data providers posting all available data is key to the
-//! utility of the platform.
+//! Submissions are compared with what other data providers have submitted for
+//! the same profile to sniff out lazy data providers, see
+//! [`crate::reputation`]. Even so, data providers posting all available data
+//! is key to the utility of the platform. Where data providers disagree, the
+//! value most of them agree on is shown rather than the newest, see
+//! [`crate::consensus`].
 //!
 //! # Content
 //! Content exists to represent any event occurring at a discrete point in time.
@@ -122,17 +125,88 @@
 //! has changed from fetch to fetch. Given that each request of the profile will
 //! generally contain a full copy of that profile, it's easier to post the
 //! entire profile to Instrumentality to determine changes.
+//!
+//! # Relationships
+//! Relationships represent one profile being connected to another on the same
+//! platform, such as following it. They're typed like content, with the types
+//! a platform allows set under `[relationship_types]` in the configuration
+//! file. Relationships posted here only say that they held when observed.
+//! The complete list of a type for a profile is posted to /relationships
+//! instead, and comparing lists is how relationships are seen to stop, see
+//! [`crate::relationship`]. For example,
+//! ```json
+//! {
+//!     "kind": "relationship",
+//!     "schema_version": 1,
+//!     "from_id": "123456789",
+//!     "to_id": "987654321",
+//!     "platform": "twitter",
+//!     "relation_type": "follows",
+//!     "observed_at": "2022-01-01T00:00:00Z",
+//! };
+//! ```
+//!
+//! # Metrics
+//! Metrics are numbers that change over time, such as a profile's follower
+//! count or the views of a video, that would otherwise have to be squeezed
+//! into `references` or the bio. They're typed per platform under
+//! `[metric_types]` in the configuration file and give a `content_id` when
+//! they measure content rather than the profile. Metrics are kept as time
+//! series rather than with the rest of the data, see [`crate::metric`]. For
+//! example,
+//! ```json
+//! {
+//!     "kind": "metric",
+//!     "schema_version": 1,
+//!     "id": "123456789",
+//!     "platform": "twitter",
+//!     "metric_type": "followers",
+//!     "value": 1024,
+//!     "retrieved_at": "2022-01-01T00:00:00Z",
+//! };
+//! ```
+//!
+//! # Wire format
+//! Data may be submitted either untagged, as in the examples above, or tagged
+//! with its kind and the version of the format it follows, e.g.
+//! ```json
+//! {
+//!     "kind": "presence",
+//!     "schema_version": 1,
+//!     "id": "123456789",
+//!     "platform": "twitch",
+//!     "presence_type": "livestream",
+//!     "retrieved_at": "2022-01-01T00:00:00Z",
+//! };
+//! ```
+//! where `kind` is one of `presence`, `content`, `meta`, `relationship` or
+//! `metric`. Untagged data is matched against each kind in turn, so a misspelt
+//! field can make it another kind or fail without saying why. Tagged data is
+//! only ever its given kind, unknown fields are refused and errors name the
+//! field at fault. New kinds of data, such as relationships and metrics, are
+//! only accepted tagged. See [`SCHEMA_VERSION`].
+//!
+//! # Merging content
+//! Content is unique by platform, content type and content ID. When the same
+//! content is submitted again it is merged into what is already stored rather
+//! than inserted: fields only ever go from `None` to `Some`, references gain
+//! any keys they were missing and `deleted` may become true. See
+//! [`Data::merge`]. Edits to the body or media are kept as revisions instead,
+//! see [`crate::revision`].
 
-use crate::database::DBHandle;
+use crate::config::{IConfig, Pattern, TypeSchema};
+use crate::consensus::StatusClaim;
+use crate::database::{StorageResult, Store};
+use crate::metric::MetricPoint;
+use crate::relationship;
 use crate::routes::queue;
-use crate::routes::queue::InternalQueueItem;
 
 use chrono::{DateTime, Utc};
-use mongodb::{bson::doc, Collection};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Data {
     Presence {
@@ -142,6 +216,7 @@ pub enum Data {
         retrieved_at: DateTime<Utc>,
         added_by: Option<String>,
         added_at: Option<DateTime<Utc>>,
+        submission: Option<String>,
     },
     Content {
         id: String,
@@ -157,6 +232,7 @@ pub enum Data {
         references: Option<HashMap<String, String>>,
         added_by: Option<String>,
         added_at: Option<DateTime<Utc>>,
+        submission: Option<String>,
     },
     Meta {
         id: String,
@@ -173,39 +249,436 @@ pub enum Data {
         link: Option<String>,
         added_by: Option<String>,
         added_at: Option<DateTime<Utc>>,
+        submission: Option<String>,
+    },
+    Relationship {
+        from_id: String,
+        to_id: String,
+        platform: String,
+        relation_type: String,
+        observed_at: DateTime<Utc>,
+        added_by: Option<String>,
+        added_at: Option<DateTime<Utc>>,
+        submission: Option<String>,
+    },
+    Metric {
+        id: String,
+        platform: String,
+        metric_type: String,
+        value: f64,
+        content_id: Option<String>,
+        retrieved_at: DateTime<Utc>,
+        added_by: Option<String>,
+        added_at: Option<DateTime<Utc>>,
+        submission: Option<String>,
     },
 }
 
+/// The current version of the tagged wire format. Tagged data must give it as
+/// `schema_version`.
+pub const SCHEMA_VERSION: u64 = 1;
+
+// Data in the tagged wire format. Mirrors Data, except that fields set by the
+// server can't be submitted.
+#[derive(Deserialize)]
+#[serde(
+    remote = "Data",
+    tag = "kind",
+    rename_all = "snake_case",
+    deny_unknown_fields
+)]
+enum TaggedData {
+    Presence {
+        id: String,
+        platform: String,
+        presence_type: String,
+        retrieved_at: DateTime<Utc>,
+        #[serde(skip)]
+        added_by: Option<String>,
+        #[serde(skip)]
+        added_at: Option<DateTime<Utc>>,
+        #[serde(skip)]
+        submission: Option<String>,
+    },
+    Content {
+        id: String,
+        platform: String,
+        content_type: String,
+        retrieved_at: DateTime<Utc>,
+        content_id: String,
+        deleted: Option<bool>,
+        retrieved_from: Option<String>,
+        created_at: Option<DateTime<Utc>>,
+        body: Option<String>,
+        media: Option<Vec<String>>,
+        references: Option<HashMap<String, String>>,
+        #[serde(skip)]
+        added_by: Option<String>,
+        #[serde(skip)]
+        added_at: Option<DateTime<Utc>>,
+        #[serde(skip)]
+        submission: Option<String>,
+    },
+    Meta {
+        id: String,
+        platform: String,
+        username: String,
+        private: bool,
+        suspended_or_banned: bool,
+        retrieved_at: DateTime<Utc>,
+        display_name: Option<String>,
+        profile_picture: Option<String>,
+        bio: Option<String>,
+        verified: Option<bool>,
+        references: Option<HashMap<String, String>>,
+        link: Option<String>,
+        #[serde(skip)]
+        added_by: Option<String>,
+        #[serde(skip)]
+        added_at: Option<DateTime<Utc>>,
+        #[serde(skip)]
+        submission: Option<String>,
+    },
+    Relationship {
+        from_id: String,
+        to_id: String,
+        platform: String,
+        relation_type: String,
+        observed_at: DateTime<Utc>,
+        #[serde(skip)]
+        added_by: Option<String>,
+        #[serde(skip)]
+        added_at: Option<DateTime<Utc>>,
+        #[serde(skip)]
+        submission: Option<String>,
+    },
+    Metric {
+        id: String,
+        platform: String,
+        metric_type: String,
+        value: f64,
+        content_id: Option<String>,
+        retrieved_at: DateTime<Utc>,
+        #[serde(skip)]
+        added_by: Option<String>,
+        #[serde(skip)]
+        added_at: Option<DateTime<Utc>>,
+        #[serde(skip)]
+        submission: Option<String>,
+    },
+}
+
+/// The fields every [`Data::Content`] has.
+pub const CONTENT_FIELDS: &[&str] = &[
+    "id",
+    "platform",
+    "content_type",
+    "retrieved_at",
+    "content_id",
+];
+/// The optional fields of [`Data::Content`], see [`Data::populated`].
+pub const CONTENT_OPTIONAL_FIELDS: &[&str] = &[
+    "retrieved_from",
+    "created_at",
+    "body",
+    "media",
+    "references",
+];
+/// The fields every [`Data::Presence`] has. It has no optional fields.
+pub const PRESENCE_FIELDS: &[&str] =
+    &["id", "platform", "presence_type", "retrieved_at"];
+
 impl Data {
-    pub fn verify(
-        &self,
-        content_types: &HashMap<String, Vec<String>>,
-        presence_types: &HashMap<String, Vec<String>>,
-    ) -> bool {
-        match self {
+    /// Parses an item as submitted, either tagged or untagged, see
+    /// [the wire format](self#wire-format).
+    pub fn from_wire(mut value: Value) -> Result<Self, (Rejection, String)> {
+        let parsed = if value.get("kind").is_some() {
+            let version = value
+                .as_object_mut()
+                .and_then(|o| o.remove("schema_version"));
+            match version {
+                Some(v) if v.as_u64() == Some(SCHEMA_VERSION) => {
+                    TaggedData::deserialize(&value).map_err(|e| e.to_string())
+                }
+                Some(v) => {
+                    return Err((
+                        Rejection::UnsupportedVersion,
+                        format!("Unsupported schema_version {v}."),
+                    ))
+                }
+                None => Err("missing field `schema_version`".to_string()),
+            }
+        } else {
+            match Self::deserialize(&value) {
+                Ok(Self::Relationship { .. }) => {
+                    Err("relationships must be tagged with their kind".into())
+                }
+                Ok(Self::Metric { .. }) => {
+                    Err("metrics must be tagged with their kind".into())
+                }
+                parsed => parsed.map_err(|e| e.to_string()),
+            }
+        };
+        parsed.map_err(|e| {
+            if malformed_timestamp(&value) {
+                (Rejection::MalformedTimestamp, e)
+            } else {
+                (Rejection::Malformed, e)
+            }
+        })
+    }
+
+    /// Whether this server supports the platform and type of the data and it
+    /// follows any rules declared for its type, and if not, why.
+    pub fn verify(&self, config: &IConfig) -> Result<(), Rejection> {
+        let (supported, schema) = match self {
             Self::Presence {
                 platform,
                 presence_type,
                 ..
-            } => presence_types
-                .get(platform)
-                .unwrap()
-                .contains(presence_type),
+            } => (
+                config
+                    .presence_types
+                    .get(platform)
+                    .ok_or(Rejection::UnknownPlatform)?
+                    .contains(presence_type),
+                config.presence_schema(platform, presence_type),
+            ),
             Self::Content {
                 platform,
                 content_type,
                 ..
-            } => content_types.get(platform).unwrap().contains(content_type),
+            } => (
+                config
+                    .content_types
+                    .get(platform)
+                    .ok_or(Rejection::UnknownPlatform)?
+                    .contains(content_type),
+                config.content_schema(platform, content_type),
+            ),
             Self::Meta { platform, .. } => {
-                presence_types.contains_key(platform)
-                    || content_types.contains_key(platform)
+                if config.presence_types.contains_key(platform)
+                    || config.content_types.contains_key(platform)
+                    || config.relationship_types.contains_key(platform)
+                    || config.metric_types.contains_key(platform)
+                {
+                    (true, None)
+                } else {
+                    return Err(Rejection::UnknownPlatform);
+                }
             }
+            Self::Relationship {
+                platform,
+                relation_type,
+                ..
+            } => (
+                config
+                    .relationship_types
+                    .get(platform)
+                    .ok_or(Rejection::UnknownPlatform)?
+                    .contains(relation_type),
+                None,
+            ),
+            Self::Metric {
+                platform,
+                metric_type,
+                ..
+            } => (
+                config
+                    .metric_types
+                    .get(platform)
+                    .ok_or(Rejection::UnknownPlatform)?
+                    .contains(metric_type),
+                None,
+            ),
+        };
+        if !supported {
+            return Err(Rejection::UnknownType);
+        }
+        match schema {
+            Some(schema) => self.conforms(schema),
+            None => Ok(()),
         }
     }
 
+    /// The optional fields of this data that are filled in. Only
+    /// [`Data::Meta`] and [`Data::Content`] have any.
+    pub fn populated(&self) -> HashSet<&'static str> {
+        let fields: Vec<(&'static str, bool)> = match self {
+            Self::Meta {
+                display_name,
+                profile_picture,
+                bio,
+                verified,
+                references,
+                link,
+                ..
+            } => vec![
+                ("display_name", display_name.is_some()),
+                ("profile_picture", profile_picture.is_some()),
+                ("bio", bio.is_some()),
+                ("verified", verified.is_some()),
+                ("references", references.is_some()),
+                ("link", link.is_some()),
+            ],
+            Self::Content {
+                retrieved_from,
+                created_at,
+                body,
+                media,
+                references,
+                ..
+            } => vec![
+                ("retrieved_from", retrieved_from.is_some()),
+                ("created_at", created_at.is_some()),
+                ("body", body.is_some()),
+                ("media", media.is_some()),
+                ("references", references.is_some()),
+            ],
+            Self::Presence { .. }
+            | Self::Relationship { .. }
+            | Self::Metric { .. } => Vec::new(),
+        };
+        fields
+            .into_iter()
+            .filter(|(_, populated)| *populated)
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    fn conforms(&self, schema: &TypeSchema) -> Result<(), Rejection> {
+        let (fields, id, content_id, media, references) = match self {
+            Self::Content {
+                id,
+                content_id,
+                media,
+                references,
+                ..
+            } => (
+                CONTENT_FIELDS,
+                id,
+                Some(content_id),
+                media.as_ref(),
+                references.as_ref(),
+            ),
+            Self::Presence { id, .. } => {
+                (PRESENCE_FIELDS, id, None, None, None)
+            }
+            Self::Meta { id, .. }
+            | Self::Relationship { from_id: id, .. }
+            | Self::Metric { id, .. } => (&[][..], id, None, None, None),
+        };
+
+        // Fields every item of the kind has are always given.
+        let populated = self.populated();
+        if schema.required.iter().any(|f| {
+            !populated.contains(f.as_str()) && !fields.contains(&f.as_str())
+        }) {
+            return Err(Rejection::MissingField);
+        }
+        if let Some(optional) = &schema.optional {
+            let allowed = |f: &&str| {
+                schema.required.iter().chain(optional).any(|a| a == f)
+            };
+            if !populated.iter().all(allowed) {
+                return Err(Rejection::UnexpectedField);
+            }
+        }
+
+        if let (Some(allowed), Some(references)) =
+            (&schema.references, references)
+        {
+            if !references.keys().all(|k| allowed.contains(k)) {
+                return Err(Rejection::UnknownReference);
+            }
+        }
+
+        let media = media.map_or(0, Vec::len);
+        if schema.min_media.map_or(false, |min| media < min)
+            || schema.max_media.map_or(false, |max| media > max)
+        {
+            return Err(Rejection::MediaCount);
+        }
+
+        fn matches(pattern: &Option<Pattern>, value: Option<&String>) -> bool {
+            match (pattern, value) {
+                (Some(pattern), Some(value)) => pattern.is_match(value),
+                _ => true,
+            }
+        }
+        if !matches(&schema.id, Some(id))
+            || !matches(&schema.content_id, content_id)
+        {
+            return Err(Rejection::InvalidId);
+        }
+        Ok(())
+    }
+
+    /// Merges a new observation of the same content into this one, returning
+    /// the merged content if anything changed. Whatever the new observation
+    /// disagrees on is kept as it was, except that content may become
+    /// deleted. Content that gains anything from another submission no longer
+    /// refers to a submission, as no one signed body holds all of it.
+    pub fn merge(&self, new: &Data) -> Option<Data> {
+        let mut merged = self.clone();
+        if let (
+            Self::Content {
+                deleted,
+                retrieved_from,
+                created_at,
+                body,
+                media,
+                references,
+                ..
+            },
+            Self::Content {
+                deleted: new_deleted,
+                retrieved_from: new_retrieved_from,
+                created_at: new_created_at,
+                body: new_body,
+                media: new_media,
+                references: new_references,
+                ..
+            },
+        ) = (&mut merged, new)
+        {
+            if *new_deleted == Some(true) {
+                *deleted = Some(true);
+            }
+            fill(deleted, new_deleted);
+            fill(retrieved_from, new_retrieved_from);
+            fill(created_at, new_created_at);
+            fill(body, new_body);
+            fill(media, new_media);
+            if let Some(new_references) = new_references {
+                let references = references.get_or_insert_with(HashMap::new);
+                for (k, v) in new_references {
+                    references.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+        }
+
+        if &merged == self {
+            return None;
+        }
+        if let (
+            Self::Content { submission, .. },
+            Self::Content {
+                submission: new_submission,
+                ..
+            },
+        ) = (&mut merged, new)
+        {
+            if submission != new_submission {
+                *submission = None;
+            }
+        }
+        Some(merged)
+    }
+
     // I'm sure this can be cleaned up but I don't know how.
     // This is the debt to be paid for using an enum.
-    pub fn tag(self, uuid: String) -> Self {
+    pub fn tag(self, uuid: String, submission: Option<String>) -> Self {
         match self {
             Self::Presence {
                 id,
@@ -220,6 +693,7 @@ impl Data {
                 retrieved_at,
                 added_by: Some(uuid),
                 added_at: Some(Utc::now()),
+                submission,
             },
             Self::Content {
                 id,
@@ -248,6 +722,7 @@ impl Data {
                 references,
                 added_by: Some(uuid),
                 added_at: Some(Utc::now()),
+                submission,
             },
             Self::Meta {
                 id,
@@ -278,7 +753,163 @@ impl Data {
                 retrieved_at,
                 added_by: Some(uuid),
                 added_at: Some(Utc::now()),
+                submission,
+            },
+            Self::Relationship {
+                from_id,
+                to_id,
+                platform,
+                relation_type,
+                observed_at,
+                ..
+            } => Self::Relationship {
+                from_id,
+                to_id,
+                platform,
+                relation_type,
+                observed_at,
+                added_by: Some(uuid),
+                added_at: Some(Utc::now()),
+                submission,
             },
+            Self::Metric {
+                id,
+                platform,
+                metric_type,
+                value,
+                content_id,
+                retrieved_at,
+                ..
+            } => Self::Metric {
+                id,
+                platform,
+                metric_type,
+                value,
+                content_id,
+                retrieved_at,
+                added_by: Some(uuid),
+                added_at: Some(Utc::now()),
+                submission,
+            },
+        }
+    }
+}
+
+fn fill<T: Clone>(field: &mut Option<T>, new: &Option<T>) {
+    if field.is_none() {
+        *field = new.clone();
+    }
+}
+
+/// What happened to a single item when it was stored.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Ingest {
+    Inserted,
+    /// Content that already existed gained new information.
+    Merged,
+    /// Content that already existed with nothing new.
+    Unchanged,
+    /// Not stored, see [`Rejection`].
+    Rejected,
+}
+
+/// Why a submitted item was not stored.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    /// The platform isn't supported by this server.
+    UnknownPlatform,
+    /// The content, presence, relation or metric type isn't supported on the
+    /// platform.
+    UnknownType,
+    /// The item doesn't belong to the queue job it was submitted for.
+    WrongJob,
+    /// The item is identical to an earlier item in the same submission.
+    Duplicate,
+    /// A timestamp isn't an RFC 3339 date and time.
+    MalformedTimestamp,
+    /// The item isn't content, presence, metadata, a relationship or a metric.
+    Malformed,
+    /// The item is tagged with a version of the wire format this server
+    /// doesn't support.
+    UnsupportedVersion,
+    /// A field its type requires is missing.
+    MissingField,
+    /// A field its type doesn't allow is given.
+    UnexpectedField,
+    /// A key in `references` isn't allowed for its type.
+    UnknownReference,
+    /// There are too few or too many media for its type.
+    MediaCount,
+    /// The profile or content ID isn't in the format for its type.
+    InvalidId,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DataResult {
+    pub id: String,
+    pub platform: String,
+    pub content_id: Option<String>,
+    pub result: Ingest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejection: Option<Rejection>,
+    /// Why an item that couldn't be parsed was malformed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DataResult {
+    pub fn new(data: &Data, result: Ingest) -> Self {
+        let (id, platform, content_id) = match data {
+            Data::Presence { id, platform, .. }
+            | Data::Meta { id, platform, .. }
+            | Data::Relationship {
+                from_id: id,
+                platform,
+                ..
+            } => (id, platform, None),
+            Data::Content {
+                id,
+                platform,
+                content_id,
+                ..
+            } => (id, platform, Some(content_id.clone())),
+            Data::Metric {
+                id,
+                platform,
+                content_id,
+                ..
+            } => (id, platform, content_id.clone()),
+        };
+        Self {
+            id: id.clone(),
+            platform: platform.clone(),
+            content_id,
+            result,
+            rejection: None,
+            error: None,
+        }
+    }
+
+    pub fn rejected(data: &Data, rejection: Rejection) -> Self {
+        Self {
+            rejection: Some(rejection),
+            ..Self::new(data, Ingest::Rejected)
+        }
+    }
+
+    // Items that couldn't be parsed are identified by whatever fields they
+    // do have.
+    fn malformed(value: &Value, rejection: Rejection, error: String) -> Self {
+        let field = |f: &str| value.get(f).and_then(Value::as_str);
+        Self {
+            id: field("id").unwrap_or_default().to_string(),
+            platform: field("platform").unwrap_or_default().to_string(),
+            content_id: field("content_id").map(String::from),
+            result: Ingest::Rejected,
+            rejection: Some(rejection),
+            error: Some(error),
         }
     }
 }
@@ -290,35 +921,66 @@ pub struct Datas {
 }
 
 impl Datas {
-    pub fn verify(
-        self,
-        content_types: &HashMap<String, Vec<String>>,
-        presence_types: &HashMap<String, Vec<String>>,
-    ) -> Self {
-        let mut verified_data = Vec::new();
-        for d in self.data {
-            if d.verify(content_types, presence_types) {
-                verified_data.push(d);
-            }
+    /// Stores every item, merging content into any existing content with the
+    /// same platform, content type and content ID and recording whether its
+    /// data provider saw it deleted, starts or refreshes the relationships
+    /// observed and adds metrics to their time series. Results are in the
+    /// same order as the data.
+    pub async fn insert(&self, db: &Store) -> StorageResult<Vec<DataResult>> {
+        let (content, other): (Vec<&Data>, Vec<&Data>) = self
+            .data
+            .iter()
+            .partition(|d| matches!(d, Data::Content { .. }));
+        let (metrics, other): (Vec<&Data>, Vec<&Data>) = other
+            .into_iter()
+            .partition(|d| matches!(d, Data::Metric { .. }));
+        let points: Vec<MetricPoint> =
+            metrics.into_iter().filter_map(MetricPoint::new).collect();
+        if !points.is_empty() {
+            db.record_metrics(&points).await?;
         }
-        Self {
-            data: verified_data,
-            queue_id: self.queue_id,
+        if !other.is_empty() {
+            let other: Vec<Data> = other.into_iter().cloned().collect();
+            db.insert_data(&other).await?;
+            for d in &other {
+                if let Data::Meta {
+                    id,
+                    platform,
+                    username,
+                    retrieved_at,
+                    ..
+                } = d
+                {
+                    db.record_username(id, platform, username, *retrieved_at)
+                        .await?;
+                }
+            }
+            relationship::observe(&other, db).await?;
         }
-    }
 
-    pub fn tag(self, uuid: String) -> Self {
-        let mut tagged_data = Vec::new();
-        for d in self.data {
-            tagged_data.push(d.tag(uuid.clone()))
-        }
-        Self {
-            data: tagged_data,
-            queue_id: self.queue_id,
+        let mut content_results = Vec::new();
+        for d in content {
+            content_results.push(db.merge_content(d).await?);
+            db.add_revision(d).await?;
+            if let Some(claim) = StatusClaim::new(d) {
+                db.record_status(&claim).await?;
+            }
         }
+
+        let mut content_results = content_results.into_iter();
+        Ok(self
+            .data
+            .iter()
+            .map(|d| match d {
+                Data::Content { .. } => {
+                    DataResult::new(d, content_results.next().unwrap())
+                }
+                _ => DataResult::new(d, Ingest::Inserted),
+            })
+            .collect())
     }
 
-    pub fn get_meta(datas: &Vec<Data>) -> Option<&Data> {
+    pub fn get_meta(datas: &[Data]) -> Option<&Data> {
         for d in datas {
             if let Data::Meta { .. } = d {
                 return Some(d);
@@ -328,7 +990,7 @@ impl Datas {
     }
 
     pub fn get_info(
-        datas: &Vec<Data>,
+        datas: &[Data],
     ) -> (&String, &String, &Option<String>, Option<&String>) {
         let meta = Datas::get_meta(datas);
         if let Some(meta) = meta {
@@ -358,97 +1020,216 @@ impl Datas {
                     added_by,
                     ..
                 } => (id, platform, added_by),
-                _ => panic!("Expected Presence or Content."),
+                Data::Relationship {
+                    from_id,
+                    platform,
+                    added_by,
+                    ..
+                } => (from_id, platform, added_by),
+                Data::Metric {
+                    id,
+                    platform,
+                    added_by,
+                    ..
+                } => (id, platform, added_by),
+                _ => panic!(
+                    "Expected Presence, Content, Relationship or Metric."
+                ),
             };
             (platform_id, platform, added_by, None)
         }
     }
+}
+
+// Fields that must be RFC 3339 timestamps where they are given.
+const TIMESTAMP_FIELDS: [&str; 3] = ["retrieved_at", "created_at", "added_at"];
+
+// A submission before its items are parsed, so that one malformed item
+// doesn't fail the rest.
+#[derive(Deserialize)]
+struct RawDatas {
+    data: Vec<Value>,
+    queue_id: Option<String>,
+}
+
+/// A submission to /add as it is checked, with each item either accepted or
+/// rejected. Items keep their place so that every one of them gets a result.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub items: Vec<Result<Data, DataResult>>,
+    pub queue_id: Option<String>,
+    /// Whether the submission completed the queue job it was for.
+    pub completed: bool,
+}
+
+impl Batch {
+    /// Parses a submission, rejecting any item that isn't valid data. Fails
+    /// only if the submission as a whole is malformed.
+    pub fn parse(body: &[u8]) -> serde_json::Result<Self> {
+        let raw: RawDatas = serde_json::from_slice(body)?;
+        let items = raw
+            .data
+            .into_iter()
+            .map(|value| {
+                Data::from_wire(value.clone()).map_err(|(rejection, e)| {
+                    DataResult::malformed(&value, rejection, e)
+                })
+            })
+            .collect();
+        Ok(Self {
+            items,
+            queue_id: raw.queue_id,
+            completed: false,
+        })
+    }
+
+    /// Rejects items for platforms, content types or presence types this
+    /// server doesn't support, or that break the rules for their type.
+    pub fn verify(self, config: &IConfig) -> Self {
+        self.check(|d| d.verify(config))
+    }
+
+    /// Rejects items identical to an earlier accepted item. Must be called
+    /// before tagging, which timestamps each item.
+    pub fn dedup(self) -> Self {
+        let mut seen: Vec<Data> = Vec::new();
+        self.check(|d| {
+            if seen.contains(d) {
+                Err(Rejection::Duplicate)
+            } else {
+                seen.push(d.clone());
+                Ok(())
+            }
+        })
+    }
+
+    /// Tags every accepted item, see [`Data::tag`].
+    pub fn tag(self, uuid: String, submission: Option<String>) -> Self {
+        Self {
+            items: self
+                .items
+                .into_iter()
+                .map(|i| i.map(|d| d.tag(uuid.clone(), submission.clone())))
+                .collect(),
+            queue_id: self.queue_id,
+            completed: self.completed,
+        }
+    }
 
     // The logic for this function needs to be simplified significantly.
     // There are several sources of uncertainty that this function resolves:
     // - Is there data to be processed and is there an attached queue_id?
     // - Does the given queue_id reference an actual job?
     // - Does the queue item have a username attached or a platform id?
-    // - Does all the data in self.data pertain to the queue job? If not filter
-    //   it out.
+    // - Does all the data in self.data pertain to the queue job? If not reject
+    //   it.
     // Then get relevant data and pass it to the queue for processing.
-    pub async fn process_queue(self, db: &DBHandle) -> Self {
-        if !self.data.is_empty() && self.queue_id.is_some() {
-            let mut verified_data = Vec::new();
-            let queue_id = self.queue_id.clone().unwrap();
-            let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-            let q_item = q_coll
-                .find_one(doc! {"queue_id": &queue_id }, None)
-                .await
-                .unwrap();
-            if q_item.is_some() {
-                let q_item = q_item.unwrap();
-                // We can't guarantee the queue item has the correct platform
-                // id, as it might be a new queue item. So we grab it early from
-                // any Data::Meta in the array.
-                let meta = Datas::get_meta(&self.data);
-                let mut platform_id: Option<String> = None;
-                if let Some(meta) = meta {
-                    platform_id = match meta {
-                        Data::Meta { id, .. } => Some(id.to_string()),
-                        _ => panic!("Expected Data::Meta."),
-                    };
-                }
-
-                // We verify that all data in the array is relevant to this job.
-                for d in &self.data {
-                    let verified: bool = match &d {
-                        Data::Meta { platform, id, .. } => {
-                            if platform_id.is_some() {
-                                platform_id.as_ref() == Some(id)
-                                    && &q_item.platform == platform
-                            } else {
-                                &q_item.platform == platform
-                            }
-                        }
-                        Data::Content { platform, id, .. } => {
-                            if platform_id.is_some() {
-                                platform_id.as_ref() == Some(id)
-                                    && &q_item.platform == platform
-                            } else {
-                                &q_item.platform == platform
-                            }
-                        }
-                        Data::Presence { platform, id, .. } => {
-                            if platform_id.is_some() {
-                                platform_id.as_ref() == Some(id)
-                                    && &q_item.platform == platform
-                            } else {
-                                &q_item.platform == platform
-                            }
-                        }
-                    };
-                    if verified {
-                        verified_data.push(d.clone());
-                    }
-                }
+    pub async fn process_queue(self, db: &Store, config: &IConfig) -> Self {
+        let accepted = self.accepted();
+        if accepted.data.is_empty() {
+            return self;
+        }
+        let queue_id = match &self.queue_id {
+            Some(queue_id) => queue_id.clone(),
+            None => return self,
+        };
+        let q_item = match db.queue_item(&queue_id).await.unwrap() {
+            Some(q_item) => q_item,
+            None => return self,
+        };
 
-                if !verified_data.is_empty() {
-                    let (platform_id, platform, added_by, username) =
-                        Datas::get_info(&verified_data);
-
-                    queue::process(
-                        &queue_id,
-                        platform_id,
-                        platform,
-                        added_by,
-                        username,
-                        db,
-                    )
-                    .await;
-                }
+        // We can't guarantee the queue item has the correct platform id, as it
+        // might be a new queue item. So we grab it early from any Data::Meta
+        // in the array.
+        let platform_id = match Datas::get_meta(&accepted.data) {
+            Some(Data::Meta { id, .. }) => Some(id.to_string()),
+            _ => None,
+        };
 
-                return Self {
-                    data: verified_data,
-                    queue_id: self.queue_id,
-                };
+        // We verify that all data in the array is relevant to this job.
+        let mut checked = self.check(|d| {
+            let (id, platform) = match d {
+                Data::Meta { platform, id, .. }
+                | Data::Content { platform, id, .. }
+                | Data::Presence { platform, id, .. }
+                | Data::Metric { platform, id, .. }
+                | Data::Relationship {
+                    platform,
+                    from_id: id,
+                    ..
+                } => (id, platform),
+            };
+            let for_job = &q_item.platform == platform
+                && platform_id.as_ref().map_or(true, |p| p == id);
+            if for_job {
+                Ok(())
+            } else {
+                Err(Rejection::WrongJob)
             }
+        });
+
+        let verified_data = checked.accepted().data;
+        if !verified_data.is_empty() {
+            checked.completed =
+                queue::process(&queue_id, &verified_data, db, config).await;
+        }
+        checked
+    }
+
+    /// The items that haven't been rejected.
+    pub fn accepted(&self) -> Datas {
+        Datas {
+            data: self
+                .items
+                .iter()
+                .filter_map(|i| i.as_ref().ok())
+                .cloned()
+                .collect(),
+            queue_id: self.queue_id.clone(),
+        }
+    }
+
+    /// A result for every item in order, given the `results` of storing the
+    /// accepted items.
+    pub fn results(&self, results: Vec<DataResult>) -> Vec<DataResult> {
+        let mut results = results.into_iter();
+        self.items
+            .iter()
+            .map(|i| match i {
+                Ok(_) => results.next().unwrap(),
+                Err(rejected) => rejected.clone(),
+            })
+            .collect()
+    }
+
+    // Rejects every accepted item that fails `check`.
+    fn check(
+        self,
+        mut check: impl FnMut(&Data) -> Result<(), Rejection>,
+    ) -> Self {
+        Self {
+            items: self
+                .items
+                .into_iter()
+                .map(|i| match i {
+                    Ok(d) => match check(&d) {
+                        Ok(()) => Ok(d),
+                        Err(rejection) => {
+                            Err(DataResult::rejected(&d, rejection))
+                        }
+                    },
+                    rejected => rejected,
+                })
+                .collect(),
+            queue_id: self.queue_id,
+            completed: self.completed,
         }
-        self
     }
 }
+
+fn malformed_timestamp(value: &Value) -> bool {
+    TIMESTAMP_FIELDS.iter().any(|f| match value.get(f) {
+        None | Some(Value::Null) => false,
+        Some(t) => serde_json::from_value::<DateTime<Utc>>(t.clone()).is_err(),
+    })
+}
@@ -0,0 +1,1416 @@
+//! In-memory implementation of [`Storage`].
+//!
+//! Nothing is persisted, everything is lost when the server stops. This exists
+//! so that the server can be run without any outside services, for tests or
+//! when embedding Instrumentality for one-off analysis. It enforces the same
+//! constraints as the MongoDB indexes: subject names are unique per creator and
+//! content is unique by content ID, platform and content type.
+
+use crate::consensus::StatusClaim;
+use crate::data::{Data, Ingest};
+use crate::database::{
+    AppliedMigration, Migration, Storage, StorageError, StorageResult,
+};
+use crate::group::Group;
+use crate::metric::MetricPoint;
+use crate::relationship::Edge;
+use crate::reputation::Reputation;
+use crate::revision::ContentHistory;
+use crate::routes::invite::Referral;
+use crate::routes::leaderboard::Credit;
+use crate::routes::queue::{FailureReason, InternalQueueItem};
+use crate::routes::stats::QueueCounts;
+use crate::signature::Submission;
+use crate::subject::Subject;
+use crate::tombstone::Tombstone;
+use crate::user::User;
+use crate::username::UsernameRecord;
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+// There is nothing to migrate in memory, but recording the initial version
+// keeps the migration report the same as for the other backends.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "Initial schema.",
+}];
+
+#[derive(Default)]
+struct Collections {
+    migrations: Vec<AppliedMigration>,
+    users: Vec<User>,
+    referrals: Vec<Referral>,
+    subjects: Vec<Subject>,
+    groups: Vec<Group>,
+    queue: Vec<InternalQueueItem>,
+    data: Vec<Data>,
+    histories: Vec<ContentHistory>,
+    statuses: Vec<StatusClaim>,
+    tombstones: Vec<Tombstone>,
+    edges: Vec<Edge>,
+    metrics: Vec<MetricPoint>,
+    usernames: Vec<UsernameRecord>,
+    credits: Vec<Credit>,
+    reputations: Vec<Reputation>,
+    submissions: Vec<Submission>,
+}
+
+#[derive(Default)]
+pub struct MemoryHandle {
+    collections: Mutex<Collections>,
+}
+
+impl MemoryHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Every operation holds the lock for its whole duration, which is what
+    // makes queue locking and referral use atomic.
+    fn lock(&self) -> MutexGuard<'_, Collections> {
+        self.collections.lock().unwrap()
+    }
+
+    fn usernames(
+        &self,
+        predicate: impl Fn(&UsernameRecord) -> bool,
+    ) -> Vec<UsernameRecord> {
+        let mut usernames: Vec<UsernameRecord> = self
+            .lock()
+            .usernames
+            .iter()
+            .filter(|u| predicate(u))
+            .cloned()
+            .collect();
+        usernames.sort_by_key(|u| Reverse(u.last_seen));
+        usernames
+    }
+}
+
+// Mirrors the sparse "Unique Content ID" index.
+fn content_key(data: &Data) -> Option<(&String, &String, &String)> {
+    match data {
+        Data::Content {
+            content_id,
+            platform,
+            content_type,
+            ..
+        } => Some((content_id, platform, content_type)),
+        _ => None,
+    }
+}
+
+fn profile(data: &Data) -> (&String, &String, &DateTime<Utc>) {
+    match data {
+        Data::Presence {
+            id,
+            platform,
+            retrieved_at,
+            ..
+        }
+        | Data::Content {
+            id,
+            platform,
+            retrieved_at,
+            ..
+        }
+        | Data::Meta {
+            id,
+            platform,
+            retrieved_at,
+            ..
+        }
+        | Data::Metric {
+            id,
+            platform,
+            retrieved_at,
+            ..
+        }
+        | Data::Relationship {
+            from_id: id,
+            platform,
+            observed_at: retrieved_at,
+            ..
+        } => (id, platform, retrieved_at),
+    }
+}
+
+fn recent<'a>(data: impl Iterator<Item = &'a Data>, limit: i64) -> Vec<Data> {
+    let mut data: Vec<Data> = data.cloned().collect();
+    data.sort_by_key(|d| Reverse(*profile(d).2));
+    data.truncate(limit.max(0) as usize);
+    data
+}
+
+#[async_trait]
+impl Storage for MemoryHandle {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn applied_migrations(&self) -> StorageResult<Vec<AppliedMigration>> {
+        Ok(self.lock().migrations.clone())
+    }
+
+    async fn apply_migration(
+        &self,
+        migration: &Migration,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        if !collections
+            .migrations
+            .iter()
+            .any(|m| m.version == migration.version)
+        {
+            collections
+                .migrations
+                .push(AppliedMigration::new(migration));
+        }
+        Ok(())
+    }
+
+    async fn count_users(&self) -> StorageResult<u64> {
+        Ok(self.lock().users.len() as u64)
+    }
+
+    async fn insert_user(&self, user: &User) -> StorageResult<()> {
+        self.lock().users.push(user.clone());
+        Ok(())
+    }
+
+    async fn user_with_key(&self, key: &str) -> StorageResult<Option<User>> {
+        Ok(self.lock().users.iter().find(|u| u.key == key).cloned())
+    }
+
+    async fn user_with_name(&self, name: &str) -> StorageResult<Option<User>> {
+        Ok(self.lock().users.iter().find(|u| u.name == name).cloned())
+    }
+
+    async fn users(&self, uuids: &[String]) -> StorageResult<Vec<User>> {
+        Ok(self
+            .lock()
+            .users
+            .iter()
+            .filter(|u| uuids.contains(&u.uuid))
+            .cloned()
+            .collect())
+    }
+
+    async fn reset_key(
+        &self,
+        key: &str,
+        new_key: &str,
+    ) -> StorageResult<Option<User>> {
+        let mut collections = self.lock();
+        let user = collections.users.iter_mut().find(|u| u.key == key);
+        Ok(user.map(|u| {
+            let old = u.clone();
+            u.key = new_key.to_string();
+            old
+        }))
+    }
+
+    async fn set_public_key(
+        &self,
+        key: &str,
+        public_key: Option<&str>,
+    ) -> StorageResult<Option<User>> {
+        let mut collections = self.lock();
+        let user = collections.users.iter_mut().find(|u| u.key == key);
+        Ok(user.map(|u| {
+            let old = u.clone();
+            u.public_key = public_key.map(String::from);
+            old
+        }))
+    }
+
+    async fn insert_referral(&self, referral: &Referral) -> StorageResult<()> {
+        self.lock().referrals.push(referral.clone());
+        Ok(())
+    }
+
+    async fn unused_referral(
+        &self,
+        code: &str,
+    ) -> StorageResult<Option<Referral>> {
+        Ok(self
+            .lock()
+            .referrals
+            .iter()
+            .find(|r| r.code == code && !r.used)
+            .cloned())
+    }
+
+    async fn use_referral(
+        &self,
+        code: &str,
+        used_by: &str,
+    ) -> StorageResult<Option<Referral>> {
+        let mut collections = self.lock();
+        let referral = collections
+            .referrals
+            .iter_mut()
+            .find(|r| r.code == code && !r.used);
+        Ok(referral.map(|r| {
+            let old = r.clone();
+            r.used = true;
+            r.used_by = Some(used_by.to_string());
+            old
+        }))
+    }
+
+    async fn insert_subject(&self, subject: &Subject) -> StorageResult<()> {
+        let mut collections = self.lock();
+        if collections.subjects.iter().any(|s| {
+            s.created_by == subject.created_by && s.name == subject.name
+        }) {
+            return Err(StorageError::Duplicate);
+        }
+        collections.subjects.push(subject.clone());
+        Ok(())
+    }
+
+    async fn subject(&self, uuid: &str) -> StorageResult<Option<Subject>> {
+        Ok(self
+            .lock()
+            .subjects
+            .iter()
+            .find(|s| s.uuid == uuid)
+            .cloned())
+    }
+
+    async fn subjects(&self, uuids: &[String]) -> StorageResult<Vec<Subject>> {
+        Ok(self
+            .lock()
+            .subjects
+            .iter()
+            .filter(|s| uuids.contains(&s.uuid))
+            .cloned()
+            .collect())
+    }
+
+    async fn subjects_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        Ok(self
+            .lock()
+            .subjects
+            .iter()
+            .filter(|s| s.created_by == created_by)
+            .cloned()
+            .collect())
+    }
+
+    async fn subjects_with_profile(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        Ok(self
+            .lock()
+            .subjects
+            .iter()
+            .filter(|s| {
+                s.profiles
+                    .get(platform)
+                    .map_or(false, |ids| ids.iter().any(|id| id == platform_id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn update_subject(&self, subject: &Subject) -> StorageResult<()> {
+        let mut collections = self.lock();
+        if collections.subjects.iter().any(|s| {
+            s.uuid != subject.uuid
+                && s.created_by == subject.created_by
+                && s.name == subject.name
+        }) {
+            return Err(StorageError::Duplicate);
+        }
+        if let Some(s) = collections
+            .subjects
+            .iter_mut()
+            .find(|s| s.uuid == subject.uuid)
+        {
+            s.name = subject.name.clone();
+            s.profiles = subject.profiles.clone();
+            s.description = subject.description.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete_subject(&self, uuid: &str) -> StorageResult<()> {
+        self.lock().subjects.retain(|s| s.uuid != uuid);
+        Ok(())
+    }
+
+    async fn replace_profile_id(
+        &self,
+        platform: &str,
+        old_id: &str,
+        new_id: &str,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let ids = collections
+            .subjects
+            .iter_mut()
+            .filter_map(|s| s.profiles.get_mut(platform))
+            .find(|ids| ids.iter().any(|id| id == old_id));
+        if let Some(ids) = ids {
+            if let Some(id) = ids.iter_mut().find(|id| *id == old_id) {
+                *id = new_id.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &Group) -> StorageResult<()> {
+        self.lock().groups.push(group.clone());
+        Ok(())
+    }
+
+    async fn group(&self, uuid: &str) -> StorageResult<Option<Group>> {
+        Ok(self.lock().groups.iter().find(|g| g.uuid == uuid).cloned())
+    }
+
+    async fn groups_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Group>> {
+        Ok(self
+            .lock()
+            .groups
+            .iter()
+            .filter(|g| g.created_by == created_by)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_group(&self, group: &Group) -> StorageResult<()> {
+        let mut collections = self.lock();
+        if let Some(g) =
+            collections.groups.iter_mut().find(|g| g.uuid == group.uuid)
+        {
+            g.name = group.name.clone();
+            g.subjects = group.subjects.clone();
+            g.description = group.description.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete_group(&self, uuid: &str) -> StorageResult<()> {
+        self.lock().groups.retain(|g| g.uuid != uuid);
+        Ok(())
+    }
+
+    async fn remove_subject_from_groups(
+        &self,
+        subject_uuid: &str,
+    ) -> StorageResult<()> {
+        for g in self.lock().groups.iter_mut() {
+            g.subjects.retain(|s| s != subject_uuid);
+        }
+        Ok(())
+    }
+
+    async fn add_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        confirmed_id: bool,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let q_item = collections
+            .queue
+            .iter_mut()
+            .find(|q| q.platform_id == platform_id && q.platform == platform);
+        if let Some(q_item) = q_item {
+            q_item.references += 1;
+            q_item.confirmed_id |= confirmed_id;
+        } else {
+            collections.queue.push(InternalQueueItem::new(
+                platform_id.to_string(),
+                platform.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn remove_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let position = collections.queue.iter().position(|q| {
+            q.platform_id == platform_id && q.platform == platform
+        });
+        if let Some(position) = position {
+            if collections.queue[position].references <= 1 {
+                collections.queue.remove(position);
+            } else {
+                collections.queue[position].references -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn queue_item(
+        &self,
+        queue_id: &str,
+    ) -> StorageResult<Option<InternalQueueItem>> {
+        Ok(self
+            .lock()
+            .queue
+            .iter()
+            .find(|q| q.queue_id == queue_id)
+            .cloned())
+    }
+
+    async fn lock_queue_items(
+        &self,
+        leases: &HashMap<String, DateTime<Utc>>,
+        lock_holder: &str,
+        count: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let now = Utc::now();
+        let mut collections = self.lock();
+        let mut q_items: Vec<&mut InternalQueueItem> = collections
+            .queue
+            .iter_mut()
+            .filter(|q| {
+                q.lock_holder.is_none()
+                    && q.quarantined_at.is_none()
+                    && leases.contains_key(&q.platform)
+                    && q.due_at <= now
+            })
+            .collect();
+        q_items.sort_by_key(|q| q.due_at);
+        Ok(q_items
+            .into_iter()
+            .take(count)
+            .map(|q| {
+                q.lock_holder = Some(lock_holder.to_string());
+                q.lock_acquired_at = Some(now);
+                q.lock_expires_at = Some(leases[&q.platform]);
+                q.clone()
+            })
+            .collect())
+    }
+
+    async fn complete_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        hot_due_at: DateTime<Utc>,
+        cold_due_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let now = Utc::now();
+        let mut collections = self.lock();
+        let q_item = collections.queue.iter_mut().find(|q| {
+            q.queue_id == queue_id
+                && q.lock_holder.as_deref() == Some(lock_holder)
+        });
+        Ok(q_item
+            .map(|q| {
+                q.lock_holder = None;
+                q.lock_acquired_at = None;
+                q.lock_expires_at = None;
+                q.last_processed = now;
+                q.failures = 0;
+                q.last_failure = None;
+                q.due_at = match q.hot_until {
+                    Some(hot_until) if hot_until > now => hot_due_at,
+                    _ => cold_due_at,
+                };
+            })
+            .is_some())
+    }
+
+    async fn extend_lock(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let mut collections = self.lock();
+        let q_item = collections.queue.iter_mut().find(|q| {
+            q.queue_id == queue_id
+                && q.lock_holder.as_deref() == Some(lock_holder)
+        });
+        Ok(q_item
+            .map(|q| q.lock_expires_at = Some(expires_at))
+            .is_some())
+    }
+
+    async fn release_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+    ) -> StorageResult<bool> {
+        let mut collections = self.lock();
+        let q_item = collections.queue.iter_mut().find(|q| {
+            q.queue_id == queue_id
+                && q.lock_holder.as_deref() == Some(lock_holder)
+        });
+        Ok(q_item
+            .map(|q| {
+                q.lock_holder = None;
+                q.lock_acquired_at = None;
+                q.lock_expires_at = None;
+            })
+            .is_some())
+    }
+
+    async fn fail_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        reason: FailureReason,
+        due_at: DateTime<Utc>,
+        quarantine: bool,
+    ) -> StorageResult<bool> {
+        let mut collections = self.lock();
+        let q_item = collections.queue.iter_mut().find(|q| {
+            q.queue_id == queue_id
+                && q.lock_holder.as_deref() == Some(lock_holder)
+        });
+        Ok(q_item
+            .map(|q| {
+                q.lock_holder = None;
+                q.lock_acquired_at = None;
+                q.lock_expires_at = None;
+                q.due_at = due_at;
+                q.failures += 1;
+                q.last_failure = Some(reason);
+                if quarantine {
+                    q.quarantined_at = Some(Utc::now());
+                }
+            })
+            .is_some())
+    }
+
+    async fn queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        Ok(self
+            .lock()
+            .queue
+            .iter()
+            .filter(|q| {
+                profiles
+                    .get(&q.platform)
+                    .map_or(false, |ids| ids.contains(&q.platform_id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn queue_counts(&self) -> StorageResult<Vec<QueueCounts>> {
+        let mut counts: BTreeMap<String, QueueCounts> = BTreeMap::new();
+        for q in &self.lock().queue {
+            let c = counts
+                .entry(q.platform.clone())
+                .or_insert_with(|| QueueCounts::new(&q.platform));
+            c.total += 1;
+            c.locked += q.lock_holder.is_some() as u64;
+            c.unconfirmed += !q.confirmed_id as u64;
+            c.quarantined += q.quarantined_at.is_some() as u64;
+            c.never_processed += !q.processed() as u64;
+        }
+        Ok(counts.into_values().collect())
+    }
+
+    async fn nth_processed(
+        &self,
+        platform: &str,
+        n: u64,
+    ) -> StorageResult<Option<DateTime<Utc>>> {
+        let mut processed: Vec<DateTime<Utc>> = self
+            .lock()
+            .queue
+            .iter()
+            .filter(|q| q.platform == platform && q.processed())
+            .map(|q| q.last_processed)
+            .collect();
+        processed.sort_unstable_by_key(|t| Reverse(*t));
+        Ok(n.checked_sub(1)
+            .and_then(|i| processed.get(i as usize))
+            .copied())
+    }
+
+    async fn unprocessed_queue_items(
+        &self,
+        platform: &str,
+        limit: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let mut q_items: Vec<InternalQueueItem> = self
+            .lock()
+            .queue
+            .iter()
+            .filter(|q| q.platform == platform && !q.processed())
+            .cloned()
+            .collect();
+        q_items.sort_by_key(|q| q.created_at);
+        q_items.truncate(limit);
+        Ok(q_items)
+    }
+
+    async fn quarantined_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        Ok(self
+            .lock()
+            .queue
+            .iter()
+            .filter(|q| {
+                q.quarantined_at.is_some()
+                    && profiles
+                        .get(&q.platform)
+                        .map_or(false, |ids| ids.contains(&q.platform_id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn restore_queue_item(&self, queue_id: &str) -> StorageResult<bool> {
+        let mut collections = self.lock();
+        let q_item = collections
+            .queue
+            .iter_mut()
+            .find(|q| q.queue_id == queue_id && q.quarantined_at.is_some());
+        Ok(q_item
+            .map(|q| {
+                q.quarantined_at = None;
+                q.failures = 0;
+                q.last_failure = None;
+                q.due_at = Utc::now();
+            })
+            .is_some())
+    }
+
+    async fn copy_queue_schedule(
+        &self,
+        from: &InternalQueueItem,
+        platform_id: &str,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let q_item = collections.queue.iter_mut().find(|q| {
+            q.platform_id == platform_id && q.platform == from.platform
+        });
+        if let Some(q) = q_item {
+            q.last_processed = from.last_processed;
+            q.due_at = from.due_at;
+            q.hot_until = from.hot_until;
+        }
+        Ok(())
+    }
+
+    async fn promote_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+        hot_until: DateTime<Utc>,
+        hot_interval: Duration,
+    ) -> StorageResult<()> {
+        for q in self.lock().queue.iter_mut() {
+            let promoted = profiles
+                .get(&q.platform)
+                .map_or(false, |ids| ids.contains(&q.platform_id));
+            if promoted {
+                q.hot_until = q.hot_until.max(Some(hot_until));
+                if q.failures == 0 {
+                    q.due_at = q.due_at.min(q.last_processed + hot_interval);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn expire_locks(&self, now: DateTime<Utc>) -> StorageResult<u64> {
+        let mut expired = 0;
+        for q in self.lock().queue.iter_mut() {
+            if matches!(q.lock_expires_at, Some(t) if t < now) {
+                q.lock_holder = None;
+                q.lock_acquired_at = None;
+                q.lock_expires_at = None;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+
+    async fn record_credit(
+        &self,
+        period: &str,
+        user: &str,
+        jobs: u64,
+        items: u64,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let credit = collections
+            .credits
+            .iter_mut()
+            .find(|c| c.period == period && c.user == user);
+        if let Some(credit) = credit {
+            credit.jobs += jobs;
+            credit.items += items;
+        } else {
+            collections.credits.push(Credit {
+                period: period.to_string(),
+                user: user.to_string(),
+                jobs,
+                items,
+            });
+        }
+        Ok(())
+    }
+
+    async fn credits(&self, period: &str) -> StorageResult<Vec<Credit>> {
+        let mut credits: Vec<Credit> = self
+            .lock()
+            .credits
+            .iter()
+            .filter(|c| c.period == period)
+            .cloned()
+            .collect();
+        credits.sort_by_key(|c| std::cmp::Reverse((c.jobs, c.items)));
+        Ok(credits)
+    }
+
+    async fn credit_periods(&self) -> StorageResult<Vec<String>> {
+        let mut periods: Vec<String> = self
+            .lock()
+            .credits
+            .iter()
+            .map(|c| c.period.clone())
+            .collect();
+        periods.sort_unstable_by(|a, b| b.cmp(a));
+        periods.dedup();
+        Ok(periods)
+    }
+
+    async fn record_reputation(
+        &self,
+        user: &str,
+        expected: u64,
+        provided: u64,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        let reputation =
+            collections.reputations.iter_mut().find(|r| r.user == user);
+        let reputation = match reputation {
+            Some(reputation) => reputation,
+            None => {
+                collections.reputations.push(Reputation::new(user));
+                collections.reputations.last_mut().unwrap()
+            }
+        };
+        reputation.assessed += 1;
+        reputation.expected += expected;
+        reputation.provided += provided;
+        Ok(())
+    }
+
+    async fn reputations(
+        &self,
+        users: &[String],
+    ) -> StorageResult<Vec<Reputation>> {
+        Ok(self
+            .lock()
+            .reputations
+            .iter()
+            .filter(|r| users.contains(&r.user))
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_submission(
+        &self,
+        submission: &Submission,
+    ) -> StorageResult<()> {
+        self.lock().submissions.push(submission.clone());
+        Ok(())
+    }
+
+    async fn submission(
+        &self,
+        uuid: &str,
+    ) -> StorageResult<Option<Submission>> {
+        Ok(self
+            .lock()
+            .submissions
+            .iter()
+            .find(|s| s.uuid == uuid)
+            .cloned())
+    }
+
+    async fn insert_data(&self, data: &[Data]) -> StorageResult<()> {
+        let mut collections = self.lock();
+        for (i, d) in data.iter().enumerate() {
+            if let Some(key) = content_key(d) {
+                let duplicate = collections
+                    .data
+                    .iter()
+                    .chain(&data[..i])
+                    .any(|e| content_key(e) == Some(key));
+                if duplicate {
+                    return Err(StorageError::Duplicate);
+                }
+            }
+        }
+        collections.data.extend_from_slice(data);
+        Ok(())
+    }
+
+    async fn merge_content(&self, content: &Data) -> StorageResult<Ingest> {
+        let key = content_key(content);
+        let mut collections = self.lock();
+        match collections
+            .data
+            .iter_mut()
+            .find(|e| key.is_some() && content_key(e) == key)
+        {
+            Some(existing) => match existing.merge(content) {
+                Some(merged) => {
+                    *existing = merged;
+                    Ok(Ingest::Merged)
+                }
+                None => Ok(Ingest::Unchanged),
+            },
+            None => {
+                collections.data.push(content.clone());
+                Ok(Ingest::Inserted)
+            }
+        }
+    }
+
+    async fn add_revision(&self, content: &Data) -> StorageResult<()> {
+        let mut history = match ContentHistory::new(content) {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        let mut collections = self.lock();
+        match collections.histories.iter_mut().find(|h| {
+            h.content_id == history.content_id
+                && h.platform == history.platform
+                && h.content_type == history.content_type
+        }) {
+            Some(existing) => existing.observe(history.revisions.remove(0)),
+            None => collections.histories.push(history),
+        }
+        Ok(())
+    }
+
+    async fn content_history(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<Option<ContentHistory>> {
+        Ok(self
+            .lock()
+            .histories
+            .iter()
+            .find(|h| {
+                h.content_id == content_id
+                    && h.platform == platform
+                    && h.content_type == content_type
+            })
+            .cloned())
+    }
+
+    async fn record_status(&self, claim: &StatusClaim) -> StorageResult<()> {
+        let mut collections = self.lock();
+        match collections.statuses.iter_mut().find(|c| {
+            c.content_id == claim.content_id
+                && c.platform == claim.platform
+                && c.content_type == claim.content_type
+                && c.added_by == claim.added_by
+        }) {
+            Some(existing) => {
+                if existing.retrieved_at <= claim.retrieved_at {
+                    *existing = claim.clone();
+                }
+            }
+            None => collections.statuses.push(claim.clone()),
+        }
+        Ok(())
+    }
+
+    async fn statuses(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<StatusClaim>> {
+        Ok(self
+            .lock()
+            .statuses
+            .iter()
+            .filter(|c| c.platform_id == platform_id && c.platform == platform)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_tombstone(
+        &self,
+        tombstone: &Tombstone,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        collections.tombstones.retain(|t| {
+            t.content_id != tombstone.content_id
+                || t.platform != tombstone.platform
+                || t.content_type != tombstone.content_type
+        });
+        collections.tombstones.push(tombstone.clone());
+        Ok(())
+    }
+
+    async fn remove_tombstone(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<()> {
+        self.lock().tombstones.retain(|t| {
+            t.content_id != content_id
+                || t.platform != platform
+                || t.content_type != content_type
+        });
+        Ok(())
+    }
+
+    async fn tombstones(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Tombstone>> {
+        Ok(self
+            .lock()
+            .tombstones
+            .iter()
+            .filter(|t| t.platform_id == platform_id && t.platform == platform)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_edges(&self, edges: &[Edge]) -> StorageResult<()> {
+        let mut collections = self.lock();
+        for edge in edges {
+            collections.edges.retain(|e| {
+                e.platform != edge.platform
+                    || e.from_id != edge.from_id
+                    || e.relation_type != edge.relation_type
+                    || e.to_id != edge.to_id
+                    || e.added_by != edge.added_by
+                    || e.first_seen != edge.first_seen
+            });
+            collections.edges.push(edge.clone());
+        }
+        Ok(())
+    }
+
+    async fn edges(
+        &self,
+        from_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Edge>> {
+        Ok(self
+            .lock()
+            .edges
+            .iter()
+            .filter(|e| e.from_id == from_id && e.platform == platform)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_metrics(
+        &self,
+        points: &[MetricPoint],
+    ) -> StorageResult<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        self.lock().metrics.extend(points.iter().cloned());
+        Ok(())
+    }
+
+    async fn metrics(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        content_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<MetricPoint>> {
+        let mut points: Vec<MetricPoint> = self
+            .lock()
+            .metrics
+            .iter()
+            .filter(|p| {
+                p.platform_id == platform_id
+                    && p.platform == platform
+                    && p.content_id.as_deref() == content_id
+                    && from.map_or(true, |from| p.retrieved_at >= from)
+                    && to.map_or(true, |to| p.retrieved_at <= to)
+            })
+            .cloned()
+            .collect();
+        points.sort_by_key(|p| Reverse(p.retrieved_at));
+        points.truncate(limit.max(0) as usize);
+        Ok(points)
+    }
+
+    async fn record_username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        username: &str,
+        retrieved_at: DateTime<Utc>,
+    ) -> StorageResult<()> {
+        let mut collections = self.lock();
+        match collections.usernames.iter_mut().find(|u| {
+            u.platform_id == platform_id
+                && u.platform == platform
+                && u.username == username
+        }) {
+            Some(record) => record.observe(retrieved_at),
+            None => collections.usernames.push(UsernameRecord::new(
+                platform_id,
+                platform,
+                username,
+                retrieved_at,
+            )),
+        }
+        Ok(())
+    }
+
+    async fn username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<String>> {
+        Ok(self
+            .username_history(platform_id, platform)
+            .await?
+            .into_iter()
+            .next()
+            .map(|u| u.username))
+    }
+
+    async fn username_history(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        Ok(self.usernames(|u| {
+            u.platform_id == platform_id && u.platform == platform
+        }))
+    }
+
+    async fn username_owners(
+        &self,
+        username: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        Ok(
+            self.usernames(|u| {
+                u.username == username && u.platform == platform
+            }),
+        )
+    }
+
+    async fn meta(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<Data>> {
+        let collections = self.lock();
+        let metas = collections.data.iter().filter(|d| {
+            matches!(d, Data::Meta { id, platform: p, .. }
+                if id == platform_id && p == platform)
+        });
+        Ok(recent(metas, 1).pop())
+    }
+
+    async fn metas(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        until: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let collections = self.lock();
+        let metas = collections.data.iter().filter(|d| {
+            matches!(d, Data::Meta { id, platform: p, retrieved_at, .. }
+                if id == platform_id
+                    && p == platform
+                    && until.map_or(true, |until| *retrieved_at <= until))
+        });
+        let mut metas = recent(metas, limit);
+        metas.reverse();
+        Ok(metas)
+    }
+
+    async fn latest_meta_excluding(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        added_by: &str,
+    ) -> StorageResult<Option<Data>> {
+        let collections = self.lock();
+        let metas = collections.data.iter().filter(|d| {
+            matches!(d, Data::Meta { id, platform: p, added_by: a, .. }
+                if id == platform_id
+                    && p == platform
+                    && a.as_deref() != Some(added_by))
+        });
+        Ok(recent(metas, 1).pop())
+    }
+
+    async fn content(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let collections = self.lock();
+        let content = collections.data.iter().filter(|d| {
+            matches!(d, Data::Content { id, platform: p, .. }
+                if id == platform_id && p == platform)
+        });
+        Ok(recent(content, limit))
+    }
+
+    async fn content_with_ids(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        ids: &[(String, String)],
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let collections = self.lock();
+        let content = collections.data.iter().filter(|d| {
+            matches!(d, Data::Content {
+                id,
+                platform: p,
+                content_type,
+                content_id,
+                ..
+            } if id == platform_id
+                && p == platform
+                && ids
+                    .iter()
+                    .any(|(t, c)| t == content_type && c == content_id))
+        });
+        Ok(recent(content, limit))
+    }
+
+    async fn presence(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let collections = self.lock();
+        let presence = collections.data.iter().filter(|d| {
+            matches!(d, Data::Presence { id, platform: p, retrieved_at, .. }
+                if id == platform_id
+                    && p == platform
+                    && from.map_or(true, |from| *retrieved_at >= from)
+                    && to.map_or(true, |to| *retrieved_at <= to))
+        });
+        Ok(recent(presence, limit))
+    }
+
+    async fn drop(&self) -> StorageResult<()> {
+        *self.lock() = Collections::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn subject(created_by: &str, name: &str) -> Subject {
+        Subject {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            created_by: created_by.to_string(),
+            name: name.to_string(),
+            profiles: HashMap::new(),
+            description: None,
+        }
+    }
+
+    fn content(content_id: &str) -> Data {
+        Data::Content {
+            id: "user1".to_string(),
+            platform: "PLATFORM_1".to_string(),
+            content_type: "post".to_string(),
+            retrieved_at: Utc::now(),
+            content_id: content_id.to_string(),
+            deleted: None,
+            retrieved_from: None,
+            created_at: None,
+            body: None,
+            media: None,
+            references: None,
+            added_by: None,
+            added_at: None,
+            submission: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unique_subject_name() {
+        let db = MemoryHandle::new();
+
+        db.insert_subject(&subject("a", "test")).await.unwrap();
+        db.insert_subject(&subject("b", "test")).await.unwrap();
+
+        assert!(matches!(
+            db.insert_subject(&subject("a", "test")).await,
+            Err(StorageError::Duplicate)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unique_content() {
+        let db = MemoryHandle::new();
+
+        db.insert_data(&[content("1")]).await.unwrap();
+
+        assert!(matches!(
+            db.insert_data(&[content("2"), content("1")]).await,
+            Err(StorageError::Duplicate)
+        ));
+        assert!(matches!(
+            db.insert_data(&[content("3"), content("3")]).await,
+            Err(StorageError::Duplicate)
+        ));
+        assert_eq!(
+            db.content("user1", "PLATFORM_1", 100).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_locking() {
+        let db = MemoryHandle::new();
+        let mut leases = HashMap::new();
+        leases.insert(
+            "PLATFORM_1".to_string(),
+            Utc::now() + Duration::minutes(1),
+        );
+
+        db.add_queue_item("user1", "PLATFORM_1", false)
+            .await
+            .unwrap();
+        let q_item = db.lock_queue_item(&leases, "a").await.unwrap();
+
+        assert!(q_item.is_some());
+        assert!(db.lock_queue_item(&leases, "b").await.unwrap().is_none());
+
+        let queue_id = q_item.unwrap().queue_id;
+        let now = Utc::now();
+        assert!(!db
+            .complete_queue_item(&queue_id, "b", now, now)
+            .await
+            .unwrap());
+        assert!(db
+            .complete_queue_item(&queue_id, "a", now, now)
+            .await
+            .unwrap());
+        assert!(db.lock_queue_item(&leases, "b").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_queue_scheduling() {
+        let db = MemoryHandle::new();
+        let mut leases = HashMap::new();
+        leases.insert(
+            "PLATFORM_1".to_string(),
+            Utc::now() + Duration::minutes(1),
+        );
+        let later = Utc::now() + Duration::hours(1);
+
+        db.add_queue_item("user1", "PLATFORM_1", false)
+            .await
+            .unwrap();
+        let queue_id = db
+            .lock_queue_item(&leases, "a")
+            .await
+            .unwrap()
+            .unwrap()
+            .queue_id;
+        db.complete_queue_item(&queue_id, "a", Utc::now(), later)
+            .await
+            .unwrap();
+
+        // Cold and not due for another hour.
+        assert!(db.lock_queue_item(&leases, "a").await.unwrap().is_none());
+
+        let mut profiles = HashMap::new();
+        profiles.insert("PLATFORM_1".to_string(), vec!["user1".to_string()]);
+        db.promote_queue_items(&profiles, later, Duration::zero())
+            .await
+            .unwrap();
+
+        let q_item = db.lock_queue_item(&leases, "a").await.unwrap();
+        assert_eq!(q_item.unwrap().hot_until, Some(later));
+    }
+
+    #[tokio::test]
+    async fn test_lock_expiry() {
+        let db = MemoryHandle::new();
+        let expires_at = Utc::now() + Duration::minutes(1);
+        let mut leases = HashMap::new();
+        leases.insert("PLATFORM_1".to_string(), expires_at);
+
+        db.add_queue_item("user1", "PLATFORM_1", false)
+            .await
+            .unwrap();
+        let q_item = db.lock_queue_item(&leases, "a").await.unwrap();
+        assert_eq!(q_item.unwrap().lock_expires_at, Some(expires_at));
+
+        assert_eq!(db.expire_locks(Utc::now()).await.unwrap(), 0);
+        assert!(db.lock_queue_item(&leases, "b").await.unwrap().is_none());
+
+        let later = expires_at + Duration::seconds(1);
+        assert_eq!(db.expire_locks(later).await.unwrap(), 1);
+        assert!(db.lock_queue_item(&leases, "b").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_referral_single_use() {
+        let db = MemoryHandle::new();
+        let referral = Referral::new("a".to_string());
+
+        db.insert_referral(&referral).await.unwrap();
+
+        assert!(db
+            .use_referral(&referral.code, "b")
+            .await
+            .unwrap()
+            .is_some());
+        assert!(db
+            .use_referral(&referral.code, "c")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(db.unused_referral(&referral.code).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+//! Versioned schema migrations.
+//!
+//! Indexes, tables and the shape of stored documents change as Instrumentality
+//! does. Every backend lists the steps needed to bring its storage up to date
+//! through [`Storage::migrations`], oldest first, and records each step it has
+//! applied (in a `migrations` collection or table) so that it only ever runs
+//! once per database.
+//!
+//! [`run`] is called by [`open`](super::open) on every startup and applies
+//! anything that is pending, in order. Running `instrumentality --dry-run`
+//! instead logs a [`MigrationReport`] of what has been applied and what would
+//! be, without changing anything.
+//!
+//! # Writing a migration
+//! Append a new [`Migration`] with the next version to each backend and handle
+//! that version in [`Storage::apply_migration`]. Never edit or reorder a
+//! migration that has shipped. Steps must be idempotent: a step may have been
+//! run but not recorded if the server stopped in between, or if two servers
+//! started at the same time.
+
+use crate::database::{Storage, StorageResult};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub description: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+impl AppliedMigration {
+    pub fn new(migration: &Migration) -> Self {
+        Self {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<Migration>,
+    /// Migrations recorded in the database that this version of
+    /// Instrumentality does not know about, e.g. after a downgrade.
+    pub unknown: Vec<AppliedMigration>,
+}
+
+impl MigrationReport {
+    pub fn log(&self) {
+        for migration in &self.applied {
+            tracing::info!(
+                "Migration {} applied at {}: {}",
+                migration.version,
+                migration.applied_at,
+                migration.description
+            );
+        }
+        for migration in &self.pending {
+            tracing::info!(
+                "Migration {} pending: {}",
+                migration.version,
+                migration.description
+            );
+        }
+        for migration in &self.unknown {
+            tracing::warn!(
+                "Migration {} is unknown to this version: {}",
+                migration.version,
+                migration.description
+            );
+        }
+    }
+}
+
+/// Compares the migrations a backend knows about with those it has recorded.
+/// Nothing is changed.
+pub async fn report(storage: &dyn Storage) -> StorageResult<MigrationReport> {
+    let known = storage.migrations();
+    let mut applied = storage.applied_migrations().await?;
+    applied.sort_by_key(|m| m.version);
+
+    let pending = known
+        .iter()
+        .filter(|k| !applied.iter().any(|a| a.version == k.version))
+        .copied()
+        .collect();
+    let (applied, unknown) = applied
+        .into_iter()
+        .partition(|a| known.iter().any(|k| k.version == a.version));
+
+    Ok(MigrationReport {
+        applied,
+        pending,
+        unknown,
+    })
+}
+
+/// Applies every pending migration in order, returning those that were run.
+pub async fn run(storage: &dyn Storage) -> StorageResult<Vec<Migration>> {
+    let report = report(storage).await?;
+    for migration in &report.unknown {
+        tracing::warn!(
+            "Migration {} is unknown to this version: {}",
+            migration.version,
+            migration.description
+        );
+    }
+    for migration in &report.pending {
+        tracing::info!(
+            "Applying migration {}: {}",
+            migration.version,
+            migration.description
+        );
+        storage.apply_migration(migration).await?;
+    }
+
+    Ok(report.pending)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory, mongo, postgres, MemoryHandle};
+
+    #[test]
+    fn test_versions_ascending() {
+        for migrations in
+            [mongo::MIGRATIONS, postgres::MIGRATIONS, memory::MIGRATIONS]
+        {
+            assert!(!migrations.is_empty());
+            assert!(migrations.windows(2).all(|w| w[0].version < w[1].version));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_is_recorded() {
+        let storage = MemoryHandle::new();
+
+        let before = report(&storage).await.unwrap();
+        assert!(before.applied.is_empty());
+        assert_eq!(before.pending, memory::MIGRATIONS);
+
+        let ran = run(&storage).await.unwrap();
+        assert_eq!(ran, memory::MIGRATIONS);
+
+        let after = report(&storage).await.unwrap();
+        assert!(after.pending.is_empty());
+        assert_eq!(after.applied.len(), memory::MIGRATIONS.len());
+        assert!(run(&storage).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,586 @@
+//! Database functions and implementations for Instrumentality.
+//!
+//! Routes never talk to a database directly. Instead, every operation that
+//! Instrumentality needs to perform against persistent storage is described by
+//! the [`Storage`] trait and routes are handed a [`Store`], which wraps
+//! whichever backend the server was configured with.
+//!
+//! # Backends
+//! - [`mongo`]: the original MongoDB backend, implemented by
+//!   [`DBPool`]/[`DBHandle`].
+//! - [`postgres`]: a PostgreSQL backend.
+//! - [`memory`]: a backend that holds everything in memory, for tests and for
+//!   embedding Instrumentality without any outside services.
+//!
+//! The backend is chosen with `backend` under `[settings]` in the
+//! configuration file and defaults to MongoDB.
+//!
+//! # Adding an operation
+//! Any new operation must be added to [`Storage`] and implemented for every
+//! backend. Implementations are expected to be atomic where the equivalent
+//! MongoDB operation is, for example [`Storage::lock_queue_item`] must never
+//! hand the same job to two data providers.
+//!
+//! Changes to indexes, tables or stored documents are made through
+//! [`migrations`] rather than at connection time, so that existing deployments
+//! pick them up too.
+
+pub mod memory;
+pub mod migrations;
+pub mod mongo;
+pub mod postgres;
+
+pub use memory::MemoryHandle;
+pub use migrations::{AppliedMigration, Migration, MigrationReport};
+pub use mongo::{DBHandle, DBPool};
+
+use crate::config::{Backend, IConfig};
+use crate::consensus::StatusClaim;
+use crate::data::{Data, Ingest};
+use crate::group::Group;
+use crate::metric::MetricPoint;
+use crate::relationship::Edge;
+use crate::reputation::Reputation;
+use crate::revision::ContentHistory;
+use crate::routes::invite::Referral;
+use crate::routes::leaderboard::Credit;
+use crate::routes::queue::{FailureReason, InternalQueueItem};
+use crate::routes::stats::QueueCounts;
+use crate::signature::Submission;
+use crate::subject::Subject;
+use crate::tombstone::Tombstone;
+use crate::user::User;
+use crate::username::UsernameRecord;
+
+use axum::async_trait;
+use axum::extract::{FromRequest, RequestParts};
+use axum::response::Response;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// A uniqueness constraint was violated, e.g. a subject name was reused.
+    Duplicate,
+    /// Any other error raised by the underlying database.
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate => write!(f, "Duplicate key."),
+            Self::Backend(e) => write!(f, "Storage backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Every migration this backend knows about, oldest first.
+    fn migrations(&self) -> &'static [Migration];
+    async fn applied_migrations(&self) -> StorageResult<Vec<AppliedMigration>>;
+    /// Runs a single migration step and records it as applied.
+    async fn apply_migration(&self, migration: &Migration)
+        -> StorageResult<()>;
+
+    async fn count_users(&self) -> StorageResult<u64>;
+    async fn insert_user(&self, user: &User) -> StorageResult<()>;
+    async fn user_with_key(&self, key: &str) -> StorageResult<Option<User>>;
+    async fn user_with_name(&self, name: &str) -> StorageResult<Option<User>>;
+    async fn users(&self, uuids: &[String]) -> StorageResult<Vec<User>>;
+    /// Replaces the key of the user holding `key`, returning the user as it
+    /// was before the reset.
+    async fn reset_key(
+        &self,
+        key: &str,
+        new_key: &str,
+    ) -> StorageResult<Option<User>>;
+    /// Replaces the public key of the user holding `key`, returning the user
+    /// as it was before.
+    async fn set_public_key(
+        &self,
+        key: &str,
+        public_key: Option<&str>,
+    ) -> StorageResult<Option<User>>;
+
+    async fn insert_referral(&self, referral: &Referral) -> StorageResult<()>;
+    async fn unused_referral(
+        &self,
+        code: &str,
+    ) -> StorageResult<Option<Referral>>;
+    /// Atomically marks an unused referral as used by `used_by`.
+    async fn use_referral(
+        &self,
+        code: &str,
+        used_by: &str,
+    ) -> StorageResult<Option<Referral>>;
+
+    /// Fails with [`StorageError::Duplicate`] if the creator already has a
+    /// subject by that name.
+    async fn insert_subject(&self, subject: &Subject) -> StorageResult<()>;
+    async fn subject(&self, uuid: &str) -> StorageResult<Option<Subject>>;
+    async fn subjects(&self, uuids: &[String]) -> StorageResult<Vec<Subject>>;
+    async fn subjects_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Subject>>;
+    /// Every subject tracking a profile.
+    async fn subjects_with_profile(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Subject>>;
+    /// Overwrites the name, profiles and description of the subject with the
+    /// same UUID.
+    async fn update_subject(&self, subject: &Subject) -> StorageResult<()>;
+    async fn delete_subject(&self, uuid: &str) -> StorageResult<()>;
+    /// Replaces `old_id` with `new_id` in the profiles of the first subject
+    /// tracking `old_id` on `platform`.
+    async fn replace_profile_id(
+        &self,
+        platform: &str,
+        old_id: &str,
+        new_id: &str,
+    ) -> StorageResult<()>;
+
+    async fn insert_group(&self, group: &Group) -> StorageResult<()>;
+    async fn group(&self, uuid: &str) -> StorageResult<Option<Group>>;
+    async fn groups_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Group>>;
+    /// Overwrites the name, subjects and description of the group with the
+    /// same UUID.
+    async fn update_group(&self, group: &Group) -> StorageResult<()>;
+    async fn delete_group(&self, uuid: &str) -> StorageResult<()>;
+    async fn remove_subject_from_groups(
+        &self,
+        subject_uuid: &str,
+    ) -> StorageResult<()>;
+
+    /// Adds a reference to the queue item for a profile, creating it if it
+    /// does not exist.
+    async fn add_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        confirmed_id: bool,
+    ) -> StorageResult<()>;
+    /// Removes a reference to the queue item for a profile, deleting it once
+    /// nothing references it.
+    async fn remove_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<()>;
+    async fn queue_item(
+        &self,
+        queue_id: &str,
+    ) -> StorageResult<Option<InternalQueueItem>>;
+    /// Atomically locks up to `count` of the unlocked queue items for any
+    /// platform in `leases` that have been due the longest on behalf of
+    /// `lock_holder`, each until the lease expiry given for its platform.
+    /// Items that are not due yet or are quarantined are never locked. Returns
+    /// the locked items, due the longest first.
+    async fn lock_queue_items(
+        &self,
+        leases: &HashMap<String, DateTime<Utc>>,
+        lock_holder: &str,
+        count: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>>;
+    /// Locks a single queue item, see [`Storage::lock_queue_items`].
+    async fn lock_queue_item(
+        &self,
+        leases: &HashMap<String, DateTime<Utc>>,
+        lock_holder: &str,
+    ) -> StorageResult<Option<InternalQueueItem>> {
+        Ok(self.lock_queue_items(leases, lock_holder, 1).await?.pop())
+    }
+    /// Releases the lock on a queue item held by `lock_holder` and marks it
+    /// as processed, clearing its failures and making it next due at
+    /// `hot_due_at` if it is still hot and at `cold_due_at` otherwise. Returns
+    /// false if no such lock was held.
+    async fn complete_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        hot_due_at: DateTime<Utc>,
+        cold_due_at: DateTime<Utc>,
+    ) -> StorageResult<bool>;
+    /// Extends the lease on a queue item held by `lock_holder` to
+    /// `expires_at`. Returns false if no such lock was held.
+    async fn extend_lock(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StorageResult<bool>;
+    /// Releases the lock on a queue item held by `lock_holder` without
+    /// marking it as processed. Returns false if no such lock was held.
+    async fn release_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+    ) -> StorageResult<bool>;
+    /// Releases the lock on a queue item held by `lock_holder` and records a
+    /// failure for `reason`, making it next due at `due_at` and quarantining
+    /// it if `quarantine`. Returns false if no such lock was held.
+    async fn fail_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        reason: FailureReason,
+        due_at: DateTime<Utc>,
+        quarantine: bool,
+    ) -> StorageResult<bool>;
+    /// The queue items of `profiles` (platform to platform IDs).
+    async fn queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>>;
+    /// How many queue items each platform has in total and in each state, in
+    /// platform order.
+    async fn queue_counts(&self) -> StorageResult<Vec<QueueCounts>>;
+    /// When the `n`th most recently processed queue item on `platform` was
+    /// last processed, counting from 1. None if fewer than `n` have been
+    /// processed.
+    async fn nth_processed(
+        &self,
+        platform: &str,
+        n: u64,
+    ) -> StorageResult<Option<DateTime<Utc>>>;
+    /// The `limit` queue items on `platform` created the longest ago that
+    /// have never been processed, oldest first.
+    async fn unprocessed_queue_items(
+        &self,
+        platform: &str,
+        limit: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>>;
+    /// The quarantined queue items of `profiles` (platform to platform IDs).
+    async fn quarantined_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>>;
+    /// Takes a queue item out of quarantine, clearing its failures and making
+    /// it due immediately. Returns false if it wasn't quarantined.
+    async fn restore_queue_item(&self, queue_id: &str) -> StorageResult<bool>;
+    /// Gives the queue item for `platform_id` on the platform of `from` the
+    /// schedule of `from`: when it was last processed, when it is next due
+    /// and until when it is hot.
+    async fn copy_queue_schedule(
+        &self,
+        from: &InternalQueueItem,
+        platform_id: &str,
+    ) -> StorageResult<()>;
+    /// Makes the queue items of `profiles` (platform to platform IDs) hot
+    /// until at least `hot_until`, bringing each forward to be due no later
+    /// than `hot_interval` after it was last processed. Items that have failed
+    /// since they were last processed are left to wait out their backoff.
+    async fn promote_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+        hot_until: DateTime<Utc>,
+        hot_interval: Duration,
+    ) -> StorageResult<()>;
+    /// Releases every lock whose lease expired before `now`. Returns how many
+    /// were released.
+    async fn expire_locks(&self, now: DateTime<Utc>) -> StorageResult<u64>;
+
+    /// Atomically adds `jobs` and `items` to the credit of `user` for
+    /// `period`, creating it if it does not exist.
+    async fn record_credit(
+        &self,
+        period: &str,
+        user: &str,
+        jobs: u64,
+        items: u64,
+    ) -> StorageResult<()>;
+    /// Every credit for `period`, by most jobs then most items.
+    async fn credits(&self, period: &str) -> StorageResult<Vec<Credit>>;
+    /// Every period with any credit, newest first.
+    async fn credit_periods(&self) -> StorageResult<Vec<String>>;
+
+    /// Atomically records an assessment of a submission by `user` that
+    /// included `provided` of `expected` fields, see [`crate::reputation`].
+    async fn record_reputation(
+        &self,
+        user: &str,
+        expected: u64,
+        provided: u64,
+    ) -> StorageResult<()>;
+    /// The reputations of `users`. Users that have never been assessed are
+    /// left out.
+    async fn reputations(
+        &self,
+        users: &[String],
+    ) -> StorageResult<Vec<Reputation>>;
+
+    async fn insert_submission(
+        &self,
+        submission: &Submission,
+    ) -> StorageResult<()>;
+    async fn submission(&self, uuid: &str)
+        -> StorageResult<Option<Submission>>;
+
+    /// Fails with [`StorageError::Duplicate`] if any content already exists.
+    /// Use [`Storage::merge_content`] for content instead.
+    async fn insert_data(&self, data: &[Data]) -> StorageResult<()>;
+    /// Inserts content, or atomically merges it into the content with the same
+    /// platform, content type and content ID as described by [`Data::merge`].
+    async fn merge_content(&self, content: &Data) -> StorageResult<Ingest>;
+    /// Atomically records the version of content observed by `content`, see
+    /// [`ContentHistory::observe`]. Does nothing for content without a body
+    /// or media.
+    async fn add_revision(&self, content: &Data) -> StorageResult<()>;
+    async fn content_history(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<Option<ContentHistory>>;
+    /// Records `claim`, unless its data provider has already made a claim
+    /// about the same content retrieved after it.
+    async fn record_status(&self, claim: &StatusClaim) -> StorageResult<()>;
+    /// The latest claim of every data provider about each piece of a
+    /// profile's content, see [`crate::consensus`].
+    async fn statuses(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<StatusClaim>>;
+    /// Inserts `tombstone`, or replaces the tombstone for the same content.
+    async fn record_tombstone(
+        &self,
+        tombstone: &Tombstone,
+    ) -> StorageResult<()>;
+    async fn remove_tombstone(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<()>;
+    /// Every tombstone for a profile's content, see [`crate::tombstone`].
+    async fn tombstones(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Tombstone>>;
+    /// Inserts each of `edges`, or replaces the edge for the same
+    /// relationship seen by the same data provider starting at the same
+    /// time.
+    async fn record_edges(&self, edges: &[Edge]) -> StorageResult<()>;
+    /// Every edge from a profile, see [`crate::relationship`].
+    async fn edges(
+        &self,
+        from_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Edge>>;
+    /// Records each of `points`, see [`crate::metric`]. Recording none is a
+    /// no-op.
+    async fn record_metrics(&self, points: &[MetricPoint])
+        -> StorageResult<()>;
+    /// The latest `limit` points of a profile's metrics retrieved between
+    /// `from` and `to`, either of which may be left open. With a `content_id`,
+    /// only the metrics of that content, otherwise only those of the profile
+    /// itself, see [`crate::metric`].
+    async fn metrics(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        content_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<MetricPoint>>;
+    /// Records that a profile had `username` at `retrieved_at`, see
+    /// [`UsernameRecord::observe`].
+    async fn record_username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        username: &str,
+        retrieved_at: DateTime<Utc>,
+    ) -> StorageResult<()>;
+    /// The most recently seen username of a profile.
+    async fn username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<String>>;
+    /// Every username a profile has had, most recently seen first.
+    async fn username_history(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>>;
+    /// Every profile that has had `username`, most recently seen first.
+    async fn username_owners(
+        &self,
+        username: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>>;
+    /// The most recently retrieved [`Data::Meta`] for a profile.
+    async fn meta(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<Data>>;
+    /// The latest `limit` [`Data::Meta`] for a profile retrieved at or before
+    /// `until`, if given, oldest first.
+    async fn metas(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        until: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>>;
+    /// The most recently retrieved [`Data::Meta`] for a profile added by
+    /// anyone but `added_by`.
+    async fn latest_meta_excluding(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        added_by: &str,
+    ) -> StorageResult<Option<Data>>;
+    /// The most recently retrieved content for a profile, newest first.
+    async fn content(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>>;
+    /// The most recently retrieved of a profile's content with the given
+    /// content types and IDs, newest first.
+    async fn content_with_ids(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        ids: &[(String, String)], // (content_type, content_id)
+        limit: i64,
+    ) -> StorageResult<Vec<Data>>;
+    /// The most recently retrieved presence for a profile retrieved between
+    /// `from` and `to`, either of which may be left open, newest first.
+    async fn presence(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>>;
+
+    /// Irreversibly removes everything held by this backend.
+    async fn drop(&self) -> StorageResult<()>;
+}
+
+/// A cheaply cloneable handle to the configured [`Storage`] backend.
+#[derive(Clone)]
+pub struct Store {
+    inner: Arc<dyn Storage>,
+    queue_changed: Arc<Notify>,
+}
+
+impl Store {
+    pub fn new<S: Storage + 'static>(storage: S) -> Self {
+        Self {
+            inner: Arc::new(storage),
+            queue_changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Wakes every request waiting on [`Store::queue_changed`]. Call this
+    /// whenever a queue item may have become available.
+    pub fn notify_queue(&self) {
+        self.queue_changed.notify_waiters();
+    }
+
+    /// Resolves on the next call to [`Store::notify_queue`] after this was
+    /// called, even if it isn't polled until later.
+    pub fn queue_changed(&self) -> Notified<'_> {
+        self.queue_changed.notified()
+    }
+}
+
+impl Deref for Store {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+/// Connects to the configured backend, applies any pending migrations and
+/// creates the root account if there are no users.
+pub async fn open(
+    config: &IConfig,
+) -> Result<Store, Box<dyn std::error::Error>> {
+    let store = connect(config).await?;
+
+    migrations::run(&*store).await?;
+
+    if store.count_users().await? == 0 {
+        tracing::info!("Creating root account...");
+        let root_user = create_root_account(&store).await?;
+        tracing::info!("\n{:#?}", root_user);
+    }
+
+    Ok(store)
+}
+
+/// Connects to the configured backend without changing anything.
+pub async fn connect(
+    config: &IConfig,
+) -> Result<Store, Box<dyn std::error::Error>> {
+    let store = match config.settings.backend {
+        Backend::MongoDB => {
+            let mongodb = config
+                .mongodb
+                .as_ref()
+                .ok_or("Missing [mongodb] section in config.")?;
+            Store::new(mongo::open(mongodb).await?.handle())
+        }
+        Backend::PostgreSQL => {
+            let postgres = config
+                .postgres
+                .as_ref()
+                .ok_or("Missing [postgres] section in config.")?;
+            Store::new(postgres::open(postgres).await?)
+        }
+        Backend::Memory => Store::new(MemoryHandle::new()),
+    };
+
+    Ok(store)
+}
+
+async fn create_root_account(store: &Store) -> StorageResult<User> {
+    let user = User::new("root");
+    store.insert_user(&user).await?;
+    Ok(user)
+}
+
+pub async fn drop_database(store: &Store) {
+    store.drop().await.unwrap();
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Store {
+    type Rejection = Response;
+
+    async fn from_request(
+        request: &mut RequestParts<B>,
+    ) -> Result<Self, Self::Rejection> {
+        let store = request.extensions().get::<Store>().unwrap();
+
+        Ok(store.clone())
+    }
+}
@@ -0,0 +1,2230 @@
+//! MongoDB implementation of [`Storage`].
+
+use crate::config::MDBIConfig;
+use crate::consensus::StatusClaim;
+use crate::data::{Data, Ingest};
+use crate::database::{
+    AppliedMigration, Migration, Storage, StorageError, StorageResult,
+};
+use crate::group::Group;
+use crate::metric::MetricPoint;
+use crate::relationship::Edge;
+use crate::reputation::Reputation;
+use crate::revision::ContentHistory;
+use crate::routes::invite::Referral;
+use crate::routes::leaderboard::Credit;
+use crate::routes::queue::{self, FailureReason, InternalQueueItem};
+use crate::routes::stats::QueueCounts;
+use crate::signature::Submission;
+use crate::subject::Subject;
+use crate::tombstone::Tombstone;
+use crate::user::User;
+use crate::username::UsernameRecord;
+
+use axum::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson::{self, Bson, Document};
+use mongodb::error::{BulkWriteFailure, ErrorKind, WriteFailure};
+use mongodb::options::{
+    ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions,
+    IndexOptions, ReplaceOptions, ReturnDocument, UpdateOptions,
+};
+use mongodb::results::CreateIndexResult;
+use mongodb::{bson::doc, Client, Collection, Cursor, Database, IndexModel};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+const DUPLICATE_KEY: i32 = 11000;
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create indexes.",
+    },
+    Migration {
+        version: 2,
+        description: "Create content history index.",
+    },
+    Migration {
+        version: 3,
+        description: "Create username history from metadata.",
+    },
+    Migration {
+        version: 4,
+        description: "Schedule queue items.",
+    },
+    Migration {
+        version: 5,
+        description: "Add queue lease expiry.",
+    },
+    Migration {
+        version: 6,
+        description: "Add queue item creation times.",
+    },
+    Migration {
+        version: 7,
+        description: "Create leaderboard index.",
+    },
+    Migration {
+        version: 8,
+        description: "Create reputation index.",
+    },
+    Migration {
+        version: 9,
+        description: "Create content status indexes.",
+    },
+    Migration {
+        version: 10,
+        description: "Create submission index.",
+    },
+    Migration {
+        version: 11,
+        description: "Create tombstone indexes.",
+    },
+    Migration {
+        version: 12,
+        description: "Create relationship edge indexes.",
+    },
+    Migration {
+        version: 13,
+        description: "Create metric index.",
+    },
+];
+
+#[derive(Clone)]
+pub struct DBPool {
+    client: Client,
+    database: String,
+}
+
+impl DBPool {
+    pub fn handle(&self) -> DBHandle {
+        DBHandle {
+            db: self.client.database(&self.database),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DBHandle {
+    db: Database,
+}
+
+impl DBHandle {
+    pub fn collection<T>(&self, name: &str) -> Collection<T> {
+        self.db.collection::<T>(name)
+    }
+
+    async fn build_username_history(&self) -> StorageResult<()> {
+        unique_username_index(&self.db).await?;
+        create_index(
+            "Usernames Username Index",
+            "usernames",
+            doc! {"platform": 1_u32, "username": 1_u32},
+            &self.db,
+        )
+        .await?;
+
+        #[derive(Deserialize)]
+        struct Meta {
+            id: String,
+            platform: String,
+            username: String,
+            retrieved_at: DateTime<Utc>,
+        }
+
+        let data_coll: Collection<Meta> = self.collection("data");
+        let mut cursor = data_coll
+            .find(doc! {"username": {"$exists": true}}, None)
+            .await?;
+        while let Some(meta) = cursor.next().await {
+            let meta = meta?;
+            self.record_username(
+                &meta.id,
+                &meta.platform,
+                &meta.username,
+                meta.retrieved_at,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Existing items are due in the order they were last processed, which
+    // until now was stored as a string.
+    async fn schedule_queue(&self) -> StorageResult<()> {
+        #[derive(Deserialize)]
+        struct Item {
+            queue_id: String,
+            last_processed: DateTime<Utc>,
+        }
+
+        let q_coll: Collection<Item> = self.collection("queue");
+        let mut cursor = q_coll
+            .find(doc! {"due_at": {"$exists": false}}, None)
+            .await?;
+        while let Some(item) = cursor.next().await {
+            let item = item?;
+            let last_processed = bson_datetime(&item.last_processed);
+            q_coll
+                .update_one(
+                    doc! {"queue_id": &item.queue_id},
+                    doc! {"$set":
+                        {"last_processed": last_processed,
+                        "due_at": last_processed,
+                        "hot_until": Bson::Null}
+                    },
+                    None,
+                )
+                .await?;
+        }
+
+        create_index(
+            "Queue Platform & Due Index",
+            "queue",
+            doc! {"platform": 1_u32, "due_at": 1_u32},
+            &self.db,
+        )
+        .await?;
+        Ok(())
+    }
+
+    // Lock times were stored as strings. Existing locks keep the 30 second
+    // lease they were taken with.
+    async fn add_queue_leases(&self) -> StorageResult<()> {
+        #[derive(Deserialize)]
+        struct Lock {
+            queue_id: String,
+            lock_acquired_at: DateTime<Utc>,
+        }
+
+        let q_coll: Collection<Lock> = self.collection("queue");
+        let mut cursor = q_coll
+            .find(doc! {"lock_acquired_at": {"$type": "string"}}, None)
+            .await?;
+        while let Some(lock) = cursor.next().await {
+            let lock = lock?;
+            let expires_at =
+                lock.lock_acquired_at + chrono::Duration::seconds(30);
+            q_coll
+                .update_one(
+                    doc! {"queue_id": &lock.queue_id},
+                    doc! {"$set":
+                        {"lock_acquired_at":
+                            bson_datetime(&lock.lock_acquired_at),
+                        "lock_expires_at": bson_datetime(&expires_at)}
+                    },
+                    None,
+                )
+                .await?;
+        }
+
+        create_index(
+            "Queue Lock Expiry Index",
+            "queue",
+            doc! {"lock_expires_at": 1_u32},
+            &self.db,
+        )
+        .await?;
+        Ok(())
+    }
+
+    // Existing items are treated as created when the migration runs.
+    async fn add_queue_creation_times(&self) -> StorageResult<()> {
+        let q_coll: Collection<Document> = self.collection("queue");
+        q_coll
+            .update_many(
+                doc! {"created_at": {"$exists": false}},
+                vec![doc! {"$set": {"created_at": "$$NOW"}}],
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn usernames(
+        &self,
+        filter: Document,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"last_seen": -1_i32})
+            .build();
+
+        let u_coll: Collection<UsernameDocument> = self.collection("usernames");
+        let cursor = u_coll.find(filter, options).await?;
+        let usernames = collect(cursor).await?;
+        Ok(usernames.into_iter().map(UsernameRecord::from).collect())
+    }
+}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(error: mongodb::error::Error) -> Self {
+        match error.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(e))
+                if e.code == DUPLICATE_KEY =>
+            {
+                Self::Duplicate
+            }
+            ErrorKind::Command(e) if e.code == DUPLICATE_KEY => Self::Duplicate,
+            ErrorKind::BulkWrite(BulkWriteFailure {
+                write_errors: Some(errors),
+                ..
+            }) if errors.iter().any(|e| e.code == DUPLICATE_KEY) => {
+                Self::Duplicate
+            }
+            _ => Self::Backend(Box::new(error)),
+        }
+    }
+}
+
+impl From<bson::ser::Error> for StorageError {
+    fn from(error: bson::ser::Error) -> Self {
+        Self::Backend(Box::new(error))
+    }
+}
+
+async fn collect<T>(cursor: Cursor<T>) -> StorageResult<Vec<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let results: Result<Vec<T>, mongodb::error::Error> = cursor.collect().await;
+    Ok(results?)
+}
+
+fn bson_datetime(datetime: &DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(datetime.timestamp_millis())
+}
+
+fn chrono_datetime(datetime: bson::DateTime) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(datetime.timestamp_millis())
+        .unwrap()
+}
+
+// Stored with BSON datetimes, rather than chrono's strings, so that $min and
+// $max compare them correctly.
+#[derive(Serialize, Deserialize)]
+struct UsernameDocument {
+    platform: String,
+    platform_id: String,
+    username: String,
+    first_seen: bson::DateTime,
+    last_seen: bson::DateTime,
+}
+
+impl From<UsernameDocument> for UsernameRecord {
+    fn from(document: UsernameDocument) -> Self {
+        Self {
+            platform: document.platform,
+            platform_id: document.platform_id,
+            username: document.username,
+            first_seen: chrono_datetime(document.first_seen),
+            last_seen: chrono_datetime(document.last_seen),
+        }
+    }
+}
+
+// Stored with BSON datetimes so that only newer claims replace older ones.
+#[derive(Serialize, Deserialize)]
+struct StatusDocument {
+    content_id: String,
+    platform: String,
+    content_type: String,
+    platform_id: String,
+    added_by: String,
+    deleted: bool,
+    retrieved_at: bson::DateTime,
+}
+
+impl From<StatusDocument> for StatusClaim {
+    fn from(document: StatusDocument) -> Self {
+        Self {
+            content_id: document.content_id,
+            platform: document.platform,
+            content_type: document.content_type,
+            platform_id: document.platform_id,
+            added_by: document.added_by,
+            deleted: document.deleted,
+            retrieved_at: chrono_datetime(document.retrieved_at),
+        }
+    }
+}
+
+// Stored with a BSON datetime so that points can be selected by time.
+#[derive(Serialize, Deserialize)]
+struct MetricDocument {
+    platform: String,
+    platform_id: String,
+    content_id: Option<String>,
+    metric_type: String,
+    value: f64,
+    retrieved_at: bson::DateTime,
+    added_by: Option<String>,
+}
+
+impl From<&MetricPoint> for MetricDocument {
+    fn from(point: &MetricPoint) -> Self {
+        Self {
+            platform: point.platform.clone(),
+            platform_id: point.platform_id.clone(),
+            content_id: point.content_id.clone(),
+            metric_type: point.metric_type.clone(),
+            value: point.value,
+            retrieved_at: bson_datetime(&point.retrieved_at),
+            added_by: point.added_by.clone(),
+        }
+    }
+}
+
+impl From<MetricDocument> for MetricPoint {
+    fn from(document: MetricDocument) -> Self {
+        Self {
+            platform: document.platform,
+            platform_id: document.platform_id,
+            content_id: document.content_id,
+            metric_type: document.metric_type,
+            value: document.value,
+            retrieved_at: chrono_datetime(document.retrieved_at),
+            added_by: document.added_by,
+        }
+    }
+}
+
+// Stored with BSON datetimes so that due items and expired leases can be
+// compared and sorted.
+#[derive(Serialize, Deserialize)]
+struct QueueDocument {
+    queue_id: String,
+    platform_id: String,
+    platform: String,
+    created_at: bson::DateTime,
+    last_processed: bson::DateTime,
+    due_at: bson::DateTime,
+    hot_until: Option<bson::DateTime>,
+    lock_holder: Option<String>,
+    lock_acquired_at: Option<bson::DateTime>,
+    lock_expires_at: Option<bson::DateTime>,
+    references: u64,
+    confirmed_id: bool,
+    #[serde(default)]
+    failures: u32,
+    last_failure: Option<FailureReason>,
+    quarantined_at: Option<bson::DateTime>,
+}
+
+#[derive(Deserialize)]
+struct QueueCountsDocument {
+    #[serde(rename = "_id")]
+    platform: String,
+    total: i64,
+    locked: i64,
+    unconfirmed: i64,
+    quarantined: i64,
+    never_processed: i64,
+}
+
+impl From<QueueCountsDocument> for QueueCounts {
+    fn from(document: QueueCountsDocument) -> Self {
+        Self {
+            platform: document.platform,
+            total: document.total as u64,
+            locked: document.locked as u64,
+            unconfirmed: document.unconfirmed as u64,
+            quarantined: document.quarantined as u64,
+            never_processed: document.never_processed as u64,
+        }
+    }
+}
+
+impl From<QueueDocument> for InternalQueueItem {
+    fn from(document: QueueDocument) -> Self {
+        Self {
+            queue_id: document.queue_id,
+            platform_id: document.platform_id,
+            platform: document.platform,
+            created_at: chrono_datetime(document.created_at),
+            last_processed: chrono_datetime(document.last_processed),
+            due_at: chrono_datetime(document.due_at),
+            hot_until: document.hot_until.map(chrono_datetime),
+            lock_holder: document.lock_holder,
+            lock_acquired_at: document.lock_acquired_at.map(chrono_datetime),
+            lock_expires_at: document.lock_expires_at.map(chrono_datetime),
+            references: document.references,
+            confirmed_id: document.confirmed_id,
+            failures: document.failures,
+            last_failure: document.last_failure,
+            quarantined_at: document.quarantined_at.map(chrono_datetime),
+        }
+    }
+}
+
+impl From<InternalQueueItem> for QueueDocument {
+    fn from(q_item: InternalQueueItem) -> Self {
+        Self {
+            queue_id: q_item.queue_id,
+            platform_id: q_item.platform_id,
+            platform: q_item.platform,
+            created_at: bson_datetime(&q_item.created_at),
+            last_processed: bson_datetime(&q_item.last_processed),
+            due_at: bson_datetime(&q_item.due_at),
+            hot_until: q_item.hot_until.as_ref().map(bson_datetime),
+            lock_holder: q_item.lock_holder,
+            lock_acquired_at: q_item
+                .lock_acquired_at
+                .as_ref()
+                .map(bson_datetime),
+            lock_expires_at: q_item.lock_expires_at.as_ref().map(bson_datetime),
+            references: q_item.references,
+            confirmed_id: q_item.confirmed_id,
+            failures: q_item.failures,
+            last_failure: q_item.last_failure,
+            quarantined_at: q_item.quarantined_at.as_ref().map(bson_datetime),
+        }
+    }
+}
+
+pub async fn open(
+    config: &MDBIConfig,
+) -> Result<DBPool, Box<dyn std::error::Error>> {
+    let user = &config.user;
+    let password = &config.password;
+    let hosts = &config.hosts;
+    let port = &config.port;
+    let database = &config.database;
+    let mut mongo_options = ClientOptions::parse(format!(
+        "mongodb://{user}:{password}@{hosts}:{port}"
+    ))
+    .await?;
+    mongo_options.connect_timeout = Some(Duration::new(1, 0));
+    mongo_options.heartbeat_freq = Some(Duration::new(1, 0));
+    mongo_options.server_selection_timeout = Some(Duration::new(1, 0));
+    let mongo_client = Client::with_options(mongo_options).unwrap();
+    let database = mongo_client.database(database);
+
+    // It is only at this point that MongoDB actually makes a connection.
+    database
+        .run_command(doc! {"ping" : 1_u32}, None)
+        .await
+        .expect("Couldn't connect to MongoDB");
+
+    tracing::info!("Connected to MongoDB.");
+
+    Ok(DBPool {
+        client: mongo_client,
+        database: config.database.to_string(),
+    })
+}
+
+async fn create_indexes(database: &Database) -> StorageResult<()> {
+    unique_content_index(database).await?;
+    unique_subject_name_index(database).await?;
+    create_index("Users Key Index", "users", doc! {"key" : 1_u32}, database)
+        .await?;
+    create_index(
+        "Users Key & Banned Index",
+        "users",
+        doc! {"key" : 1_u32, "banned" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Queue Platform & Platform ID",
+        "queue",
+        doc! {"platform" : 1_u32, "platform_id" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Queue ID Index",
+        "queue",
+        doc! {"queue_id" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Subjects UUID Index",
+        "subjects",
+        doc! {"uuid" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Groups UUID Index",
+        "groups",
+        doc! {"uuid" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Referrals Code Used Index",
+        "referrals",
+        doc! {"code" : 1_u32, "used" : 1_u32},
+        database,
+    )
+    .await?;
+    create_index(
+        "Data Platform ID & Platform Index",
+        "data",
+        doc! {"id" : 1_u32, "platform" : 1_u32},
+        database,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn unique_subject_name_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Subject Name"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"created_by" : 1_u32, "name": 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Subject>("subjects")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_content_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Content ID"))
+        .unique(true)
+        .sparse(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"content_id" : 1_u32,
+        "platform": 1_u32,
+        "content_type" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Data>("data")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_history_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Content History"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"content_id" : 1_u32,
+        "platform": 1_u32,
+        "content_type" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<ContentHistory>("histories")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_username_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Username"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"platform" : 1_u32,
+        "platform_id": 1_u32,
+        "username" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<UsernameDocument>("usernames")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_credit_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Credit"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"period" : 1_u32, "user": 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Credit>("credits")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_reputation_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Reputation"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"user" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Reputation>("reputations")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn unique_submission_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Submission"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"uuid" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Submission>("submissions")
+        .create_index(idx_model, None)
+        .await
+}
+
+async fn status_indexes(
+    database: &Database,
+) -> Result<(), mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Status"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"content_id" : 1_u32,
+        "platform": 1_u32,
+        "content_type" : 1_u32,
+        "added_by": 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<StatusDocument>("statuses")
+        .create_index(idx_model, None)
+        .await?;
+    create_index(
+        "Status Profile",
+        "statuses",
+        doc! {"platform": 1_u32, "platform_id": 1_u32},
+        database,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn tombstone_indexes(
+    database: &Database,
+) -> Result<(), mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Tombstone"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"content_id" : 1_u32,
+        "platform": 1_u32,
+        "content_type" : 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Tombstone>("tombstones")
+        .create_index(idx_model, None)
+        .await?;
+    create_index(
+        "Tombstone Profile",
+        "tombstones",
+        doc! {"platform": 1_u32, "platform_id": 1_u32},
+        database,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn edge_indexes(
+    database: &Database,
+) -> Result<(), mongodb::error::Error> {
+    let idx_options = IndexOptions::builder()
+        .name(String::from("Unique Edge"))
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(doc! {"platform": 1_u32,
+        "from_id": 1_u32,
+        "relation_type": 1_u32,
+        "to_id": 1_u32,
+        "added_by": 1_u32,
+        "first_seen": 1_u32})
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Edge>("edges")
+        .create_index(idx_model, None)
+        .await?;
+    create_index(
+        "Edge Profile",
+        "edges",
+        doc! {"platform": 1_u32, "from_id": 1_u32},
+        database,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn metric_index(
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    create_index(
+        "Metric Series",
+        "metrics",
+        doc! {"platform": 1_u32,
+        "platform_id": 1_u32,
+        "content_id": 1_u32,
+        "retrieved_at": -1_i32},
+        database,
+    )
+    .await
+}
+
+async fn create_index(
+    index_name: &str,
+    collection_name: &str,
+    keys: Document,
+    database: &Database,
+) -> Result<CreateIndexResult, mongodb::error::Error> {
+    let idx_options =
+        IndexOptions::builder().name(index_name.to_string()).build();
+
+    let idx_model = IndexModel::builder()
+        .keys(keys)
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Data>(collection_name)
+        .create_index(idx_model, None)
+        .await
+}
+
+#[async_trait]
+impl Storage for DBHandle {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn applied_migrations(&self) -> StorageResult<Vec<AppliedMigration>> {
+        let cursor = self
+            .collection::<AppliedMigration>("migrations")
+            .find(None, None)
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn apply_migration(
+        &self,
+        migration: &Migration,
+    ) -> StorageResult<()> {
+        match migration.version {
+            1 => create_indexes(&self.db).await?,
+            2 => unique_history_index(&self.db).await.map(|_| ())?,
+            3 => self.build_username_history().await?,
+            4 => self.schedule_queue().await?,
+            5 => self.add_queue_leases().await?,
+            6 => self.add_queue_creation_times().await?,
+            7 => unique_credit_index(&self.db).await.map(|_| ())?,
+            8 => unique_reputation_index(&self.db).await.map(|_| ())?,
+            9 => status_indexes(&self.db).await?,
+            10 => unique_submission_index(&self.db).await.map(|_| ())?,
+            11 => tombstone_indexes(&self.db).await?,
+            12 => edge_indexes(&self.db).await?,
+            13 => metric_index(&self.db).await.map(|_| ())?,
+            v => {
+                return Err(StorageError::Backend(
+                    format!("Unknown migration {v}.").into(),
+                ))
+            }
+        }
+
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection::<AppliedMigration>("migrations")
+            .replace_one(
+                doc! {"version": migration.version},
+                AppliedMigration::new(migration),
+                options,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn count_users(&self) -> StorageResult<u64> {
+        let users: Collection<User> = self.collection("users");
+        Ok(users.count_documents(None, None).await?)
+    }
+
+    async fn insert_user(&self, user: &User) -> StorageResult<()> {
+        let users: Collection<User> = self.collection("users");
+        users.insert_one(user, None).await?;
+        Ok(())
+    }
+
+    async fn user_with_key(&self, key: &str) -> StorageResult<Option<User>> {
+        let users: Collection<User> = self.collection("users");
+        // Vulnerable to nosql injection?
+        // Very basic tests says no. Not convinced.
+        Ok(users.find_one(doc! {"key": key}, None).await?)
+    }
+
+    async fn user_with_name(&self, name: &str) -> StorageResult<Option<User>> {
+        let users: Collection<User> = self.collection("users");
+        Ok(users.find_one(doc! {"name": name}, None).await?)
+    }
+
+    async fn users(&self, uuids: &[String]) -> StorageResult<Vec<User>> {
+        let users: Collection<User> = self.collection("users");
+        let cursor = users.find(doc! {"uuid": {"$in": uuids}}, None).await?;
+        collect(cursor).await
+    }
+
+    async fn reset_key(
+        &self,
+        key: &str,
+        new_key: &str,
+    ) -> StorageResult<Option<User>> {
+        let users: Collection<User> = self.collection("users");
+        Ok(users
+            .find_one_and_update(
+                doc! {"key": key},
+                doc! { "$set": {"key": new_key}},
+                None,
+            )
+            .await?)
+    }
+
+    async fn set_public_key(
+        &self,
+        key: &str,
+        public_key: Option<&str>,
+    ) -> StorageResult<Option<User>> {
+        let users: Collection<User> = self.collection("users");
+        Ok(users
+            .find_one_and_update(
+                doc! {"key": key},
+                doc! { "$set": {"public_key": public_key}},
+                None,
+            )
+            .await?)
+    }
+
+    async fn insert_referral(&self, referral: &Referral) -> StorageResult<()> {
+        let referrals: Collection<Referral> = self.collection("referrals");
+        referrals.insert_one(referral, None).await?;
+        Ok(())
+    }
+
+    async fn unused_referral(
+        &self,
+        code: &str,
+    ) -> StorageResult<Option<Referral>> {
+        let referrals: Collection<Referral> = self.collection("referrals");
+        Ok(referrals
+            .find_one(doc! {"code": code, "used" : false}, None)
+            .await?)
+    }
+
+    async fn use_referral(
+        &self,
+        code: &str,
+        used_by: &str,
+    ) -> StorageResult<Option<Referral>> {
+        let referrals: Collection<Referral> = self.collection("referrals");
+        Ok(referrals
+            .find_one_and_update(
+                doc! {"code": code, "used": false},
+                doc! {"$set": {"used": true, "used_by": used_by}},
+                None,
+            )
+            .await?)
+    }
+
+    async fn insert_subject(&self, subject: &Subject) -> StorageResult<()> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        subjects.insert_one(subject, None).await?;
+        Ok(())
+    }
+
+    async fn subject(&self, uuid: &str) -> StorageResult<Option<Subject>> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        Ok(subjects.find_one(doc! {"uuid": uuid}, None).await?)
+    }
+
+    async fn subjects(&self, uuids: &[String]) -> StorageResult<Vec<Subject>> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        let cursor = subjects.find(doc! {"uuid": {"$in": uuids}}, None).await?;
+        collect(cursor).await
+    }
+
+    async fn subjects_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        let cursor =
+            subjects.find(doc! {"created_by": created_by}, None).await?;
+        collect(cursor).await
+    }
+
+    async fn subjects_with_profile(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        let platform_query_string = format!("profiles.{}", platform);
+        let cursor = subjects
+            .find(doc! {&platform_query_string: platform_id}, None)
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn update_subject(&self, subject: &Subject) -> StorageResult<()> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        subjects
+            .update_one(
+                doc! {"uuid": &subject.uuid},
+                doc! {"$set":
+                    {"name": &subject.name,
+                    "profiles": bson::to_bson(&subject.profiles)?,
+                    "description": &subject.description}
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_subject(&self, uuid: &str) -> StorageResult<()> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        subjects.delete_one(doc! {"uuid": uuid}, None).await?;
+        Ok(())
+    }
+
+    async fn replace_profile_id(
+        &self,
+        platform: &str,
+        old_id: &str,
+        new_id: &str,
+    ) -> StorageResult<()> {
+        let subjects: Collection<Subject> = self.collection("subjects");
+        let platform_query_string = format!("profiles.{}", platform);
+        let platform_set_string = format!("profiles.{}.$", platform);
+        subjects
+            .update_one(
+                doc! {&platform_query_string: old_id},
+                doc! {"$set": {&platform_set_string: new_id}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &Group) -> StorageResult<()> {
+        let groups: Collection<Group> = self.collection("groups");
+        groups.insert_one(group, None).await?;
+        Ok(())
+    }
+
+    async fn group(&self, uuid: &str) -> StorageResult<Option<Group>> {
+        let groups: Collection<Group> = self.collection("groups");
+        Ok(groups.find_one(doc! {"uuid": uuid}, None).await?)
+    }
+
+    async fn groups_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Group>> {
+        let groups: Collection<Group> = self.collection("groups");
+        let cursor = groups.find(doc! {"created_by": created_by}, None).await?;
+        collect(cursor).await
+    }
+
+    async fn update_group(&self, group: &Group) -> StorageResult<()> {
+        let groups: Collection<Group> = self.collection("groups");
+        groups
+            .update_one(
+                doc! {"uuid": &group.uuid},
+                doc! {"$set":
+                    {"name": &group.name,
+                    "subjects": &group.subjects,
+                    "description": &group.description}
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_group(&self, uuid: &str) -> StorageResult<()> {
+        let groups: Collection<Group> = self.collection("groups");
+        groups.delete_one(doc! {"uuid": uuid}, None).await?;
+        Ok(())
+    }
+
+    async fn remove_subject_from_groups(
+        &self,
+        subject_uuid: &str,
+    ) -> StorageResult<()> {
+        let groups: Collection<Group> = self.collection("groups");
+        groups
+            .update_many(
+                doc! {"subjects": subject_uuid},
+                doc! {"$pull": {"subjects": subject_uuid}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn add_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        confirmed_id: bool,
+    ) -> StorageResult<()> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let q_item = q_coll
+            .find_one(
+                doc! {"platform_id": platform_id, "platform": platform},
+                None,
+            )
+            .await?;
+        if q_item.is_some() {
+            q_coll
+                .update_one(
+                    doc! {"platform_id": platform_id, "platform": platform},
+                    doc! {"$inc": {"references": 1_u32}},
+                    None,
+                )
+                .await?;
+            if confirmed_id {
+                q_coll
+                    .update_one(
+                        doc! {"platform_id": platform_id, "platform": platform},
+                        doc! {"$set": {"confirmed_id": true}},
+                        None,
+                    )
+                    .await?;
+            }
+        } else {
+            let q_item: InternalQueueItem = InternalQueueItem::new(
+                platform_id.to_string(),
+                platform.to_string(),
+            );
+            q_coll.insert_one(QueueDocument::from(q_item), None).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<()> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .delete_one(
+                doc! {"platform_id": platform_id,
+                "platform": platform,
+                "references": 1},
+                None,
+            )
+            .await?;
+        if result.deleted_count == 0 {
+            q_coll
+                .update_one(
+                    doc! {"platform_id": platform_id, "platform": platform},
+                    doc! {"$inc": {"references": -1_i32}},
+                    None,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn queue_item(
+        &self,
+        queue_id: &str,
+    ) -> StorageResult<Option<InternalQueueItem>> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let q_item = q_coll.find_one(doc! {"queue_id": queue_id}, None).await?;
+        Ok(q_item.map(InternalQueueItem::from))
+    }
+
+    // MongoDB can't atomically modify several documents at once without a
+    // transaction, so items are locked one at a time. Each is still locked
+    // atomically, so no item is ever handed out twice.
+    async fn lock_queue_items(
+        &self,
+        leases: &HashMap<String, DateTime<Utc>>,
+        lock_holder: &str,
+        count: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! {"due_at": 1_i32})
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let platforms: Vec<&String> = leases.keys().collect();
+        let expiries: Vec<Document> = leases
+            .iter()
+            .map(|(platform, expires_at)| {
+                doc! {"case": {"$eq": ["$platform", platform]},
+                "then": bson_datetime(expires_at)}
+            })
+            .collect();
+        if expiries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let now = bson_datetime(&Utc::now());
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let mut q_items = Vec::new();
+        while q_items.len() < count {
+            let q_item = q_coll
+                .find_one_and_update(
+                    doc! {"lock_holder": Bson::Null,
+                    "quarantined_at": Bson::Null,
+                    "platform": {"$in": &platforms},
+                    "due_at": {"$lte": now}},
+                    vec![doc! {"$set":
+                        {"lock_holder": {"$literal": lock_holder},
+                        "lock_acquired_at": now,
+                        "lock_expires_at": {"$switch": {"branches": &expiries}}}
+                    }],
+                    options.clone(),
+                )
+                .await?;
+            match q_item {
+                Some(q_item) => q_items.push(InternalQueueItem::from(q_item)),
+                None => break,
+            }
+        }
+        Ok(q_items)
+    }
+
+    async fn complete_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        hot_due_at: DateTime<Utc>,
+        cold_due_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let now = bson_datetime(&Utc::now());
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_one(
+                doc! {"queue_id" : queue_id, "lock_holder": lock_holder},
+                vec![doc! {"$set":
+                    {"lock_holder": Bson::Null,
+                    "lock_acquired_at": Bson::Null,
+                    "lock_expires_at": Bson::Null,
+                    "last_processed": now,
+                    "failures": 0_i32,
+                    "last_failure": Bson::Null,
+                    "due_at": {"$cond": [
+                        {"$gt": ["$hot_until", now]},
+                        bson_datetime(&hot_due_at),
+                        bson_datetime(&cold_due_at),
+                    ]}}
+                }],
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    async fn extend_lock(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_one(
+                doc! {"queue_id" : queue_id, "lock_holder": lock_holder},
+                doc! {"$set": {"lock_expires_at": bson_datetime(&expires_at)}},
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn release_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+    ) -> StorageResult<bool> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_one(
+                doc! {"queue_id" : queue_id, "lock_holder": lock_holder},
+                doc! {"$set":
+                    {"lock_holder": Bson::Null,
+                    "lock_acquired_at": Bson::Null,
+                    "lock_expires_at": Bson::Null}
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    async fn fail_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        reason: FailureReason,
+        due_at: DateTime<Utc>,
+        quarantine: bool,
+    ) -> StorageResult<bool> {
+        let quarantined_at = if quarantine {
+            Bson::DateTime(bson_datetime(&Utc::now()))
+        } else {
+            Bson::Null
+        };
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_one(
+                doc! {"queue_id" : queue_id, "lock_holder": lock_holder},
+                doc! {"$set":
+                    {"lock_holder": Bson::Null,
+                    "lock_acquired_at": Bson::Null,
+                    "lock_expires_at": Bson::Null,
+                    "due_at": bson_datetime(&due_at),
+                    "last_failure": bson::to_bson(&reason)?,
+                    "quarantined_at": quarantined_at},
+                "$inc": {"failures": 1_i32}
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    async fn queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let profiles: Vec<Document> = profiles
+            .iter()
+            .map(|(platform, ids)| {
+                doc! {"platform": platform, "platform_id": {"$in": ids}}
+            })
+            .collect();
+        if profiles.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let cursor = q_coll.find(doc! {"$or": profiles}, None).await?;
+        let q_items = collect(cursor).await?;
+        Ok(q_items.into_iter().map(InternalQueueItem::from).collect())
+    }
+
+    async fn queue_counts(&self) -> StorageResult<Vec<QueueCounts>> {
+        // 1 for queue items with `condition`, else 0.
+        let count = |condition: Bson| doc! {"$cond": [condition, 1_i32, 0_i32]};
+        // Missing and null fields both sort before any value.
+        let set =
+            |field: &str| count(bson::bson!({"$gt": [field, Bson::Null]}));
+        let never = bson_datetime(&queue::never());
+        let pipeline = vec![
+            doc! {"$group": {
+                "_id": "$platform",
+                "total": {"$sum": 1_i32},
+                "locked": {"$sum": set("$lock_holder")},
+                "unconfirmed": {"$sum": count(
+                    bson::bson!({"$not": ["$confirmed_id"]})
+                )},
+                "quarantined": {"$sum": set("$quarantined_at")},
+                "never_processed": {"$sum": count(
+                    bson::bson!({"$lte": ["$last_processed", never]})
+                )},
+            }},
+            doc! {"$sort": {"_id": 1_i32}},
+        ];
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let cursor = q_coll.aggregate(pipeline, None).await?;
+        let counts = collect(cursor.with_type::<QueueCountsDocument>()).await?;
+        Ok(counts.into_iter().map(QueueCounts::from).collect())
+    }
+
+    async fn nth_processed(
+        &self,
+        platform: &str,
+        n: u64,
+    ) -> StorageResult<Option<DateTime<Utc>>> {
+        if n == 0 {
+            return Ok(None);
+        }
+        let options = FindOneOptions::builder()
+            .sort(doc! {"last_processed": -1_i32})
+            .skip(n - 1)
+            .build();
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let q_item = q_coll
+            .find_one(
+                doc! {"platform": platform,
+                "last_processed": {"$gt": bson_datetime(&queue::never())}},
+                options,
+            )
+            .await?;
+        Ok(q_item.map(|q| chrono_datetime(q.last_processed)))
+    }
+
+    async fn unprocessed_queue_items(
+        &self,
+        platform: &str,
+        limit: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"created_at": 1_i32})
+            .limit(limit as i64)
+            .build();
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let cursor = q_coll
+            .find(
+                doc! {"platform": platform,
+                "last_processed": {"$lte": bson_datetime(&queue::never())}},
+                options,
+            )
+            .await?;
+        let q_items = collect(cursor).await?;
+        Ok(q_items.into_iter().map(InternalQueueItem::from).collect())
+    }
+
+    async fn quarantined_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let profiles: Vec<Document> = profiles
+            .iter()
+            .map(|(platform, ids)| {
+                doc! {"platform": platform, "platform_id": {"$in": ids}}
+            })
+            .collect();
+        if profiles.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let options = FindOptions::builder()
+            .sort(doc! {"quarantined_at": 1_i32})
+            .build();
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let cursor = q_coll
+            .find(
+                doc! {"quarantined_at": {"$ne": Bson::Null}, "$or": profiles},
+                options,
+            )
+            .await?;
+        let q_items = collect(cursor).await?;
+        Ok(q_items.into_iter().map(InternalQueueItem::from).collect())
+    }
+
+    async fn restore_queue_item(&self, queue_id: &str) -> StorageResult<bool> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_one(
+                doc! {"queue_id": queue_id,
+                "quarantined_at": {"$ne": Bson::Null}},
+                doc! {"$set":
+                    {"quarantined_at": Bson::Null,
+                    "failures": 0_i32,
+                    "last_failure": Bson::Null,
+                    "due_at": bson_datetime(&Utc::now())}
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    async fn copy_queue_schedule(
+        &self,
+        from: &InternalQueueItem,
+        platform_id: &str,
+    ) -> StorageResult<()> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        q_coll
+            .update_one(
+                doc! {"platform_id": platform_id, "platform": &from.platform},
+                doc! {"$set":
+                    {"last_processed": bson_datetime(&from.last_processed),
+                    "due_at": bson_datetime(&from.due_at),
+                    "hot_until": from.hot_until.as_ref().map(bson_datetime)}
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn promote_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+        hot_until: DateTime<Utc>,
+        hot_interval: chrono::Duration,
+    ) -> StorageResult<()> {
+        let profiles: Vec<Document> = profiles
+            .iter()
+            .map(|(platform, ids)| {
+                doc! {"platform": platform, "platform_id": {"$in": ids}}
+            })
+            .collect();
+        if profiles.is_empty() {
+            return Ok(());
+        }
+
+        // $max ignores null, so items that were never hot become hot.
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        q_coll
+            .update_many(
+                doc! {"$or": profiles},
+                vec![doc! {"$set":
+                    {"hot_until":
+                        {"$max": ["$hot_until", bson_datetime(&hot_until)]},
+                    "due_at": {"$cond": [
+                        {"$eq": ["$failures", 0_i32]},
+                        {"$min": [
+                            "$due_at",
+                            {"$add": [
+                                "$last_processed",
+                                hot_interval.num_milliseconds(),
+                            ]},
+                        ]},
+                        "$due_at",
+                    ]}}
+                }],
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn expire_locks(&self, now: DateTime<Utc>) -> StorageResult<u64> {
+        let q_coll: Collection<QueueDocument> = self.collection("queue");
+        let result = q_coll
+            .update_many(
+                doc! {"lock_expires_at": {"$lt": bson_datetime(&now)}},
+                doc! {"$set":
+                    {"lock_holder": Bson::Null,
+                    "lock_acquired_at": Bson::Null,
+                    "lock_expires_at": Bson::Null}
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    async fn record_credit(
+        &self,
+        period: &str,
+        user: &str,
+        jobs: u64,
+        items: u64,
+    ) -> StorageResult<()> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        let c_coll: Collection<Credit> = self.collection("credits");
+        loop {
+            let recorded = c_coll
+                .update_one(
+                    doc! {"period": period, "user": user},
+                    doc! {"$inc": {"jobs": jobs as i64, "items": items as i64}},
+                    options.clone(),
+                )
+                .await;
+            return match recorded.map_err(StorageError::from) {
+                Ok(_) => Ok(()),
+                // Two upserts of a new credit can race.
+                Err(StorageError::Duplicate) => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    async fn credits(&self, period: &str) -> StorageResult<Vec<Credit>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"jobs": -1_i32, "items": -1_i32})
+            .build();
+        let c_coll: Collection<Credit> = self.collection("credits");
+        let cursor = c_coll.find(doc! {"period": period}, options).await?;
+        collect(cursor).await
+    }
+
+    async fn credit_periods(&self) -> StorageResult<Vec<String>> {
+        let c_coll: Collection<Credit> = self.collection("credits");
+        let periods = c_coll.distinct("period", None, None).await?;
+        let mut periods: Vec<String> = periods
+            .into_iter()
+            .filter_map(|p| p.as_str().map(|p| p.to_string()))
+            .collect();
+        periods.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(periods)
+    }
+
+    async fn record_reputation(
+        &self,
+        user: &str,
+        expected: u64,
+        provided: u64,
+    ) -> StorageResult<()> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        let r_coll: Collection<Reputation> = self.collection("reputations");
+        loop {
+            let recorded = r_coll
+                .update_one(
+                    doc! {"user": user},
+                    doc! {"$inc": {"assessed": 1_i64,
+                    "expected": expected as i64,
+                    "provided": provided as i64}},
+                    options.clone(),
+                )
+                .await;
+            return match recorded.map_err(StorageError::from) {
+                Ok(_) => Ok(()),
+                // Two upserts of a new reputation can race.
+                Err(StorageError::Duplicate) => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    async fn reputations(
+        &self,
+        users: &[String],
+    ) -> StorageResult<Vec<Reputation>> {
+        let r_coll: Collection<Reputation> = self.collection("reputations");
+        let cursor = r_coll.find(doc! {"user": {"$in": users}}, None).await?;
+        collect(cursor).await
+    }
+
+    async fn insert_submission(
+        &self,
+        submission: &Submission,
+    ) -> StorageResult<()> {
+        let s_coll: Collection<Submission> = self.collection("submissions");
+        s_coll.insert_one(submission, None).await?;
+        Ok(())
+    }
+
+    async fn submission(
+        &self,
+        uuid: &str,
+    ) -> StorageResult<Option<Submission>> {
+        let s_coll: Collection<Submission> = self.collection("submissions");
+        Ok(s_coll.find_one(doc! {"uuid": uuid}, None).await?)
+    }
+
+    async fn insert_data(&self, data: &[Data]) -> StorageResult<()> {
+        let data_coll: Collection<Data> = self.collection("data");
+        data_coll.insert_many(data, None).await?;
+        Ok(())
+    }
+
+    async fn merge_content(&self, content: &Data) -> StorageResult<Ingest> {
+        let (content_id, platform, content_type, deleted, references) =
+            match content {
+                Data::Content {
+                    content_id,
+                    platform,
+                    content_type,
+                    deleted,
+                    references,
+                    ..
+                } => (content_id, platform, content_type, deleted, references),
+                _ => {
+                    return Err(StorageError::Backend(
+                        "Expected content.".into(),
+                    ))
+                }
+            };
+
+        // The same rules as Data::merge as a single atomic update: stored
+        // fields are kept unless they are null, references gain missing keys
+        // and content may only become deleted. Values are wrapped in $literal
+        // so that strings beginning with $ aren't read as field paths. The
+        // submission is only set on insert, and cleared below.
+        let mut set = Document::new();
+        for (field, value) in bson::to_document(content)? {
+            let merged = if field == "submission" {
+                doc! {"$cond": [
+                    {"$eq": [{"$type": "$retrieved_at"}, "missing"]},
+                    {"$literal": value},
+                    "$submission"
+                ]}
+            } else {
+                doc! {"$ifNull": [format!("${field}"), {"$literal": value}]}
+            };
+            set.insert(field, merged);
+        }
+        if *deleted == Some(true) {
+            set.insert("deleted", true);
+        }
+        if let Some(references) = references {
+            set.insert(
+                "references",
+                doc! {"$mergeObjects": [
+                    {"$literal": bson::to_bson(references)?},
+                    {"$ifNull": ["$references", {}]}
+                ]},
+            );
+        }
+
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::Before)
+            .build();
+
+        let key = doc! {"content_id": content_id,
+        "platform": platform,
+        "content_type": content_type};
+        let data_coll: Collection<Data> = self.collection("data");
+        loop {
+            let existing = data_coll
+                .find_one_and_update(
+                    key.clone(),
+                    vec![doc! {"$set": set.clone()}],
+                    options.clone(),
+                )
+                .await;
+            return match existing.map_err(StorageError::from) {
+                Ok(None) => Ok(Ingest::Inserted),
+                Ok(Some(existing)) => {
+                    let merged = match existing.merge(content) {
+                        Some(merged) => merged,
+                        None => return Ok(Ingest::Unchanged),
+                    };
+                    if let (
+                        Data::Content {
+                            submission: Some(read),
+                            ..
+                        },
+                        Data::Content {
+                            submission: None, ..
+                        },
+                    ) = (&existing, &merged)
+                    {
+                        // Merged from another submission. Filtered on the
+                        // submission read, so that it can only be cleared.
+                        let mut filter = key.clone();
+                        filter.insert("submission", read);
+                        data_coll
+                            .update_one(
+                                filter,
+                                doc! {"$set": {"submission": Bson::Null}},
+                                None,
+                            )
+                            .await?;
+                    }
+                    Ok(Ingest::Merged)
+                }
+                // Two upserts of the same new content can race, the loser
+                // merges into the winner.
+                Err(StorageError::Duplicate) => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    async fn add_revision(&self, content: &Data) -> StorageResult<()> {
+        let history = match ContentHistory::new(content) {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        let key = doc! {"content_id": &history.content_id,
+        "platform": &history.platform,
+        "content_type": &history.content_type};
+
+        let h_coll: Collection<ContentHistory> = self.collection("histories");
+        // Compare and swap, as in the PostgreSQL backend.
+        loop {
+            let existing = h_coll.find_one(key.clone(), None).await?;
+            let result = match existing {
+                Some(mut existing) => {
+                    let mut filter = key.clone();
+                    filter.insert(
+                        "revisions",
+                        bson::to_bson(&existing.revisions)?,
+                    );
+                    existing.observe(history.revisions[0].clone());
+                    let updated = h_coll
+                        .update_one(
+                            filter,
+                            doc! {"$set": {"revisions":
+                            bson::to_bson(&existing.revisions)?}},
+                            None,
+                        )
+                        .await?;
+                    if updated.matched_count == 1 {
+                        Ok(())
+                    } else {
+                        continue;
+                    }
+                }
+                None => h_coll
+                    .insert_one(&history, None)
+                    .await
+                    .map(|_| ())
+                    .map_err(StorageError::from),
+            };
+            match result {
+                // The history was created by someone else in the meantime.
+                Err(StorageError::Duplicate) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    async fn content_history(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<Option<ContentHistory>> {
+        let h_coll: Collection<ContentHistory> = self.collection("histories");
+        Ok(h_coll
+            .find_one(
+                doc! {"content_id": content_id,
+                "platform": platform,
+                "content_type": content_type},
+                None,
+            )
+            .await?)
+    }
+
+    async fn record_status(&self, claim: &StatusClaim) -> StorageResult<()> {
+        let retrieved_at = bson_datetime(&claim.retrieved_at);
+        // A missing retrieval time, i.e. a new claim, is never after.
+        let newer = doc! {"$not": [{"$gt": ["$retrieved_at", retrieved_at]}]};
+        let update = doc! {"$set": {
+            "platform_id": {"$literal": &claim.platform_id},
+            "deleted": {"$cond": [newer.clone(), claim.deleted, "$deleted"]},
+            "retrieved_at":
+                {"$cond": [newer, retrieved_at, "$retrieved_at"]},
+        }};
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        let s_coll: Collection<StatusDocument> = self.collection("statuses");
+        loop {
+            let recorded = s_coll
+                .update_one(
+                    doc! {"content_id": &claim.content_id,
+                    "platform": &claim.platform,
+                    "content_type": &claim.content_type,
+                    "added_by": &claim.added_by},
+                    vec![update.clone()],
+                    options.clone(),
+                )
+                .await;
+            return match recorded.map_err(StorageError::from) {
+                Ok(_) => Ok(()),
+                // Two upserts of a new claim can race.
+                Err(StorageError::Duplicate) => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    async fn statuses(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<StatusClaim>> {
+        let s_coll: Collection<StatusDocument> = self.collection("statuses");
+        let cursor = s_coll
+            .find(
+                doc! {"platform_id": platform_id, "platform": platform},
+                None,
+            )
+            .await?;
+        let statuses = collect(cursor).await?;
+        Ok(statuses.into_iter().map(StatusClaim::from).collect())
+    }
+
+    async fn record_tombstone(
+        &self,
+        tombstone: &Tombstone,
+    ) -> StorageResult<()> {
+        let t_coll: Collection<Tombstone> = self.collection("tombstones");
+        let options = ReplaceOptions::builder().upsert(true).build();
+        t_coll
+            .replace_one(
+                doc! {"content_id": &tombstone.content_id,
+                "platform": &tombstone.platform,
+                "content_type": &tombstone.content_type},
+                tombstone,
+                options,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_tombstone(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<()> {
+        let t_coll: Collection<Tombstone> = self.collection("tombstones");
+        t_coll
+            .delete_one(
+                doc! {"content_id": content_id,
+                "platform": platform,
+                "content_type": content_type},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn tombstones(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Tombstone>> {
+        let t_coll: Collection<Tombstone> = self.collection("tombstones");
+        let cursor = t_coll
+            .find(
+                doc! {"platform_id": platform_id, "platform": platform},
+                None,
+            )
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn record_edges(&self, edges: &[Edge]) -> StorageResult<()> {
+        let e_coll: Collection<Edge> = self.collection("edges");
+        for edge in edges {
+            let options = ReplaceOptions::builder().upsert(true).build();
+            e_coll
+                .replace_one(
+                    doc! {"platform": &edge.platform,
+                    "from_id": &edge.from_id,
+                    "relation_type": &edge.relation_type,
+                    "to_id": &edge.to_id,
+                    "added_by": &edge.added_by,
+                    "first_seen": bson::to_bson(&edge.first_seen)?},
+                    edge,
+                    options,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn edges(
+        &self,
+        from_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Edge>> {
+        let e_coll: Collection<Edge> = self.collection("edges");
+        let cursor = e_coll
+            .find(doc! {"from_id": from_id, "platform": platform}, None)
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn record_metrics(
+        &self,
+        points: &[MetricPoint],
+    ) -> StorageResult<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        let m_coll: Collection<MetricDocument> = self.collection("metrics");
+        m_coll
+            .insert_many(points.iter().map(MetricDocument::from), None)
+            .await?;
+        Ok(())
+    }
+
+    async fn metrics(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        content_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<MetricPoint>> {
+        let mut retrieved_at = Document::new();
+        if let Some(from) = from {
+            retrieved_at.insert("$gte", bson_datetime(&from));
+        }
+        if let Some(to) = to {
+            retrieved_at.insert("$lte", bson_datetime(&to));
+        }
+        let mut filter = doc! {"platform_id": platform_id,
+        "platform": platform,
+        "content_id": content_id};
+        if !retrieved_at.is_empty() {
+            filter.insert("retrieved_at", retrieved_at);
+        }
+
+        let m_coll: Collection<MetricDocument> = self.collection("metrics");
+        let cursor = m_coll.find(filter, recent(limit)).await?;
+        let documents = collect(cursor).await?;
+        Ok(documents.into_iter().map(MetricPoint::from).collect())
+    }
+
+    async fn record_username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        username: &str,
+        retrieved_at: DateTime<Utc>,
+    ) -> StorageResult<()> {
+        let retrieved_at = bson_datetime(&retrieved_at);
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        let u_coll: Collection<UsernameDocument> = self.collection("usernames");
+        loop {
+            let recorded = u_coll
+                .update_one(
+                    doc! {"platform": platform,
+                    "platform_id": platform_id,
+                    "username": username},
+                    doc! {"$min": {"first_seen": retrieved_at},
+                    "$max": {"last_seen": retrieved_at}},
+                    options.clone(),
+                )
+                .await;
+            return match recorded.map_err(StorageError::from) {
+                Ok(_) => Ok(()),
+                // Two upserts of a new username can race.
+                Err(StorageError::Duplicate) => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    async fn username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<String>> {
+        Ok(self
+            .username_history(platform_id, platform)
+            .await?
+            .into_iter()
+            .next()
+            .map(|u| u.username))
+    }
+
+    async fn username_history(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        self.usernames(doc! {"platform_id": platform_id, "platform": platform})
+            .await
+    }
+
+    async fn username_owners(
+        &self,
+        username: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        self.usernames(doc! {"username": username, "platform": platform})
+            .await
+    }
+
+    async fn meta(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<Data>> {
+        let options = FindOneOptions::builder()
+            .sort(doc! {"retrieved_at": -1_i32})
+            .build();
+
+        let data_coll: Collection<Data> = self.collection("data");
+        Ok(data_coll
+            .find_one(
+                doc! {"id": platform_id,
+                    "platform": platform,
+                    "profile_picture": {"$exists": true}
+                },
+                options,
+            )
+            .await?)
+    }
+
+    async fn metas(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        until: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let mut filter = doc! {"id": platform_id,
+            "platform": platform,
+            "profile_picture": {"$exists": true}
+        };
+        if let Some(until) = until {
+            filter
+                .insert("retrieved_at", doc! {"$lte": bson::to_bson(&until)?});
+        }
+
+        let data_coll: Collection<Data> = self.collection("data");
+        let cursor = data_coll.find(filter, recent(limit)).await?;
+        let mut metas = collect(cursor).await?;
+        metas.reverse();
+        Ok(metas)
+    }
+
+    async fn latest_meta_excluding(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        added_by: &str,
+    ) -> StorageResult<Option<Data>> {
+        let options = FindOneOptions::builder()
+            .sort(doc! {"retrieved_at": -1_i32})
+            .build();
+
+        let data_coll: Collection<Data> = self.collection("data");
+        Ok(data_coll
+            .find_one(
+                doc! {"id": platform_id,
+                    "platform": platform,
+                    "profile_picture": {"$exists": true},
+                    "added_by": {"$ne": added_by}
+                },
+                options,
+            )
+            .await?)
+    }
+
+    async fn content(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let data_coll: Collection<Data> = self.collection("data");
+        let cursor = data_coll
+            .find(
+                doc! {"id": platform_id,
+                    "platform": platform,
+                    "content_type": {"$exists": true}
+                },
+                recent(limit),
+            )
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn content_with_ids(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        ids: &[(String, String)],
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let ids: Vec<Document> = ids
+            .iter()
+            .map(|(content_type, content_id)| {
+                doc! {"content_type": content_type, "content_id": content_id}
+            })
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let data_coll: Collection<Data> = self.collection("data");
+        let cursor = data_coll
+            .find(
+                doc! {"id": platform_id, "platform": platform, "$or": ids},
+                recent(limit),
+            )
+            .await?;
+        collect(cursor).await
+    }
+
+    async fn presence(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let mut filter = doc! {"id": platform_id,
+            "platform": platform,
+            "presence_type": {"$exists": true}
+        };
+        let mut retrieved_at = Document::new();
+        if let Some(from) = from {
+            retrieved_at.insert("$gte", bson::to_bson(&from)?);
+        }
+        if let Some(to) = to {
+            retrieved_at.insert("$lte", bson::to_bson(&to)?);
+        }
+        if !retrieved_at.is_empty() {
+            filter.insert("retrieved_at", retrieved_at);
+        }
+
+        let data_coll: Collection<Data> = self.collection("data");
+        let cursor = data_coll.find(filter, recent(limit)).await?;
+        collect(cursor).await
+    }
+
+    async fn drop(&self) -> StorageResult<()> {
+        Ok(self.db.drop(None).await?)
+    }
+}
+
+fn recent(limit: i64) -> FindOptions {
+    FindOptions::builder()
+        .limit(limit)
+        .sort(doc! {"retrieved_at": -1_i32})
+        .batch_size(100)
+        .build()
+}
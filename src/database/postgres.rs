@@ -0,0 +1,2207 @@
+//! PostgreSQL implementation of [`Storage`].
+//!
+//! Every MongoDB collection has an equivalent table. Data is kept as a JSONB
+//! document next to the handful of columns needed to index and query it, so
+//! that fields can still be added to [`Data`] without altering the table.
+
+use crate::config::PGIConfig;
+use crate::consensus::StatusClaim;
+use crate::data::{Data, Ingest};
+use crate::database::{
+    AppliedMigration, Migration, Storage, StorageError, StorageResult,
+};
+use crate::group::Group;
+use crate::metric::MetricPoint;
+use crate::relationship::Edge;
+use crate::reputation::Reputation;
+use crate::revision::ContentHistory;
+use crate::routes::invite::Referral;
+use crate::routes::leaderboard::Credit;
+use crate::routes::queue::{self, FailureReason, InternalQueueItem};
+use crate::routes::stats::QueueCounts;
+use crate::signature::Submission;
+use crate::subject::Subject;
+use crate::tombstone::Tombstone;
+use crate::user::User;
+use crate::username::UsernameRecord;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Client, Manager, Pool, PoolError};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::Json;
+use tokio_postgres::{NoTls, Row};
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create tables.",
+    },
+    Migration {
+        version: 2,
+        description: "Create content history table.",
+    },
+    Migration {
+        version: 3,
+        description: "Create username history table from metadata.",
+    },
+    Migration {
+        version: 4,
+        description: "Schedule queue items.",
+    },
+    Migration {
+        version: 5,
+        description: "Add queue lease expiry.",
+    },
+    Migration {
+        version: 6,
+        description: "Add queue failures and quarantine.",
+    },
+    Migration {
+        version: 7,
+        description: "Add queue item creation times.",
+    },
+    Migration {
+        version: 8,
+        description: "Create leaderboard table.",
+    },
+    Migration {
+        version: 9,
+        description: "Create reputation table.",
+    },
+    Migration {
+        version: 10,
+        description: "Create content status table.",
+    },
+    Migration {
+        version: 11,
+        description: "Add user public keys and create submission table.",
+    },
+    Migration {
+        version: 12,
+        description: "Create tombstone table.",
+    },
+    Migration {
+        version: 13,
+        description: "Create relationship edge table.",
+    },
+    Migration {
+        version: 14,
+        description: "Create metric table.",
+    },
+];
+
+// Created on connection, before any migration can be recorded.
+const MIGRATIONS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS migrations (
+    version BIGINT PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL
+);
+";
+
+const TABLES: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    uuid TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    key TEXT NOT NULL,
+    banned BOOLEAN NOT NULL
+);
+CREATE INDEX IF NOT EXISTS users_key ON users (key, banned);
+
+CREATE TABLE IF NOT EXISTS referrals (
+    code TEXT PRIMARY KEY,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    used BOOLEAN NOT NULL,
+    used_by TEXT
+);
+
+CREATE TABLE IF NOT EXISTS subjects (
+    uuid TEXT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL,
+    created_by TEXT NOT NULL,
+    name TEXT NOT NULL,
+    profiles JSONB NOT NULL,
+    description TEXT,
+    UNIQUE (created_by, name)
+);
+
+CREATE TABLE IF NOT EXISTS groups (
+    uuid TEXT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL,
+    created_by TEXT NOT NULL,
+    name TEXT NOT NULL,
+    subjects TEXT[] NOT NULL,
+    description TEXT
+);
+
+CREATE TABLE IF NOT EXISTS queue (
+    queue_id TEXT PRIMARY KEY,
+    platform_id TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    last_processed TIMESTAMPTZ NOT NULL,
+    lock_holder TEXT,
+    lock_acquired_at TIMESTAMPTZ,
+    \"references\" BIGINT NOT NULL,
+    confirmed_id BOOLEAN NOT NULL,
+    UNIQUE (platform, platform_id)
+);
+
+CREATE TABLE IF NOT EXISTS data (
+    kind TEXT NOT NULL,
+    id TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    content_type TEXT,
+    presence_type TEXT,
+    content_id TEXT,
+    username TEXT,
+    retrieved_at TIMESTAMPTZ NOT NULL,
+    document JSONB NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS data_unique_content
+    ON data (content_id, platform, content_type);
+CREATE INDEX IF NOT EXISTS data_profile ON data (id, platform, kind);
+";
+
+const HISTORIES_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS histories (
+    content_id TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    revisions JSONB NOT NULL,
+    PRIMARY KEY (content_id, platform, content_type)
+);
+";
+
+const USERNAMES_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS usernames (
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (platform, platform_id, username)
+);
+CREATE INDEX IF NOT EXISTS usernames_username ON usernames (platform, username);
+
+INSERT INTO usernames
+SELECT platform, id, username, MIN(retrieved_at), MAX(retrieved_at)
+FROM data WHERE kind = 'meta'
+GROUP BY platform, id, username
+ON CONFLICT (platform, platform_id, username) DO UPDATE SET
+    first_seen = LEAST(usernames.first_seen, EXCLUDED.first_seen),
+    last_seen = GREATEST(usernames.last_seen, EXCLUDED.last_seen);
+";
+
+// Existing items are due in the order they were last processed.
+const QUEUE_SCHEDULE: &str = "
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS due_at TIMESTAMPTZ;
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS hot_until TIMESTAMPTZ;
+UPDATE queue SET due_at = last_processed WHERE due_at IS NULL;
+ALTER TABLE queue ALTER COLUMN due_at SET NOT NULL;
+CREATE INDEX IF NOT EXISTS queue_due ON queue (platform, due_at)
+    WHERE lock_holder IS NULL;
+";
+
+// Existing locks keep the 30 second lease they were taken with.
+const QUEUE_LEASES: &str = "
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS lock_expires_at TIMESTAMPTZ;
+UPDATE queue SET lock_expires_at = lock_acquired_at + INTERVAL '30 seconds'
+    WHERE lock_holder IS NOT NULL AND lock_expires_at IS NULL;
+CREATE INDEX IF NOT EXISTS queue_lock_expiry ON queue (lock_expires_at)
+    WHERE lock_holder IS NOT NULL;
+";
+
+const QUEUE_FAILURES: &str = "
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS failures BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS last_failure JSONB;
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS quarantined_at TIMESTAMPTZ;
+";
+
+// Existing items are treated as created when the migration runs.
+const QUEUE_CREATED: &str = "
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL
+    DEFAULT NOW();
+";
+
+const CREDITS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS credits (
+    period TEXT NOT NULL,
+    user_uuid TEXT NOT NULL,
+    jobs BIGINT NOT NULL,
+    items BIGINT NOT NULL,
+    PRIMARY KEY (period, user_uuid)
+);
+";
+
+const REPUTATIONS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS reputations (
+    user_uuid TEXT PRIMARY KEY,
+    assessed BIGINT NOT NULL,
+    expected BIGINT NOT NULL,
+    provided BIGINT NOT NULL
+);
+";
+
+const STATUSES_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS statuses (
+    content_id TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    deleted BOOLEAN NOT NULL,
+    retrieved_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (content_id, platform, content_type, added_by)
+);
+CREATE INDEX IF NOT EXISTS statuses_profile ON statuses (platform, platform_id);
+";
+
+const SUBMISSIONS_TABLE: &str = "
+ALTER TABLE users ADD COLUMN IF NOT EXISTS public_key TEXT;
+
+CREATE TABLE IF NOT EXISTS submissions (
+    uuid TEXT PRIMARY KEY,
+    added_by TEXT NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    body TEXT NOT NULL
+);
+";
+
+const TOMBSTONES_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS tombstones (
+    content_id TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    suspected_at TIMESTAMPTZ NOT NULL,
+    suspected_by TEXT NOT NULL,
+    confirmed_at TIMESTAMPTZ,
+    confirmed_by TEXT,
+    PRIMARY KEY (content_id, platform, content_type)
+);
+CREATE INDEX IF NOT EXISTS tombstones_profile
+    ON tombstones (platform, platform_id);
+";
+
+const EDGES_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS edges (
+    platform TEXT NOT NULL,
+    from_id TEXT NOT NULL,
+    to_id TEXT NOT NULL,
+    relation_type TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    started_after TIMESTAMPTZ,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    last_snapshot TIMESTAMPTZ,
+    stopped_before TIMESTAMPTZ,
+    PRIMARY KEY (platform, from_id, relation_type, to_id, added_by,
+        first_seen)
+);
+CREATE INDEX IF NOT EXISTS edges_profile ON edges (platform, from_id);
+";
+
+const METRICS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS metrics (
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    content_id TEXT,
+    metric_type TEXT NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    retrieved_at TIMESTAMPTZ NOT NULL,
+    added_by TEXT
+);
+CREATE INDEX IF NOT EXISTS metrics_series
+    ON metrics (platform, platform_id, content_id, retrieved_at DESC);
+";
+
+const DROP_TABLES: &str = "
+DROP TABLE IF EXISTS migrations, users, referrals, subjects, groups, queue,
+    data, histories, usernames, credits, reputations, statuses, submissions,
+    tombstones, edges, metrics;
+";
+
+pub struct PGHandle {
+    pool: Pool,
+    schema: Option<String>,
+}
+
+impl PGHandle {
+    // Every call takes its own connection, so that requests aren't queued
+    // behind each other on a single one.
+    async fn client(&self) -> StorageResult<Client> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+impl From<tokio_postgres::Error> for StorageError {
+    fn from(error: tokio_postgres::Error) -> Self {
+        if error.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            Self::Duplicate
+        } else {
+            Self::Backend(Box::new(error))
+        }
+    }
+}
+
+impl From<PoolError> for StorageError {
+    fn from(error: PoolError) -> Self {
+        Self::Backend(Box::new(error))
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Backend(Box::new(error))
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+pub async fn open(
+    config: &PGIConfig,
+) -> Result<PGHandle, Box<dyn std::error::Error>> {
+    let mut pg_config = tokio_postgres::Config::new();
+    pg_config
+        .host(&config.hosts)
+        .port(config.port.parse()?)
+        .user(&config.user)
+        .password(&config.password)
+        .dbname(&config.database)
+        .connect_timeout(Duration::new(1, 0));
+    // Every connection in the pool has to look in the schema, not just the
+    // one that creates it.
+    if let Some(schema) = &config.schema {
+        pg_config
+            .options(&format!("-c search_path={}", quote_identifier(schema)));
+    }
+
+    let manager = Manager::new(pg_config, NoTls);
+    let mut pool = Pool::builder(manager);
+    if let Some(pool_size) = config.pool_size {
+        pool = pool.max_size(pool_size);
+    }
+    let pool = pool.build()?;
+
+    let client = pool.get().await?;
+    if let Some(schema) = &config.schema {
+        client
+            .batch_execute(&format!(
+                "CREATE SCHEMA IF NOT EXISTS {};",
+                quote_identifier(schema)
+            ))
+            .await?;
+    }
+
+    client.batch_execute(MIGRATIONS_TABLE).await?;
+
+    tracing::info!("Connected to PostgreSQL.");
+
+    Ok(PGHandle {
+        pool,
+        schema: config.schema.clone(),
+    })
+}
+
+fn applied_migration(row: &Row) -> AppliedMigration {
+    let version: i64 = row.get("version");
+    AppliedMigration {
+        version: version as u32,
+        description: row.get("description"),
+        applied_at: row.get("applied_at"),
+    }
+}
+
+fn content_history(row: &Row) -> ContentHistory {
+    let revisions: Json<_> = row.get("revisions");
+    ContentHistory {
+        content_id: row.get("content_id"),
+        platform: row.get("platform"),
+        content_type: row.get("content_type"),
+        revisions: revisions.0,
+    }
+}
+
+fn status_claim(row: &Row) -> StatusClaim {
+    StatusClaim {
+        content_id: row.get("content_id"),
+        platform: row.get("platform"),
+        content_type: row.get("content_type"),
+        platform_id: row.get("platform_id"),
+        added_by: row.get("added_by"),
+        deleted: row.get("deleted"),
+        retrieved_at: row.get("retrieved_at"),
+    }
+}
+
+fn tombstone(row: &Row) -> Tombstone {
+    Tombstone {
+        content_id: row.get("content_id"),
+        platform: row.get("platform"),
+        content_type: row.get("content_type"),
+        platform_id: row.get("platform_id"),
+        suspected_at: row.get("suspected_at"),
+        suspected_by: row.get("suspected_by"),
+        confirmed_at: row.get("confirmed_at"),
+        confirmed_by: row.get("confirmed_by"),
+    }
+}
+
+fn edge(row: &Row) -> Edge {
+    Edge {
+        platform: row.get("platform"),
+        from_id: row.get("from_id"),
+        to_id: row.get("to_id"),
+        relation_type: row.get("relation_type"),
+        added_by: row.get("added_by"),
+        started_after: row.get("started_after"),
+        first_seen: row.get("first_seen"),
+        last_seen: row.get("last_seen"),
+        last_snapshot: row.get("last_snapshot"),
+        stopped_before: row.get("stopped_before"),
+    }
+}
+
+fn metric_point(row: &Row) -> MetricPoint {
+    MetricPoint {
+        platform: row.get("platform"),
+        platform_id: row.get("platform_id"),
+        content_id: row.get("content_id"),
+        metric_type: row.get("metric_type"),
+        value: row.get("value"),
+        retrieved_at: row.get("retrieved_at"),
+        added_by: row.get("added_by"),
+    }
+}
+
+fn username_record(row: &Row) -> UsernameRecord {
+    UsernameRecord {
+        platform: row.get("platform"),
+        platform_id: row.get("platform_id"),
+        username: row.get("username"),
+        first_seen: row.get("first_seen"),
+        last_seen: row.get("last_seen"),
+    }
+}
+
+fn user(row: &Row) -> User {
+    User {
+        uuid: row.get("uuid"),
+        name: row.get("name"),
+        key: row.get("key"),
+        banned: row.get("banned"),
+        public_key: row.get("public_key"),
+    }
+}
+
+fn submission(row: &Row) -> Submission {
+    Submission {
+        uuid: row.get("uuid"),
+        added_by: row.get("added_by"),
+        added_at: row.get("added_at"),
+        public_key: row.get("public_key"),
+        signature: row.get("signature"),
+        body: row.get("body"),
+    }
+}
+
+fn referral(row: &Row) -> Referral {
+    Referral {
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        code: row.get("code"),
+        used: row.get("used"),
+        used_by: row.get("used_by"),
+    }
+}
+
+fn subject(row: &Row) -> Subject {
+    let profiles: Json<HashMap<String, Vec<String>>> = row.get("profiles");
+    Subject {
+        uuid: row.get("uuid"),
+        created_at: row.get("created_at"),
+        created_by: row.get("created_by"),
+        name: row.get("name"),
+        profiles: profiles.0,
+        description: row.get("description"),
+    }
+}
+
+fn group(row: &Row) -> Group {
+    Group {
+        uuid: row.get("uuid"),
+        created_at: row.get("created_at"),
+        created_by: row.get("created_by"),
+        name: row.get("name"),
+        subjects: row.get("subjects"),
+        description: row.get("description"),
+    }
+}
+
+fn queue_item(row: &Row) -> InternalQueueItem {
+    let references: i64 = row.get("references");
+    let failures: i64 = row.get("failures");
+    let last_failure: Option<Json<FailureReason>> = row.get("last_failure");
+    InternalQueueItem {
+        queue_id: row.get("queue_id"),
+        platform_id: row.get("platform_id"),
+        platform: row.get("platform"),
+        created_at: row.get("created_at"),
+        last_processed: row.get("last_processed"),
+        due_at: row.get("due_at"),
+        hot_until: row.get("hot_until"),
+        lock_holder: row.get("lock_holder"),
+        lock_acquired_at: row.get("lock_acquired_at"),
+        lock_expires_at: row.get("lock_expires_at"),
+        references: references as u64,
+        confirmed_id: row.get("confirmed_id"),
+        failures: failures as u32,
+        last_failure: last_failure.map(|l| l.0),
+        quarantined_at: row.get("quarantined_at"),
+    }
+}
+
+fn credit(row: &Row) -> Credit {
+    let jobs: i64 = row.get("jobs");
+    let items: i64 = row.get("items");
+    Credit {
+        period: row.get("period"),
+        user: row.get("user_uuid"),
+        jobs: jobs as u64,
+        items: items as u64,
+    }
+}
+
+fn queue_counts(row: &Row) -> QueueCounts {
+    let count = |column| row.get::<_, i64>(column) as u64;
+    QueueCounts {
+        platform: row.get("platform"),
+        total: count("total"),
+        locked: count("locked"),
+        unconfirmed: count("unconfirmed"),
+        quarantined: count("quarantined"),
+        never_processed: count("never_processed"),
+    }
+}
+
+fn reputation(row: &Row) -> Reputation {
+    let assessed: i64 = row.get("assessed");
+    let expected: i64 = row.get("expected");
+    let provided: i64 = row.get("provided");
+    Reputation {
+        user: row.get("user_uuid"),
+        assessed: assessed as u64,
+        expected: expected as u64,
+        provided: provided as u64,
+    }
+}
+
+fn data(row: &Row) -> Data {
+    let document: Json<Data> = row.get("document");
+    document.0
+}
+
+#[async_trait]
+impl Storage for PGHandle {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn applied_migrations(&self) -> StorageResult<Vec<AppliedMigration>> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT * FROM migrations ORDER BY version", &[])
+            .await?;
+        Ok(rows.iter().map(applied_migration).collect())
+    }
+
+    async fn apply_migration(
+        &self,
+        migration: &Migration,
+    ) -> StorageResult<()> {
+        let sql = match migration.version {
+            1 => TABLES,
+            2 => HISTORIES_TABLE,
+            3 => USERNAMES_TABLE,
+            4 => QUEUE_SCHEDULE,
+            5 => QUEUE_LEASES,
+            6 => QUEUE_FAILURES,
+            7 => QUEUE_CREATED,
+            8 => CREDITS_TABLE,
+            9 => REPUTATIONS_TABLE,
+            10 => STATUSES_TABLE,
+            11 => SUBMISSIONS_TABLE,
+            12 => TOMBSTONES_TABLE,
+            13 => EDGES_TABLE,
+            14 => METRICS_TABLE,
+            v => {
+                return Err(StorageError::Backend(
+                    format!("Unknown migration {v}.").into(),
+                ))
+            }
+        };
+        self.client().await?.batch_execute(sql).await?;
+
+        let applied = AppliedMigration::new(migration);
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO migrations (version, description, applied_at)
+                VALUES ($1, $2, $3) ON CONFLICT (version) DO NOTHING",
+                &[
+                    &i64::from(applied.version),
+                    &applied.description,
+                    &applied.applied_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn count_users(&self) -> StorageResult<u64> {
+        let row = self
+            .client()
+            .await?
+            .query_one("SELECT COUNT(*) FROM users", &[])
+            .await?;
+        let count: i64 = row.get(0);
+        Ok(count as u64)
+    }
+
+    async fn insert_user(&self, user: &User) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO users (uuid, name, key, banned)
+                VALUES ($1, $2, $3, $4)",
+                &[&user.uuid, &user.name, &user.key, &user.banned],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn user_with_key(&self, key: &str) -> StorageResult<Option<User>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM users WHERE key = $1", &[&key])
+            .await?;
+        Ok(row.as_ref().map(user))
+    }
+
+    async fn user_with_name(&self, name: &str) -> StorageResult<Option<User>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM users WHERE name = $1 LIMIT 1", &[&name])
+            .await?;
+        Ok(row.as_ref().map(user))
+    }
+
+    async fn users(&self, uuids: &[String]) -> StorageResult<Vec<User>> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT * FROM users WHERE uuid = ANY($1)", &[&uuids])
+            .await?;
+        Ok(rows.iter().map(user).collect())
+    }
+
+    async fn reset_key(
+        &self,
+        key: &str,
+        new_key: &str,
+    ) -> StorageResult<Option<User>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "UPDATE users SET key = $2 WHERE key = $1 RETURNING *",
+                &[&key, &new_key],
+            )
+            .await?;
+        Ok(row.as_ref().map(|r| User {
+            key: key.to_string(),
+            ..user(r)
+        }))
+    }
+
+    async fn set_public_key(
+        &self,
+        key: &str,
+        public_key: Option<&str>,
+    ) -> StorageResult<Option<User>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "UPDATE users u SET public_key = $2 FROM users old
+                WHERE u.key = $1 AND old.uuid = u.uuid RETURNING old.*",
+                &[&key, &public_key],
+            )
+            .await?;
+        Ok(row.as_ref().map(user))
+    }
+
+    async fn insert_referral(&self, referral: &Referral) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO referrals
+                (code, created_by, created_at, used, used_by)
+                VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &referral.code,
+                    &referral.created_by,
+                    &referral.created_at,
+                    &referral.used,
+                    &referral.used_by,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn unused_referral(
+        &self,
+        code: &str,
+    ) -> StorageResult<Option<Referral>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT * FROM referrals WHERE code = $1 AND NOT used",
+                &[&code],
+            )
+            .await?;
+        Ok(row.as_ref().map(referral))
+    }
+
+    async fn use_referral(
+        &self,
+        code: &str,
+        used_by: &str,
+    ) -> StorageResult<Option<Referral>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "UPDATE referrals SET used = TRUE, used_by = $2
+                WHERE code = $1 AND NOT used RETURNING *",
+                &[&code, &used_by],
+            )
+            .await?;
+        Ok(row.as_ref().map(referral))
+    }
+
+    async fn insert_subject(&self, subject: &Subject) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO subjects
+                (uuid, created_at, created_by, name, profiles, description)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &subject.uuid,
+                    &subject.created_at,
+                    &subject.created_by,
+                    &subject.name,
+                    &Json(&subject.profiles),
+                    &subject.description,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn subject(&self, uuid: &str) -> StorageResult<Option<Subject>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM subjects WHERE uuid = $1", &[&uuid])
+            .await?;
+        Ok(row.as_ref().map(subject))
+    }
+
+    async fn subjects(&self, uuids: &[String]) -> StorageResult<Vec<Subject>> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT * FROM subjects WHERE uuid = ANY($1)", &[&uuids])
+            .await?;
+        Ok(rows.iter().map(subject).collect())
+    }
+
+    async fn subjects_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM subjects WHERE created_by = $1
+                ORDER BY created_at",
+                &[&created_by],
+            )
+            .await?;
+        Ok(rows.iter().map(subject).collect())
+    }
+
+    async fn subjects_with_profile(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Subject>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM subjects WHERE profiles -> $1 ? $2
+                ORDER BY created_at",
+                &[&platform, &platform_id],
+            )
+            .await?;
+        Ok(rows.iter().map(subject).collect())
+    }
+
+    async fn update_subject(&self, subject: &Subject) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE subjects SET name = $2, profiles = $3, description = $4
+                WHERE uuid = $1",
+                &[
+                    &subject.uuid,
+                    &subject.name,
+                    &Json(&subject.profiles),
+                    &subject.description,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_subject(&self, uuid: &str) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute("DELETE FROM subjects WHERE uuid = $1", &[&uuid])
+            .await?;
+        Ok(())
+    }
+
+    async fn replace_profile_id(
+        &self,
+        platform: &str,
+        old_id: &str,
+        new_id: &str,
+    ) -> StorageResult<()> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT * FROM subjects WHERE profiles -> $1 ? $2 LIMIT 1",
+                &[&platform, &old_id],
+            )
+            .await?;
+        if let Some(row) = row {
+            let mut subject = subject(&row);
+            if let Some(ids) = subject.profiles.get_mut(platform) {
+                if let Some(id) = ids.iter_mut().find(|id| *id == old_id) {
+                    *id = new_id.to_string();
+                }
+            }
+            self.update_subject(&subject).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &Group) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO groups
+                (uuid, created_at, created_by, name, subjects, description)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &group.uuid,
+                    &group.created_at,
+                    &group.created_by,
+                    &group.name,
+                    &group.subjects,
+                    &group.description,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn group(&self, uuid: &str) -> StorageResult<Option<Group>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM groups WHERE uuid = $1", &[&uuid])
+            .await?;
+        Ok(row.as_ref().map(group))
+    }
+
+    async fn groups_created_by(
+        &self,
+        created_by: &str,
+    ) -> StorageResult<Vec<Group>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM groups WHERE created_by = $1
+                ORDER BY created_at",
+                &[&created_by],
+            )
+            .await?;
+        Ok(rows.iter().map(group).collect())
+    }
+
+    async fn update_group(&self, group: &Group) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE groups SET name = $2, subjects = $3, description = $4
+                WHERE uuid = $1",
+                &[
+                    &group.uuid,
+                    &group.name,
+                    &group.subjects,
+                    &group.description,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_group(&self, uuid: &str) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute("DELETE FROM groups WHERE uuid = $1", &[&uuid])
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_subject_from_groups(
+        &self,
+        subject_uuid: &str,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE groups SET subjects = array_remove(subjects, $1)
+                WHERE $1 = ANY(subjects)",
+                &[&subject_uuid],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn add_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        confirmed_id: bool,
+    ) -> StorageResult<()> {
+        let q_item = InternalQueueItem::new(
+            platform_id.to_string(),
+            platform.to_string(),
+        );
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO queue
+                (queue_id, platform_id, platform, created_at, last_processed,
+                due_at, \"references\", confirmed_id)
+                VALUES ($1, $2, $3, $7, $4, $6, 1, FALSE)
+                ON CONFLICT (platform, platform_id) DO UPDATE
+                SET \"references\" = queue.\"references\" + 1,
+                confirmed_id = queue.confirmed_id OR $5",
+                &[
+                    &q_item.queue_id,
+                    &q_item.platform_id,
+                    &q_item.platform,
+                    &q_item.last_processed,
+                    &confirmed_id,
+                    &q_item.due_at,
+                    &q_item.created_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_queue_item(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<()> {
+        let deleted = self
+            .client()
+            .await?
+            .execute(
+                "DELETE FROM queue WHERE platform_id = $1 AND platform = $2
+                AND \"references\" = 1",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        if deleted == 0 {
+            self.client()
+                .await?
+                .execute(
+                    "UPDATE queue SET \"references\" = \"references\" - 1
+                    WHERE platform_id = $1 AND platform = $2",
+                    &[&platform_id, &platform],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn queue_item(
+        &self,
+        queue_id: &str,
+    ) -> StorageResult<Option<InternalQueueItem>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM queue WHERE queue_id = $1", &[&queue_id])
+            .await?;
+        Ok(row.as_ref().map(queue_item))
+    }
+
+    async fn lock_queue_items(
+        &self,
+        leases: &HashMap<String, DateTime<Utc>>,
+        lock_holder: &str,
+        count: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let (platforms, expiries): (Vec<&String>, Vec<&DateTime<Utc>>) =
+            leases.iter().unzip();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "UPDATE queue SET lock_holder = $3, lock_acquired_at = $4,
+                lock_expires_at = leases.expires_at
+                FROM UNNEST($1::TEXT[], $2::TIMESTAMPTZ[])
+                    AS leases (platform, expires_at)
+                WHERE queue.platform = leases.platform AND queue_id IN (
+                    SELECT queue_id FROM queue
+                    WHERE lock_holder IS NULL AND quarantined_at IS NULL
+                    AND platform = ANY($1) AND due_at <= $4
+                    ORDER BY due_at
+                    LIMIT $5
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING queue.*",
+                &[
+                    &platforms,
+                    &expiries,
+                    &lock_holder,
+                    &Utc::now(),
+                    &(count as i64),
+                ],
+            )
+            .await?;
+        let mut q_items: Vec<InternalQueueItem> =
+            rows.iter().map(queue_item).collect();
+        q_items.sort_by_key(|q| q.due_at);
+        Ok(q_items)
+    }
+
+    async fn complete_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        hot_due_at: DateTime<Utc>,
+        cold_due_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET lock_holder = NULL, lock_acquired_at = NULL,
+                lock_expires_at = NULL, last_processed = $3,
+                failures = 0, last_failure = NULL,
+                due_at = CASE WHEN hot_until > $3
+                    THEN $4::TIMESTAMPTZ ELSE $5::TIMESTAMPTZ END
+                WHERE queue_id = $1 AND lock_holder = $2",
+                &[
+                    &queue_id,
+                    &lock_holder,
+                    &Utc::now(),
+                    &hot_due_at,
+                    &cold_due_at,
+                ],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn extend_lock(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        expires_at: DateTime<Utc>,
+    ) -> StorageResult<bool> {
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET lock_expires_at = $3
+                WHERE queue_id = $1 AND lock_holder = $2",
+                &[&queue_id, &lock_holder, &expires_at],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn release_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+    ) -> StorageResult<bool> {
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET lock_holder = NULL, lock_acquired_at = NULL,
+                lock_expires_at = NULL
+                WHERE queue_id = $1 AND lock_holder = $2",
+                &[&queue_id, &lock_holder],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn fail_queue_item(
+        &self,
+        queue_id: &str,
+        lock_holder: &str,
+        reason: FailureReason,
+        due_at: DateTime<Utc>,
+        quarantine: bool,
+    ) -> StorageResult<bool> {
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET lock_holder = NULL, lock_acquired_at = NULL,
+                lock_expires_at = NULL, due_at = $3,
+                failures = failures + 1, last_failure = $4,
+                quarantined_at = CASE WHEN $5 THEN $6::TIMESTAMPTZ END
+                WHERE queue_id = $1 AND lock_holder = $2",
+                &[
+                    &queue_id,
+                    &lock_holder,
+                    &due_at,
+                    &Json(reason),
+                    &quarantine,
+                    &Utc::now(),
+                ],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let (platforms, platform_ids): (Vec<&String>, Vec<&String>) = profiles
+            .iter()
+            .flat_map(|(platform, ids)| {
+                ids.iter().map(move |id| (platform, id))
+            })
+            .unzip();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM queue WHERE (platform, platform_id) IN (
+                    SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[])
+                )",
+                &[&platforms, &platform_ids],
+            )
+            .await?;
+        Ok(rows.iter().map(queue_item).collect())
+    }
+
+    async fn queue_counts(&self) -> StorageResult<Vec<QueueCounts>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT platform, COUNT(*) AS total,
+                COUNT(lock_holder) AS locked,
+                COUNT(*) FILTER (WHERE NOT confirmed_id) AS unconfirmed,
+                COUNT(quarantined_at) AS quarantined,
+                COUNT(*) FILTER (WHERE last_processed <= $1)
+                    AS never_processed
+                FROM queue GROUP BY platform ORDER BY platform",
+                &[&queue::never()],
+            )
+            .await?;
+        Ok(rows.iter().map(queue_counts).collect())
+    }
+
+    async fn nth_processed(
+        &self,
+        platform: &str,
+        n: u64,
+    ) -> StorageResult<Option<DateTime<Utc>>> {
+        if n == 0 {
+            return Ok(None);
+        }
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT last_processed FROM queue
+                WHERE platform = $1 AND last_processed > $2
+                ORDER BY last_processed DESC OFFSET $3 LIMIT 1",
+                &[&platform, &queue::never(), &(n as i64 - 1)],
+            )
+            .await?;
+        Ok(row.map(|row| row.get("last_processed")))
+    }
+
+    async fn unprocessed_queue_items(
+        &self,
+        platform: &str,
+        limit: usize,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM queue
+                WHERE platform = $1 AND last_processed <= $2
+                ORDER BY created_at LIMIT $3",
+                &[&platform, &queue::never(), &(limit as i64)],
+            )
+            .await?;
+        Ok(rows.iter().map(queue_item).collect())
+    }
+
+    async fn quarantined_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+    ) -> StorageResult<Vec<InternalQueueItem>> {
+        let (platforms, platform_ids): (Vec<&String>, Vec<&String>) = profiles
+            .iter()
+            .flat_map(|(platform, ids)| {
+                ids.iter().map(move |id| (platform, id))
+            })
+            .unzip();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM queue WHERE quarantined_at IS NOT NULL
+                AND (platform, platform_id) IN (
+                    SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[])
+                )
+                ORDER BY quarantined_at",
+                &[&platforms, &platform_ids],
+            )
+            .await?;
+        Ok(rows.iter().map(queue_item).collect())
+    }
+
+    async fn restore_queue_item(&self, queue_id: &str) -> StorageResult<bool> {
+        let updated = self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET quarantined_at = NULL, failures = 0,
+                last_failure = NULL, due_at = $2
+                WHERE queue_id = $1 AND quarantined_at IS NOT NULL",
+                &[&queue_id, &Utc::now()],
+            )
+            .await?;
+        Ok(updated == 1)
+    }
+
+    async fn copy_queue_schedule(
+        &self,
+        from: &InternalQueueItem,
+        platform_id: &str,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE queue SET last_processed = $3, due_at = $4,
+                hot_until = $5
+                WHERE platform_id = $1 AND platform = $2",
+                &[
+                    &platform_id,
+                    &from.platform,
+                    &from.last_processed,
+                    &from.due_at,
+                    &from.hot_until,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn promote_queue_items(
+        &self,
+        profiles: &HashMap<String, Vec<String>>,
+        hot_until: DateTime<Utc>,
+        hot_interval: chrono::Duration,
+    ) -> StorageResult<()> {
+        let (platforms, platform_ids): (Vec<&String>, Vec<&String>) = profiles
+            .iter()
+            .flat_map(|(platform, ids)| {
+                ids.iter().map(move |id| (platform, id))
+            })
+            .unzip();
+        let hot_interval = hot_interval.num_milliseconds() as f64 / 1000.0;
+        self.client()
+            .await?
+            .execute(
+                "UPDATE queue SET hot_until = GREATEST(hot_until, $3),
+                due_at = CASE WHEN failures = 0 THEN LEAST(
+                    due_at, last_processed + $4 * INTERVAL '1 second'
+                ) ELSE due_at END
+                WHERE (platform, platform_id) IN (
+                    SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[])
+                )",
+                &[&platforms, &platform_ids, &hot_until, &hot_interval],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn expire_locks(&self, now: DateTime<Utc>) -> StorageResult<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                "UPDATE queue SET lock_holder = NULL, lock_acquired_at = NULL,
+                lock_expires_at = NULL
+                WHERE lock_expires_at < $1",
+                &[&now],
+            )
+            .await?)
+    }
+
+    async fn record_credit(
+        &self,
+        period: &str,
+        user: &str,
+        jobs: u64,
+        items: u64,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO credits (period, user_uuid, jobs, items)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (period, user_uuid) DO UPDATE
+                SET jobs = credits.jobs + $3, items = credits.items + $4",
+                &[&period, &user, &(jobs as i64), &(items as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn credits(&self, period: &str) -> StorageResult<Vec<Credit>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM credits WHERE period = $1
+                ORDER BY jobs DESC, items DESC",
+                &[&period],
+            )
+            .await?;
+        Ok(rows.iter().map(credit).collect())
+    }
+
+    async fn credit_periods(&self) -> StorageResult<Vec<String>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT DISTINCT period FROM credits ORDER BY period DESC",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get("period")).collect())
+    }
+
+    async fn record_reputation(
+        &self,
+        user: &str,
+        expected: u64,
+        provided: u64,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO reputations
+                (user_uuid, assessed, expected, provided)
+                VALUES ($1, 1, $2, $3)
+                ON CONFLICT (user_uuid) DO UPDATE
+                SET assessed = reputations.assessed + 1,
+                expected = reputations.expected + $2,
+                provided = reputations.provided + $3",
+                &[&user, &(expected as i64), &(provided as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn reputations(
+        &self,
+        users: &[String],
+    ) -> StorageResult<Vec<Reputation>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM reputations WHERE user_uuid = ANY($1)",
+                &[&users],
+            )
+            .await?;
+        Ok(rows.iter().map(reputation).collect())
+    }
+
+    async fn insert_submission(
+        &self,
+        submission: &Submission,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO submissions VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &submission.uuid,
+                    &submission.added_by,
+                    &submission.added_at,
+                    &submission.public_key,
+                    &submission.signature,
+                    &submission.body,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn submission(
+        &self,
+        uuid: &str,
+    ) -> StorageResult<Option<Submission>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt("SELECT * FROM submissions WHERE uuid = $1", &[&uuid])
+            .await?;
+        Ok(row.as_ref().map(submission))
+    }
+
+    async fn insert_data(&self, data: &[Data]) -> StorageResult<()> {
+        let mut kinds = Vec::new();
+        let mut ids = Vec::new();
+        let mut platforms = Vec::new();
+        let mut content_types = Vec::new();
+        let mut presence_types = Vec::new();
+        let mut content_ids = Vec::new();
+        let mut usernames = Vec::new();
+        let mut retrieved_ats = Vec::new();
+        let mut documents = Vec::new();
+        for d in data {
+            let (kind, id, platform, retrieved_at) = match d {
+                Data::Presence {
+                    id,
+                    platform,
+                    retrieved_at,
+                    ..
+                } => ("presence", id, platform, retrieved_at),
+                Data::Content {
+                    id,
+                    platform,
+                    retrieved_at,
+                    ..
+                } => ("content", id, platform, retrieved_at),
+                Data::Meta {
+                    id,
+                    platform,
+                    retrieved_at,
+                    ..
+                } => ("meta", id, platform, retrieved_at),
+                Data::Relationship {
+                    from_id,
+                    platform,
+                    observed_at,
+                    ..
+                } => ("relationship", from_id, platform, observed_at),
+                Data::Metric {
+                    id,
+                    platform,
+                    retrieved_at,
+                    ..
+                } => ("metric", id, platform, retrieved_at),
+            };
+            kinds.push(kind);
+            ids.push(id.as_str());
+            platforms.push(platform.as_str());
+            retrieved_ats.push(*retrieved_at);
+            content_types.push(match d {
+                Data::Content { content_type, .. } => Some(content_type),
+                _ => None,
+            });
+            presence_types.push(match d {
+                Data::Presence { presence_type, .. } => Some(presence_type),
+                _ => None,
+            });
+            content_ids.push(match d {
+                Data::Content { content_id, .. } => Some(content_id),
+                _ => None,
+            });
+            usernames.push(match d {
+                Data::Meta { username, .. } => Some(username),
+                _ => None,
+            });
+            documents.push(serde_json::to_value(d)?);
+        }
+
+        // A single statement so that the insert is all or nothing.
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO data
+                (kind, id, platform, content_type, presence_type, content_id,
+                username, retrieved_at, document)
+                SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[],
+                $4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TEXT[],
+                $8::TIMESTAMPTZ[], $9::JSONB[])",
+                &[
+                    &kinds,
+                    &ids,
+                    &platforms,
+                    &content_types,
+                    &presence_types,
+                    &content_ids,
+                    &usernames,
+                    &retrieved_ats,
+                    &documents,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn merge_content(&self, content: &Data) -> StorageResult<Ingest> {
+        let (content_id, platform, content_type) = match content {
+            Data::Content {
+                content_id,
+                platform,
+                content_type,
+                ..
+            } => (content_id, platform, content_type),
+            _ => return Err(StorageError::Backend("Expected content.".into())),
+        };
+
+        // Compare and swap, starting over if the content is inserted or
+        // changed by someone else between being read and written.
+        loop {
+            let row = self
+                .client()
+                .await?
+                .query_opt(
+                    "SELECT document FROM data
+                    WHERE content_id = $1 AND platform = $2
+                    AND content_type = $3",
+                    &[content_id, platform, content_type],
+                )
+                .await?;
+
+            let row = match row {
+                Some(row) => row,
+                None => {
+                    match self.insert_data(std::slice::from_ref(content)).await
+                    {
+                        Ok(()) => return Ok(Ingest::Inserted),
+                        Err(StorageError::Duplicate) => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+            };
+
+            let merged = match data(&row).merge(content) {
+                Some(merged) => merged,
+                None => return Ok(Ingest::Unchanged),
+            };
+            let document: serde_json::Value = row.get("document");
+            let updated = self
+                .client()
+                .await?
+                .execute(
+                    "UPDATE data SET document = $4
+                    WHERE content_id = $1 AND platform = $2
+                    AND content_type = $3 AND document = $5",
+                    &[
+                        content_id,
+                        platform,
+                        content_type,
+                        &serde_json::to_value(&merged)?,
+                        &document,
+                    ],
+                )
+                .await?;
+            if updated == 1 {
+                return Ok(Ingest::Merged);
+            }
+        }
+    }
+
+    async fn add_revision(&self, content: &Data) -> StorageResult<()> {
+        let history = match ContentHistory::new(content) {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        let content_id = &history.content_id;
+        let platform = &history.platform;
+        let content_type = &history.content_type;
+
+        // Compare and swap, as in merge_content.
+        loop {
+            let row = self
+                .client()
+                .await?
+                .query_opt(
+                    "SELECT revisions FROM histories
+                    WHERE content_id = $1 AND platform = $2
+                    AND content_type = $3",
+                    &[content_id, platform, content_type],
+                )
+                .await?;
+
+            let updated = match row {
+                Some(row) => {
+                    let revisions: serde_json::Value = row.get("revisions");
+                    let mut existing = history.clone();
+                    existing.revisions =
+                        serde_json::from_value(revisions.clone())?;
+                    existing.observe(history.revisions[0].clone());
+                    self.client()
+                        .await?
+                        .execute(
+                            "UPDATE histories SET revisions = $4
+                            WHERE content_id = $1 AND platform = $2
+                            AND content_type = $3 AND revisions = $5",
+                            &[
+                                content_id,
+                                platform,
+                                content_type,
+                                &serde_json::to_value(&existing.revisions)?,
+                                &revisions,
+                            ],
+                        )
+                        .await?
+                }
+                None => {
+                    self.client()
+                        .await?
+                        .execute(
+                            "INSERT INTO histories
+                            (content_id, platform, content_type, revisions)
+                            VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+                            &[
+                                content_id,
+                                platform,
+                                content_type,
+                                &serde_json::to_value(&history.revisions)?,
+                            ],
+                        )
+                        .await?
+                }
+            };
+            if updated == 1 {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn content_history(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<Option<ContentHistory>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT * FROM histories
+                WHERE content_id = $1 AND platform = $2 AND content_type = $3",
+                &[&content_id, &platform, &content_type],
+            )
+            .await?;
+        Ok(row.as_ref().map(content_history))
+    }
+
+    async fn record_status(&self, claim: &StatusClaim) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO statuses VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (content_id, platform, content_type, added_by)
+                DO UPDATE SET deleted = EXCLUDED.deleted,
+                retrieved_at = EXCLUDED.retrieved_at
+                WHERE statuses.retrieved_at <= EXCLUDED.retrieved_at",
+                &[
+                    &claim.content_id,
+                    &claim.platform,
+                    &claim.content_type,
+                    &claim.added_by,
+                    &claim.platform_id,
+                    &claim.deleted,
+                    &claim.retrieved_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn statuses(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<StatusClaim>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM statuses
+                WHERE platform_id = $1 AND platform = $2",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        Ok(rows.iter().map(status_claim).collect())
+    }
+
+    async fn record_tombstone(
+        &self,
+        tombstone: &Tombstone,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO tombstones VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (content_id, platform, content_type)
+                DO UPDATE SET platform_id = EXCLUDED.platform_id,
+                suspected_at = EXCLUDED.suspected_at,
+                suspected_by = EXCLUDED.suspected_by,
+                confirmed_at = EXCLUDED.confirmed_at,
+                confirmed_by = EXCLUDED.confirmed_by",
+                &[
+                    &tombstone.content_id,
+                    &tombstone.platform,
+                    &tombstone.content_type,
+                    &tombstone.platform_id,
+                    &tombstone.suspected_at,
+                    &tombstone.suspected_by,
+                    &tombstone.confirmed_at,
+                    &tombstone.confirmed_by,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_tombstone(
+        &self,
+        content_id: &str,
+        platform: &str,
+        content_type: &str,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM tombstones
+                WHERE content_id = $1 AND platform = $2 AND content_type = $3",
+                &[&content_id, &platform, &content_type],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn tombstones(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Tombstone>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM tombstones
+                WHERE platform_id = $1 AND platform = $2",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        Ok(rows.iter().map(tombstone).collect())
+    }
+
+    async fn record_edges(&self, edges: &[Edge]) -> StorageResult<()> {
+        for edge in edges {
+            self.client()
+                .await?
+                .execute(
+                    "INSERT INTO edges
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                    ON CONFLICT (platform, from_id, relation_type, to_id,
+                    added_by, first_seen)
+                    DO UPDATE SET started_after = EXCLUDED.started_after,
+                    last_seen = EXCLUDED.last_seen,
+                    last_snapshot = EXCLUDED.last_snapshot,
+                    stopped_before = EXCLUDED.stopped_before",
+                    &[
+                        &edge.platform,
+                        &edge.from_id,
+                        &edge.to_id,
+                        &edge.relation_type,
+                        &edge.added_by,
+                        &edge.started_after,
+                        &edge.first_seen,
+                        &edge.last_seen,
+                        &edge.last_snapshot,
+                        &edge.stopped_before,
+                    ],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn edges(
+        &self,
+        from_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<Edge>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM edges WHERE from_id = $1 AND platform = $2",
+                &[&from_id, &platform],
+            )
+            .await?;
+        Ok(rows.iter().map(edge).collect())
+    }
+
+    async fn record_metrics(
+        &self,
+        points: &[MetricPoint],
+    ) -> StorageResult<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        let platforms: Vec<&String> =
+            points.iter().map(|p| &p.platform).collect();
+        let platform_ids: Vec<&String> =
+            points.iter().map(|p| &p.platform_id).collect();
+        let content_ids: Vec<&Option<String>> =
+            points.iter().map(|p| &p.content_id).collect();
+        let metric_types: Vec<&String> =
+            points.iter().map(|p| &p.metric_type).collect();
+        let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+        let retrieved_ats: Vec<DateTime<Utc>> =
+            points.iter().map(|p| p.retrieved_at).collect();
+        let added_bys: Vec<&Option<String>> =
+            points.iter().map(|p| &p.added_by).collect();
+
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO metrics
+                SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[],
+                $4::TEXT[], $5::FLOAT8[], $6::TIMESTAMPTZ[], $7::TEXT[])",
+                &[
+                    &platforms,
+                    &platform_ids,
+                    &content_ids,
+                    &metric_types,
+                    &values,
+                    &retrieved_ats,
+                    &added_bys,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn metrics(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        content_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<MetricPoint>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM metrics
+                WHERE platform_id = $1 AND platform = $2
+                AND content_id IS NOT DISTINCT FROM $3
+                AND ($4::TIMESTAMPTZ IS NULL OR retrieved_at >= $4)
+                AND ($5::TIMESTAMPTZ IS NULL OR retrieved_at <= $5)
+                ORDER BY retrieved_at DESC
+                LIMIT $6",
+                &[&platform_id, &platform, &content_id, &from, &to, &limit],
+            )
+            .await?;
+        Ok(rows.iter().map(metric_point).collect())
+    }
+
+    async fn record_username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        username: &str,
+        retrieved_at: DateTime<Utc>,
+    ) -> StorageResult<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO usernames VALUES ($1, $2, $3, $4, $4)
+                ON CONFLICT (platform, platform_id, username) DO UPDATE SET
+                first_seen = LEAST(usernames.first_seen, EXCLUDED.first_seen),
+                last_seen = GREATEST(usernames.last_seen, EXCLUDED.last_seen)",
+                &[&platform, &platform_id, &username, &retrieved_at],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn username(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<String>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT username FROM usernames
+                WHERE platform_id = $1 AND platform = $2
+                ORDER BY last_seen DESC LIMIT 1",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        Ok(row.map(|r| r.get("username")))
+    }
+
+    async fn username_history(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM usernames
+                WHERE platform_id = $1 AND platform = $2
+                ORDER BY last_seen DESC",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        Ok(rows.iter().map(username_record).collect())
+    }
+
+    async fn username_owners(
+        &self,
+        username: &str,
+        platform: &str,
+    ) -> StorageResult<Vec<UsernameRecord>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT * FROM usernames
+                WHERE username = $1 AND platform = $2
+                ORDER BY last_seen DESC",
+                &[&username, &platform],
+            )
+            .await?;
+        Ok(rows.iter().map(username_record).collect())
+    }
+
+    async fn meta(
+        &self,
+        platform_id: &str,
+        platform: &str,
+    ) -> StorageResult<Option<Data>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT document FROM data
+                WHERE id = $1 AND platform = $2 AND kind = 'meta'
+                ORDER BY retrieved_at DESC LIMIT 1",
+                &[&platform_id, &platform],
+            )
+            .await?;
+        Ok(row.as_ref().map(data))
+    }
+
+    async fn metas(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        until: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT document FROM (
+                    SELECT document, retrieved_at FROM data
+                    WHERE id = $1 AND platform = $2 AND kind = 'meta'
+                    AND ($3::TIMESTAMPTZ IS NULL OR retrieved_at <= $3)
+                    ORDER BY retrieved_at DESC
+                    LIMIT $4
+                ) AS latest ORDER BY retrieved_at",
+                &[&platform_id, &platform, &until, &limit],
+            )
+            .await?;
+        Ok(rows.iter().map(data).collect())
+    }
+
+    async fn latest_meta_excluding(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        added_by: &str,
+    ) -> StorageResult<Option<Data>> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT document FROM data
+                WHERE id = $1 AND platform = $2 AND kind = 'meta'
+                AND document->>'added_by' IS DISTINCT FROM $3
+                ORDER BY retrieved_at DESC
+                LIMIT 1",
+                &[&platform_id, &platform, &added_by],
+            )
+            .await?;
+        Ok(row.as_ref().map(data))
+    }
+
+    async fn content(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT document FROM data
+                WHERE id = $1 AND platform = $2 AND kind = 'content'
+                ORDER BY retrieved_at DESC
+                LIMIT $3",
+                &[&platform_id, &platform, &limit],
+            )
+            .await?;
+        Ok(rows.iter().map(data).collect())
+    }
+
+    async fn content_with_ids(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        ids: &[(String, String)],
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let (content_types, content_ids): (Vec<&String>, Vec<&String>) =
+            ids.iter().map(|(t, c)| (t, c)).unzip();
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT document FROM data
+                WHERE id = $1 AND platform = $2 AND kind = 'content'
+                AND (content_type, content_id) IN (
+                    SELECT * FROM UNNEST($3::TEXT[], $4::TEXT[])
+                )
+                ORDER BY retrieved_at DESC
+                LIMIT $5",
+                &[
+                    &platform_id,
+                    &platform,
+                    &content_types,
+                    &content_ids,
+                    &limit,
+                ],
+            )
+            .await?;
+        Ok(rows.iter().map(data).collect())
+    }
+
+    async fn presence(
+        &self,
+        platform_id: &str,
+        platform: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> StorageResult<Vec<Data>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT document FROM data
+                WHERE id = $1 AND platform = $2 AND kind = 'presence'
+                AND ($3::TIMESTAMPTZ IS NULL OR retrieved_at >= $3)
+                AND ($4::TIMESTAMPTZ IS NULL OR retrieved_at <= $4)
+                ORDER BY retrieved_at DESC
+                LIMIT $5",
+                &[&platform_id, &platform, &from, &to, &limit],
+            )
+            .await?;
+        Ok(rows.iter().map(data).collect())
+    }
+
+    async fn drop(&self) -> StorageResult<()> {
+        match &self.schema {
+            Some(schema) => {
+                self.client()
+                    .await?
+                    .batch_execute(&format!(
+                        "DROP SCHEMA {} CASCADE;",
+                        quote_identifier(schema)
+                    ))
+                    .await?
+            }
+            None => self.client().await?.batch_execute(DROP_TABLES).await?,
+        }
+        Ok(())
+    }
+}
@@ -1,29 +1,19 @@
 //! API keys for authorisation.
 
-use crate::database::{DBHandle, DBPool};
+use crate::database::Store;
 use crate::response::Error;
-use crate::user::User;
 
 use axum::extract::{FromRequest, RequestParts};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::{async_trait, Json};
-use mongodb::bson::doc;
-use mongodb::Collection;
 
 pub struct Key {
     pub key: String,
 }
 
-async fn user_exists_and_not_banned(db: &DBHandle, key: &str) -> bool {
-    let users: Collection<User> = db.collection("users");
-    // Vulnerable to nosql injection?
-    // Very basic tests says no. Not convinced.
-    users
-        .find_one(doc! {"key": key, "banned": false}, None)
-        .await
-        .unwrap()
-        .is_some()
+async fn user_exists_and_not_banned(db: &Store, key: &str) -> bool {
+    matches!(db.user_with_key(key).await.unwrap(), Some(user) if !user.banned)
 }
 
 #[async_trait]
@@ -33,15 +23,14 @@ impl<B: Send> FromRequest<B> for Key {
     async fn from_request(
         request: &mut RequestParts<B>,
     ) -> Result<Self, Self::Rejection> {
-        let db = request.extensions().get::<DBPool>().unwrap();
+        let db = request.extensions().get::<Store>().unwrap();
 
         // This will still perform a lookup for key 'invalid'.
         let key = request.headers().get("x-api-key");
         match key {
             Some(key) => {
                 let key = key.to_str().unwrap();
-                let result =
-                    user_exists_and_not_banned(&db.handle(), key).await;
+                let result = user_exists_and_not_banned(db, key).await;
 
                 match result {
                     true => Ok(Key {
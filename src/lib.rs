@@ -7,21 +7,34 @@
 //! <https://docs.berserksystems.com/>
 //!
 //! Instrumentality makes heavy use of [Axum] and [MongoDB]. MongoDB is
-//! probably not the correct choice for this system at scale, and a re-write
-//! using PostgreSQL would likely be more performant. However, whilst iterating,
-//! being able to add and remove fields ad-hoc is useful.
+//! probably not the correct choice for this system at scale, so all storage
+//! goes through the [`database::Storage`] trait and [PostgreSQL] can be used
+//! instead. However, whilst iterating, being able to add and remove fields
+//! ad-hoc is useful.
 //!
 //! [MongoDB]: https://www.mongodb.com/
+//! [PostgreSQL]: https://www.postgresql.org/
 //! [Axum]: https://github.com/tokio-rs/axum/
 
+pub mod broadcast;
+pub mod changelog;
 pub mod config;
+pub mod consensus;
 pub mod data;
 pub mod database;
 pub mod group;
 pub mod key;
+pub mod metric;
+pub mod relationship;
+pub mod reputation;
 pub mod response;
+pub mod revision;
 pub mod routes;
 pub mod server;
+pub mod session;
+pub mod signature;
 pub mod subject;
+pub mod tombstone;
 pub mod user;
+pub mod username;
 pub mod utils;
@@ -1,13 +1,24 @@
+pub mod broadcast;
+pub mod changelog;
 pub mod config;
+pub mod consensus;
 pub mod data;
 pub mod database;
 pub mod group;
 pub mod key;
+pub mod metric;
+pub mod relationship;
+pub mod reputation;
 pub mod response;
+pub mod revision;
 pub mod routes;
 pub mod server;
+pub mod session;
+pub mod signature;
 pub mod subject;
+pub mod tombstone;
 pub mod user;
+pub mod username;
 pub mod utils;
 
 use std::fs::File;
@@ -21,6 +32,11 @@ async fn main() {
     if let Ok(config) = config {
         tracing::info!("Config file loaded.");
 
+        if std::env::args().any(|arg| arg == "--dry-run") {
+            server::report_migrations(&config).await;
+            return;
+        }
+
         let (app, tls_config, addr) = server::build_server(&config).await;
 
         let server = axum_server::bind_rustls(addr, tls_config)
@@ -49,6 +65,27 @@ twitter = [\"follower_count_increase\"]
 last_fm = [\"now_playing\"]
 twitch_tv = [\"live\"]
 
+[relationship_types]
+twitter = [\"follows\"]
+
+[metric_types]
+twitter = [\"followers\", \"likes\", \"retweets\"]
+twitch_tv = [\"followers\", \"views\"]
+
+# Optional rules for content and presence types, by platform and type.
+# [content_schemas.instagram.post]
+# Fields that must be given.
+# required = [\"created_at\", \"media\"]
+# Fields that may also be given. Leave out to allow any.
+# optional = [\"body\", \"references\", \"retrieved_from\"]
+# Keys allowed in references. Leave out to allow any.
+# references = [\"location\"]
+# min_media = 1
+# max_media = 10
+# Regular expressions for profile and content IDs.
+# id = \"^[0-9]+$\"
+# content_id = \"^[0-9]+$\"
+
 [mongodb]
 hosts = \"127.0.0.1\"
 port = \"27017\"
@@ -56,8 +93,83 @@ user = \"instrumentality\"
 password = \"\"
 database = \"instrumentality\"
 
+# Only needed when backend = \"postgresql\".
+# [postgres]
+# hosts = \"127.0.0.1\"
+# port = \"5432\"
+# user = \"instrumentality\"
+# password = \"\"
+# database = \"instrumentality\"
+# The most connections kept open at once, four per CPU by default.
+# pool_size = 16
+
 [settings]
 log_level = \"INFO\"
+# One of \"mongodb\", \"postgresql\" or \"memory\" (nothing is saved to disk).
+backend = \"mongodb\"
+
+# Optional, these are the defaults. All durations are in seconds.
+# [queue]
+# Content created this recently makes its profile, and every other profile
+# of the same subjects, hot.
+# promotion_window = 86400
+# How long a profile stays hot for.
+# hot_decay = 259200
+# How often hot profiles are due.
+# hot_interval = 900
+# How often every other profile is due.
+# max_staleness = 21600
+# How long a data provider has to finish a job.
+# lease = 30
+# How often expired leases are cleared.
+# reap_interval = 5
+# The most jobs that can be taken in one request.
+# max_batch = 50
+# The longest a request can wait for a job to become available.
+# max_wait = 30
+# How long a failed job waits, doubling with each failure in a row.
+# backoff = 60
+# max_backoff = 86400
+# Failures in a row before a profile is quarantined.
+# quarantine_after = 5
+# Only confirm a suspected deletion when a different data provider finds the
+# content missing too.
+# independent_confirmation = false
+# Longer or shorter leases for particular platforms.
+# [queue.leases]
+# instagram = 300
+
+# Optional, these are the defaults.
+# [reputation]
+# Submissions compared with others' before a data provider is judged.
+# min_assessed = 20
+# The lowest share of expected fields a trusted data provider includes.
+# threshold = 0.5
+
+# Optional, these are the defaults.
+# [consensus]
+# Data providers that must agree on a value.
+# quorum = 1
+# Seconds before the newest observation that others still count.
+# window = 86400
+
+# Optional, these are the defaults.
+# [sessions]
+# Seconds between presence observations of the same session.
+# gap = 900
+# Longer or shorter gaps for particular presence types.
+# [sessions.gaps]
+# livestream = 300
+
+# Optional rules for pairing livestream events into broadcasts, by platform.
+# [broadcasts.twitch_tv]
+# start = \"stream_start\"
+# end = \"stream_end\"
+# presence = \"live\"
+# recording = \"video\"
+# Seconds a broadcast can last, and after it ends its recording is posted.
+# max_duration = 86400
+# recording_window = 3600
 
 [network]
 address = \"127.0.0.1\"
@@ -0,0 +1,232 @@
+//! Numeric metrics of profiles and content over time.
+//!
+//! A [`Data::Metric`] is a count such as followers, likes or views, observed
+//! at `retrieved_at`. Without a `content_id` it measures the profile, with one
+//! it measures that piece of the profile's content. Metric types are set per
+//! platform under `[metric_types]` in the configuration file.
+//!
+//! Metrics aren't kept with the rest of the data. Each is stored as a
+//! [`MetricPoint`] in a time series of its own, so that a profile's growth can
+//! be read back without going through its content and presence.
+//!
+//! Series are returned by [`crate::routes::metrics`], either as every point or
+//! downsampled into hourly or daily buckets, see [`downsample`]. Buckets start
+//! on the hour or at midnight UTC.
+
+use crate::data::Data;
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MetricPoint {
+    pub platform: String,
+    pub platform_id: String,
+    /// None means the metric is of the profile itself.
+    pub content_id: Option<String>,
+    pub metric_type: String,
+    pub value: f64,
+    pub retrieved_at: DateTime<Utc>,
+    pub added_by: Option<String>, // UUID
+}
+
+impl MetricPoint {
+    /// The point given by `metric`. Data other than [`Data::Metric`] gives
+    /// none.
+    pub fn new(metric: &Data) -> Option<Self> {
+        match metric {
+            Data::Metric {
+                id,
+                platform,
+                metric_type,
+                value,
+                content_id,
+                retrieved_at,
+                added_by,
+                ..
+            } => Some(Self {
+                platform: platform.clone(),
+                platform_id: id.clone(),
+                content_id: content_id.clone(),
+                metric_type: metric_type.clone(),
+                value: *value,
+                retrieved_at: *retrieved_at,
+                added_by: added_by.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Every point as its own bucket.
+    Raw,
+    Hourly,
+    Daily,
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self::Raw
+    }
+}
+
+impl Resolution {
+    // The start of the bucket `at` falls in.
+    fn bucket(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let width = match self {
+            Self::Raw => return at,
+            Self::Hourly => Duration::hours(1),
+            Self::Daily => Duration::days(1),
+        };
+        at.duration_trunc(width).unwrap_or(at)
+    }
+}
+
+/// The points of one metric type falling in one bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Bucket {
+    pub start: DateTime<Utc>,
+    /// When the last point in the bucket was retrieved, which for raw
+    /// buckets is `start`.
+    pub last_at: DateTime<Utc>,
+    pub observations: u64,
+    pub first: f64,
+    pub last: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Bucket {
+    fn new(start: DateTime<Utc>, point: &MetricPoint) -> Self {
+        Self {
+            start,
+            last_at: point.retrieved_at,
+            observations: 1,
+            first: point.value,
+            last: point.value,
+            min: point.value,
+            max: point.value,
+            mean: point.value,
+        }
+    }
+
+    fn add(&mut self, point: &MetricPoint) {
+        self.last_at = point.retrieved_at;
+        self.last = point.value;
+        self.min = self.min.min(point.value);
+        self.max = self.max.max(point.value);
+        self.mean += (point.value - self.mean) / (self.observations + 1) as f64;
+        self.observations += 1;
+    }
+}
+
+/// A metric type's buckets, earliest first.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Series {
+    pub metric_type: String,
+    pub buckets: Vec<Bucket>,
+}
+
+/// The series made up by `points` in any order at `resolution`, by metric
+/// type.
+pub fn downsample(
+    points: &[MetricPoint],
+    resolution: Resolution,
+) -> Vec<Series> {
+    let mut by_type: BTreeMap<&str, Vec<&MetricPoint>> = BTreeMap::new();
+    for p in points {
+        by_type.entry(&p.metric_type).or_default().push(p);
+    }
+
+    let mut series = Vec::new();
+    for (metric_type, mut points) in by_type {
+        points.sort_by_key(|p| p.retrieved_at);
+        let mut buckets: Vec<Bucket> = Vec::new();
+        for p in points {
+            let start = resolution.bucket(p.retrieved_at);
+            match buckets.last_mut() {
+                Some(bucket)
+                    if resolution != Resolution::Raw
+                        && bucket.start == start =>
+                {
+                    bucket.add(p)
+                }
+                _ => buckets.push(Bucket::new(start, p)),
+            }
+        }
+        series.push(Series {
+            metric_type: metric_type.to_string(),
+            buckets,
+        });
+    }
+    series
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn point(
+        metric_type: &str,
+        hour: u32,
+        minute: u32,
+        value: f64,
+    ) -> MetricPoint {
+        MetricPoint {
+            platform: "PLATFORM_1".to_string(),
+            platform_id: "1".to_string(),
+            content_id: None,
+            metric_type: metric_type.to_string(),
+            value,
+            retrieved_at: Utc
+                .with_ymd_and_hms(2022, 1, 1 + hour / 24, hour % 24, minute, 0)
+                .unwrap(),
+            added_by: None,
+        }
+    }
+
+    #[test]
+    fn test_downsample() {
+        let points = vec![
+            point("followers", 0, 30, 20.0),
+            point("followers", 0, 0, 10.0),
+            point("followers", 0, 45, 15.0),
+            point("followers", 1, 0, 40.0),
+            point("followers", 25, 0, 50.0),
+            point("likes", 0, 0, 1.0),
+        ];
+
+        let raw = downsample(&points, Resolution::Raw);
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw[0].metric_type, "followers");
+        assert_eq!(raw[0].buckets.len(), 5);
+        assert_eq!(raw[0].buckets[1].start, raw[0].buckets[1].last_at);
+        assert_eq!(raw[1].buckets.len(), 1);
+
+        let hourly = downsample(&points, Resolution::Hourly);
+        let followers = &hourly[0].buckets;
+        assert_eq!(followers.len(), 3);
+        assert_eq!(followers[0].observations, 3);
+        assert_eq!(followers[0].first, 10.0);
+        assert_eq!(followers[0].last, 15.0);
+        assert_eq!(followers[0].min, 10.0);
+        assert_eq!(followers[0].max, 20.0);
+        assert_eq!(followers[0].mean, 15.0);
+
+        let daily = downsample(&points, Resolution::Daily);
+        let followers = &daily[0].buckets;
+        assert_eq!(followers.len(), 2);
+        assert_eq!(followers[0].observations, 4);
+        assert_eq!(followers[0].last, 40.0);
+        assert_eq!(
+            followers[1].start,
+            Utc.with_ymd_and_hms(2022, 1, 2, 0, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Relationships between profiles, such as following.
+//!
+//! A [`Data::Relationship`] is an observation that one profile was related to
+//! another, e.g. that `from_id` follows `to_id`, at `observed_at`. Each is
+//! kept as an [`Edge`] per data provider and period a relationship lasted.
+//!
+//! Relationships posted to /add only say that they held when observed, as a
+//! single item or a page of a longer list may leave others out. They start
+//! or refresh edges but never end them. Only a [`Snapshot`] posted to
+//! /relationships, the complete list of a relation type for a profile, ends
+//! the edges it leaves out, and may be empty if the profile has no
+//! relationships of that type left.
+//!
+//! Snapshots are only taken a fetch at a time, so when a relationship
+//! started or stopped is only known to lie between two snapshots. Those
+//! seen before a data provider's first snapshot of a profile have no known
+//! start. Observations no newer than the data provider's latest snapshot
+//! are stored but not compared, as the relationships since then are already
+//! known.
+//!
+//! Changes are listed for a profile by [`crate::routes::relationships`].
+
+use crate::data::Data;
+use crate::database::{StorageResult, Store};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A period over which one profile was related to another, as seen by one
+/// data provider.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub platform: String,
+    pub from_id: String,
+    pub to_id: String,
+    pub relation_type: String,
+    pub added_by: String, // UUID
+    /// The last snapshot without the relationship, if there was one.
+    pub started_after: Option<DateTime<Utc>>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// The latest snapshot listing the relationship, if there has been one.
+    pub last_snapshot: Option<DateTime<Utc>>,
+    /// The first snapshot without the relationship again, if there has been
+    /// one.
+    pub stopped_before: Option<DateTime<Utc>>,
+}
+
+// The data provider, platform, profile and relation type edges are compared
+// by.
+type Key<'a> = (&'a str, &'a str, &'a str, &'a str);
+
+impl Edge {
+    fn new(
+        key: Key,
+        to_id: &str,
+        started_after: Option<DateTime<Utc>>,
+        at: DateTime<Utc>,
+    ) -> Self {
+        let (added_by, platform, from_id, relation_type) = key;
+        Self {
+            platform: platform.to_string(),
+            from_id: from_id.to_string(),
+            to_id: to_id.to_string(),
+            relation_type: relation_type.to_string(),
+            added_by: added_by.to_string(),
+            started_after,
+            first_seen: at,
+            last_seen: at,
+            last_snapshot: None,
+            stopped_before: None,
+        }
+    }
+
+    pub fn current(&self) -> bool {
+        self.stopped_before.is_none()
+    }
+
+    // The latest snapshot this edge was compared with, if any.
+    fn latest_snapshot(&self) -> Option<DateTime<Utc>> {
+        self.stopped_before.max(self.last_snapshot)
+    }
+}
+
+/// The complete list of profiles a profile is related to by one relation
+/// type, observed at `observed_at`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub platform: String,
+    pub from_id: String,
+    pub relation_type: String,
+    pub observed_at: DateTime<Utc>,
+    pub to_ids: Vec<String>,
+}
+
+impl Snapshot {
+    /// Each relationship listed as data added by `uuid`.
+    pub fn data(&self, uuid: &str) -> Vec<Data> {
+        self.to_ids
+            .iter()
+            .map(|to_id| {
+                Data::Relationship {
+                    from_id: self.from_id.clone(),
+                    to_id: to_id.clone(),
+                    platform: self.platform.clone(),
+                    relation_type: self.relation_type.clone(),
+                    observed_at: self.observed_at,
+                    added_by: None,
+                    added_at: None,
+                    submission: None,
+                }
+                .tag(uuid.to_string(), None)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Started,
+    Stopped,
+}
+
+/// A relationship starting or stopping between two snapshots, as seen by
+/// the data provider `added_by`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RelationshipChange {
+    pub to_id: String,
+    pub relation_type: String,
+    pub change: Change,
+    pub after: DateTime<Utc>,
+    pub before: DateTime<Utc>,
+    pub added_by: String, // UUID
+}
+
+impl RelationshipChange {
+    /// Whether the change could have happened between `from` and `to`.
+    /// Either may be left open.
+    pub fn overlaps(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> bool {
+        from.map_or(true, |from| self.before >= from)
+            && to.map_or(true, |to| self.after <= to)
+    }
+}
+
+/// Every start and stop known from `edges`, earliest first.
+pub fn changes(edges: &[Edge]) -> Vec<RelationshipChange> {
+    let mut changes = Vec::new();
+    for e in edges {
+        let change = |change, after, before| RelationshipChange {
+            to_id: e.to_id.clone(),
+            relation_type: e.relation_type.clone(),
+            change,
+            after,
+            before,
+            added_by: e.added_by.clone(),
+        };
+        if let Some(after) = e.started_after {
+            changes.push(change(Change::Started, after, e.first_seen));
+        }
+        if let Some(before) = e.stopped_before {
+            changes.push(change(Change::Stopped, e.last_seen, before));
+        }
+    }
+    changes.sort_by_key(|c| c.before);
+    changes
+}
+
+/// Starts or refreshes the edges of the relationships in `data`. Edges
+/// missing from `data` are left as they are, see [`observe_snapshot`].
+pub async fn observe(data: &[Data], db: &Store) -> StorageResult<()> {
+    let mut observed: HashMap<Key, HashMap<&str, DateTime<Utc>>> =
+        HashMap::new();
+    for d in data {
+        if let Data::Relationship {
+            from_id,
+            to_id,
+            platform,
+            relation_type,
+            observed_at,
+            added_by,
+            ..
+        } = d
+        {
+            let added_by = added_by.as_deref().unwrap_or_default();
+            let (platform, from_id) = (platform.as_str(), from_id.as_str());
+            let at = observed
+                .entry((added_by, platform, from_id, relation_type.as_str()))
+                .or_default()
+                .entry(to_id.as_str())
+                .or_insert(*observed_at);
+            *at = (*at).max(*observed_at);
+        }
+    }
+
+    for (key, to_ids) in observed {
+        let edges = provider_edges(key, db).await?;
+        let previous = edges.iter().filter_map(Edge::latest_snapshot).max();
+        let mut current: HashMap<String, Edge> = edges
+            .into_iter()
+            .filter(Edge::current)
+            .map(|e| (e.to_id.clone(), e))
+            .collect();
+
+        let mut changed = Vec::new();
+        for (to_id, at) in to_ids {
+            if previous.map_or(false, |p| at <= p) {
+                continue;
+            }
+            match current.remove(to_id) {
+                Some(mut e) => {
+                    e.last_seen = e.last_seen.max(at);
+                    changed.push(e);
+                }
+                None => changed.push(Edge::new(key, to_id, previous, at)),
+            }
+        }
+        db.record_edges(&changed).await?;
+    }
+    Ok(())
+}
+
+/// Compares `snapshot`, taken by the data provider `added_by`, with the
+/// edges it has stored for the profile and records what started and
+/// stopped.
+pub async fn observe_snapshot(
+    snapshot: &Snapshot,
+    added_by: &str,
+    db: &Store,
+) -> StorageResult<()> {
+    let key = (
+        added_by,
+        snapshot.platform.as_str(),
+        snapshot.from_id.as_str(),
+        snapshot.relation_type.as_str(),
+    );
+    let at = snapshot.observed_at;
+    let edges = provider_edges(key, db).await?;
+    let previous = edges.iter().filter_map(Edge::latest_snapshot).max();
+    if previous.map_or(false, |p| at <= p) {
+        return Ok(());
+    }
+
+    let to_ids: HashSet<&String> = snapshot.to_ids.iter().collect();
+    let mut changed = Vec::new();
+    let mut current = HashSet::new();
+    for mut e in edges.into_iter().filter(Edge::current) {
+        if to_ids.contains(&e.to_id) {
+            e.last_seen = e.last_seen.max(at);
+            e.last_snapshot = Some(at);
+            current.insert(e.to_id.clone());
+        } else if e.last_seen < at {
+            e.stopped_before = Some(at);
+        } else {
+            // Observed since the snapshot was taken.
+            continue;
+        }
+        changed.push(e);
+    }
+    for to_id in to_ids {
+        if !current.contains(to_id) {
+            let mut e = Edge::new(key, to_id, previous, at);
+            e.last_snapshot = Some(at);
+            changed.push(e);
+        }
+    }
+    db.record_edges(&changed).await
+}
+
+// The edges of the profile and relation type in `key` stored for the data
+// provider in it.
+async fn provider_edges(key: Key<'_>, db: &Store) -> StorageResult<Vec<Edge>> {
+    let (added_by, platform, from_id, relation_type) = key;
+    Ok(db
+        .edges(from_id, platform)
+        .await?
+        .into_iter()
+        .filter(|e| e.added_by == added_by && e.relation_type == relation_type)
+        .collect())
+}
@@ -0,0 +1,152 @@
+//! Reputation of data providers.
+//!
+//! A data provider that posts the minimum subset of a profile or piece of
+//! content forces Instrumentality to store less than it could have (see
+//! [`crate::data`]). Every [`Data::Meta`] and [`Data::Content`] submitted is
+//! therefore compared with what other data providers have already submitted
+//! for the same profile or content. The optional fields they included are
+//! expected, and each data provider's reputation is the share of expected
+//! fields they have included across everything they have submitted.
+//!
+//! A field that was filled in before may legitimately be empty now, e.g. a bio
+//! that was removed, so a single submission says little. Data providers are
+//! only judged once they have been assessed `min_assessed` times, after which
+//! a score below `threshold` makes them untrusted. Both are set under
+//! `[reputation]` in the configuration file.
+//!
+//! Jobs completed by untrusted data providers are due again as if their
+//! profile were hot (see [`crate::routes::queue`]) and their data is held back
+//! from /view unless asked for (see [`crate::routes::view`]).
+
+use crate::config::ReputationConfig;
+use crate::data::Data;
+use crate::database::Store;
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+// How much of a profile's recent content is searched for earlier submissions
+// of the same content.
+const CONTENT_LIMIT: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Reputation {
+    pub user: String, // UUID
+    /// Submissions compared with earlier submissions by others.
+    pub assessed: u64,
+    /// Optional fields included by earlier submissions.
+    pub expected: u64,
+    /// Expected fields that the data provider included too.
+    pub provided: u64,
+}
+
+impl Reputation {
+    pub fn new(user: &str) -> Self {
+        Self {
+            user: user.to_string(),
+            assessed: 0,
+            expected: 0,
+            provided: 0,
+        }
+    }
+
+    /// The share of expected fields provided, from 0 to 1.
+    pub fn score(&self) -> f64 {
+        if self.expected == 0 {
+            1.0
+        } else {
+            self.provided as f64 / self.expected as f64
+        }
+    }
+
+    pub fn trusted(&self, config: &ReputationConfig) -> bool {
+        self.assessed < config.min_assessed || self.score() >= config.threshold
+    }
+}
+
+/// Compares tagged `data` with what has already been stored and records the
+/// result against each data provider. Must be called before `data` is
+/// inserted.
+pub async fn assess(data: &[Data], db: &Store) {
+    let mut contents: HashMap<(&String, &String), Vec<Data>> = HashMap::new();
+    for d in data {
+        let earlier = match d {
+            Data::Meta {
+                id,
+                platform,
+                added_by: Some(added_by),
+                ..
+            } => db
+                .latest_meta_excluding(id, platform, added_by)
+                .await
+                .unwrap(),
+            Data::Content {
+                id,
+                platform,
+                content_id,
+                content_type,
+                added_by,
+                ..
+            } => {
+                if let Entry::Vacant(e) = contents.entry((id, platform)) {
+                    e.insert(
+                        db.content(id, platform, CONTENT_LIMIT).await.unwrap(),
+                    );
+                }
+                contents[&(id, platform)]
+                    .iter()
+                    .find(|c| {
+                        matches!(c, Data::Content {
+                            content_id: c_id,
+                            content_type: c_type,
+                            ..
+                        } if c_id == content_id && c_type == content_type)
+                            && added_by_of(c) != added_by
+                    })
+                    .cloned()
+            }
+            Data::Meta { .. }
+            | Data::Presence { .. }
+            | Data::Relationship { .. }
+            | Data::Metric { .. } => None,
+        };
+
+        if let (Some(earlier), Some(added_by)) = (earlier, added_by_of(d)) {
+            let expected = earlier.populated();
+            let provided = d.populated().intersection(&expected).count();
+            db.record_reputation(
+                added_by,
+                expected.len() as u64,
+                provided as u64,
+            )
+            .await
+            .unwrap();
+        }
+    }
+}
+
+/// The data providers among `users` that are not trusted.
+pub async fn untrusted(
+    users: &[String],
+    db: &Store,
+    config: &ReputationConfig,
+) -> HashSet<String> {
+    db.reputations(users)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|r| !r.trusted(config))
+        .map(|r| r.user)
+        .collect()
+}
+
+pub fn added_by_of(data: &Data) -> &Option<String> {
+    match data {
+        Data::Presence { added_by, .. }
+        | Data::Content { added_by, .. }
+        | Data::Meta { added_by, .. }
+        | Data::Relationship { added_by, .. }
+        | Data::Metric { added_by, .. } => added_by,
+    }
+}
@@ -35,6 +35,157 @@ impl Default for Ok {
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AddResponse {
+    pub response: String,
+    pub results: Vec<crate::data::DataResult>,
+}
+
+impl AddResponse {
+    pub fn new(results: Vec<crate::data::DataResult>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            results,
+        }
+    }
+
+    /// Every item was rejected, see [`crate::data::Rejection`].
+    pub fn rejected(results: Vec<crate::data::DataResult>) -> Self {
+        Self {
+            response: "ERROR".to_string(),
+            results,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangelogResponse {
+    pub response: String,
+    pub platform: String,
+    pub id: String,
+    pub changes: Vec<crate::changelog::MetaChange>,
+    /// Pass as `until` for the changes before these, if there are any.
+    pub next: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ChangelogResponse {
+    pub fn new(
+        platform: String,
+        id: String,
+        changes: Vec<crate::changelog::MetaChange>,
+        next: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platform,
+            id,
+            changes,
+            next,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct BroadcastsResponse {
+    pub response: String,
+    pub subject: String,
+    pub broadcasts: Vec<crate::broadcast::Broadcast>,
+}
+
+impl BroadcastsResponse {
+    pub fn new(
+        subject: String,
+        broadcasts: Vec<crate::broadcast::Broadcast>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            subject,
+            broadcasts,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RelationshipsResponse {
+    pub response: String,
+    pub platform: String,
+    pub id: String,
+    pub current: Vec<crate::relationship::Edge>,
+    pub changes: Vec<crate::relationship::RelationshipChange>,
+    pub held_back: u64,
+}
+
+impl RelationshipsResponse {
+    pub fn new(
+        platform: String,
+        id: String,
+        current: Vec<crate::relationship::Edge>,
+        changes: Vec<crate::relationship::RelationshipChange>,
+        held_back: u64,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platform,
+            id,
+            current,
+            changes,
+            held_back,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MetricsResponse {
+    pub response: String,
+    pub platform: String,
+    pub id: String,
+    pub content_id: Option<String>,
+    pub resolution: crate::metric::Resolution,
+    pub series: Vec<crate::metric::Series>,
+}
+
+impl MetricsResponse {
+    pub fn new(
+        platform: String,
+        id: String,
+        content_id: Option<String>,
+        resolution: crate::metric::Resolution,
+        series: Vec<crate::metric::Series>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platform,
+            id,
+            content_id,
+            resolution,
+            series,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LookupResponse {
+    pub response: String,
+    pub platform: String,
+    pub username: String,
+    pub profiles: Vec<crate::routes::lookup::LookupProfile>,
+}
+
+impl LookupResponse {
+    pub fn new(
+        platform: String,
+        username: String,
+        profiles: Vec<crate::routes::lookup::LookupProfile>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platform,
+            username,
+            profiles,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct InviteResponse {
     pub response: String,
@@ -57,6 +208,7 @@ pub struct QueueResponse {
     pub username: String,
     pub platform: String,
     pub platform_id: String,
+    pub lease_expires_at: chrono::DateTime<chrono::Utc>,
 }
 
 impl QueueResponse {
@@ -65,6 +217,7 @@ impl QueueResponse {
         username: String,
         platform: String,
         platform_id: String,
+        lease_expires_at: chrono::DateTime<chrono::Utc>,
     ) -> Self {
         Self {
             response: "OK".to_string(),
@@ -72,6 +225,145 @@ impl QueueResponse {
             username,
             platform,
             platform_id,
+            lease_expires_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct QueueBatchResponse {
+    pub response: String,
+    pub jobs: Vec<QueueResponse>,
+}
+
+impl QueueBatchResponse {
+    pub fn new(jobs: Vec<QueueResponse>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            jobs,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NoJobsResponse {
+    pub response: String,
+}
+
+impl NoJobsResponse {
+    pub fn new() -> Self {
+        Self {
+            response: "NO_JOBS".to_string(),
+        }
+    }
+}
+
+impl Default for NoJobsResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct QueueStatsResponse {
+    pub response: String,
+    pub platforms: Vec<crate::routes::stats::PlatformStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<crate::routes::stats::ProfileFreshness>>,
+}
+
+impl QueueStatsResponse {
+    pub fn new(
+        platforms: Vec<crate::routes::stats::PlatformStats>,
+        profiles: Option<Vec<crate::routes::stats::ProfileFreshness>>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platforms,
+            profiles,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LeaderboardResponse {
+    pub response: String,
+    pub period: String,
+    pub periods: Vec<String>,
+    pub entries: Vec<crate::routes::leaderboard::LeaderboardEntry>,
+}
+
+impl LeaderboardResponse {
+    pub fn new(
+        period: String,
+        periods: Vec<String>,
+        entries: Vec<crate::routes::leaderboard::LeaderboardEntry>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            period,
+            periods,
+            entries,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LeaseResponse {
+    pub response: String,
+    pub queue_id: String,
+    pub lease_expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl LeaseResponse {
+    pub fn new(
+        queue_id: String,
+        lease_expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            queue_id,
+            lease_expires_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FailResponse {
+    pub response: String,
+    pub queue_id: String,
+    pub due_at: chrono::DateTime<chrono::Utc>,
+    pub quarantined: bool,
+}
+
+impl FailResponse {
+    pub fn new(
+        queue_id: String,
+        due_at: chrono::DateTime<chrono::Utc>,
+        quarantined: bool,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            queue_id,
+            due_at,
+            quarantined,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct QuarantineResponse {
+    pub response: String,
+    pub profiles: Vec<crate::routes::quarantine::QuarantinedProfile>,
+}
+
+impl QuarantineResponse {
+    pub fn new(
+        profiles: Vec<crate::routes::quarantine::QuarantinedProfile>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            profiles,
         }
     }
 }
@@ -111,17 +403,28 @@ pub struct TypesResponse {
     pub response: String,
     pub content_types: std::collections::HashMap<String, Vec<String>>,
     pub presence_types: std::collections::HashMap<String, Vec<String>>,
+    pub relationship_types: std::collections::HashMap<String, Vec<String>>,
+    pub metric_types: std::collections::HashMap<String, Vec<String>>,
+    pub content_schemas: std::collections::HashMap<
+        String,
+        std::collections::HashMap<String, crate::config::TypeSchema>,
+    >,
+    pub presence_schemas: std::collections::HashMap<
+        String,
+        std::collections::HashMap<String, crate::config::TypeSchema>,
+    >,
 }
 
 impl TypesResponse {
-    pub fn new(
-        content_types: std::collections::HashMap<String, Vec<String>>,
-        presence_types: std::collections::HashMap<String, Vec<String>>,
-    ) -> Self {
+    pub fn new(config: crate::config::IConfig) -> Self {
         Self {
             response: "OK".to_string(),
-            content_types,
-            presence_types,
+            content_types: config.content_types,
+            presence_types: config.presence_types,
+            relationship_types: config.relationship_types,
+            metric_types: config.metric_types,
+            content_schemas: config.content_schemas,
+            presence_schemas: config.presence_schemas,
         }
     }
 }
@@ -178,3 +481,18 @@ impl CreateResponse {
         }
     }
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SubmissionResponse {
+    pub response: String,
+    pub submission: crate::signature::Submission,
+}
+
+impl SubmissionResponse {
+    pub fn new(submission: crate::signature::Submission) -> Self {
+        Self {
+            response: "OK".to_string(),
+            submission,
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Revisions of content.
+//!
+//! Content is often edited after it is posted. Merging (see
+//! [`Data::merge`]) never overwrites the body or media of stored content, so
+//! every distinct version that data providers observe is kept separately as a
+//! [`Revision`], along with who observed it and when. Each data provider's
+//! observations of a version are kept as when it first and last saw it, so a
+//! history only grows with new versions and data providers.
+//!
+//! A version is the body and media of a [`Data::Content`]. Data providers
+//! don't always retrieve both, so a missing body or media is taken as not
+//! observed rather than removed: content agreeing with the most recently seen
+//! version on whatever it has is another observation of it, and fills in
+//! whatever that version was missing. Content submitted with neither is not a
+//! version of anything and is not recorded.
+
+use crate::data::Data;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ContentHistory {
+    pub content_id: String,
+    pub platform: String,
+    pub content_type: String,
+    pub revisions: Vec<Revision>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Revision {
+    pub body: Option<String>,
+    pub media: Option<Vec<String>>,
+    pub observations: Vec<Observation>,
+}
+
+/// When one data provider first and last observed a version.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Observation {
+    pub added_by: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl ContentHistory {
+    /// A history holding the single revision observed by `content`, if it is
+    /// content with a body or media.
+    pub fn new(content: &Data) -> Option<Self> {
+        match content {
+            Data::Content {
+                content_id,
+                platform,
+                content_type,
+                body,
+                media,
+                retrieved_at,
+                added_by,
+                ..
+            } if body.is_some() || media.is_some() => Some(Self {
+                content_id: content_id.clone(),
+                platform: platform.clone(),
+                content_type: content_type.clone(),
+                revisions: vec![Revision {
+                    body: body.clone(),
+                    media: media.clone(),
+                    observations: vec![Observation {
+                        added_by: added_by.clone(),
+                        first_seen: *retrieved_at,
+                        last_seen: *retrieved_at,
+                    }],
+                }],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Adds `revision` to the most recently seen revision of the same
+    /// version, or adds it as a new revision.
+    pub fn observe(&mut self, revision: Revision) {
+        match self
+            .revisions
+            .iter_mut()
+            .filter(|r| r.same_version(&revision))
+            .max_by_key(|r| r.last_seen())
+        {
+            Some(existing) => existing.absorb(revision),
+            None => self.revisions.push(revision),
+        }
+    }
+
+    /// The revisions in the order they were first observed.
+    pub fn chain(mut self) -> Vec<Revision> {
+        self.revisions.sort_by_key(|r| r.first_seen());
+        self.revisions
+    }
+}
+
+impl Revision {
+    /// Whether the two agree on every field both of them have.
+    pub fn same_version(&self, other: &Revision) -> bool {
+        agree(&self.body, &other.body) && agree(&self.media, &other.media)
+    }
+
+    pub fn first_seen(&self) -> Option<DateTime<Utc>> {
+        self.observations.iter().map(|o| o.first_seen).min()
+    }
+
+    pub fn last_seen(&self) -> Option<DateTime<Utc>> {
+        self.observations.iter().map(|o| o.last_seen).max()
+    }
+
+    // Fills in fields from `other`, a revision of the same version, and
+    // widens the observations of each of its data providers.
+    fn absorb(&mut self, other: Revision) {
+        self.body = self.body.take().or(other.body);
+        self.media = self.media.take().or(other.media);
+        for o in other.observations {
+            match self
+                .observations
+                .iter_mut()
+                .find(|e| e.added_by == o.added_by)
+            {
+                Some(existing) => {
+                    existing.first_seen = existing.first_seen.min(o.first_seen);
+                    existing.last_seen = existing.last_seen.max(o.last_seen);
+                }
+                None => self.observations.push(o),
+            }
+        }
+    }
+}
+
+// Whether `a` and `b` are equal, taking a missing value as unknown.
+fn agree<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Timelike};
+
+    fn revision(
+        body: Option<&str>,
+        media: Option<&str>,
+        added_by: &str,
+        hour: u32,
+    ) -> Revision {
+        let at = Utc.with_ymd_and_hms(2022, 1, 1, hour, 0, 0).unwrap();
+        Revision {
+            body: body.map(str::to_string),
+            media: media.map(|m| vec![m.to_string()]),
+            observations: vec![Observation {
+                added_by: Some(added_by.to_string()),
+                first_seen: at,
+                last_seen: at,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut history = ContentHistory {
+            content_id: "1".to_string(),
+            platform: "PLATFORM_1".to_string(),
+            content_type: "post".to_string(),
+            revisions: vec![revision(Some("Hello."), None, "a", 1)],
+        };
+
+        // A provider leaving out the body sees the same version.
+        history.observe(revision(None, Some("1.jpg"), "b", 2));
+        history.observe(revision(Some("Hello."), None, "a", 3));
+        history.observe(revision(Some("Hello."), Some("1.jpg"), "a", 4));
+        assert_eq!(history.revisions.len(), 1);
+        let revision_1 = &history.revisions[0];
+        assert_eq!(revision_1.media, Some(vec!["1.jpg".to_string()]));
+        assert_eq!(revision_1.observations.len(), 2);
+        assert_eq!(revision_1.first_seen().unwrap().hour(), 1);
+        assert_eq!(revision_1.last_seen().unwrap().hour(), 4);
+
+        history.observe(revision(Some("Edited."), None, "b", 5));
+        history.observe(revision(None, Some("1.jpg"), "a", 6));
+        let chain = history.chain();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[1].body.as_deref(), Some("Edited."));
+        assert_eq!(chain[1].media, Some(vec!["1.jpg".to_string()]));
+        assert_eq!(chain[1].last_seen().unwrap().hour(), 6);
+    }
+}
@@ -6,46 +6,131 @@
 //! <https://docs.berserksystems.com/endpoints/add/>.
 //!
 //! See [`Data`] for examples of valid data objects.
+//!
+//! The response holds a result for each submitted item, in order, saying
+//! whether it was inserted, merged into existing content, changed nothing or
+//! was rejected. Rejected items carry a [`Rejection`](crate::data::Rejection)
+//! code:
+//! - `unknown_platform`: the platform isn't supported by this server.
+//! - `unknown_type`: the content or presence type isn't supported on the
+//!   platform.
+//! - `wrong_job`: the item doesn't belong to the queue job in `queue_id`.
+//! - `duplicate`: the item is identical to an earlier item in the submission.
+//! - `malformed_timestamp`: a timestamp isn't an RFC 3339 date and time.
+//! - `malformed`: the item isn't content, presence or metadata.
+//! - `unsupported_version`: the item is tagged with an unknown
+//!   `schema_version`.
+//!
+//! Content and presence types may also declare rules, published by /types.
+//! Items breaking them are rejected with one of `missing_field`,
+//! `unexpected_field`, `unknown_reference`, `media_count` or `invalid_id`.
+//!
+//! Items that couldn't be parsed also carry the `error` that stopped them,
+//! see [the wire format](crate::data#wire-format). If every item is rejected,
+//! the results are returned with 406 Not Acceptable.
+//!
+//! Profiles with newly created content are promoted in the queue, see
+//! [`queue`]. Completing a queue job credits the data provider on the
+//! leaderboard, see [`leaderboard`]. Submissions are compared with what is
+//! already stored to judge the data provider, see [`reputation`].
+//!
+//! Data providers with a registered public key may sign the request body and
+//! send the signature in the `X-SIGNATURE` header. An invalid signature is
+//! rejected outright, see [`crate::signature`]. The submission is only stored
+//! if some of its data is newly inserted, as nothing else refers to it.
 
 use crate::config::IConfig;
-use crate::data::{Data, Datas};
-use crate::database::DBHandle;
+use crate::data::{Batch, Ingest};
+use crate::database::Store;
 use crate::key::Key;
-use crate::response::{Error, Ok};
+use crate::reputation;
+use crate::response::{AddResponse, Error};
+use crate::routes::{leaderboard, queue};
+use crate::signature::{self, Submission};
 use crate::user::User;
 
+use axum::body::Bytes;
+use axum::http::HeaderMap;
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use mongodb::Collection;
+use chrono::Utc;
 
 pub async fn add(
     key: Key,
-    Json(data): Json<Datas>,
-    db: DBHandle,
+    headers: HeaderMap,
+    db: Store,
     config: IConfig,
+    body: Bytes,
 ) -> impl IntoResponse {
-    let data = data
-        .verify(&config.content_types, &config.presence_types)
-        .tag(User::with_key(&key.key, &db).await.unwrap().uuid)
-        .process_queue(&db)
+    let batch = match Batch::parse(&body) {
+        Ok(batch) => batch,
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new(&format!("Malformed submission: {e}."))),
+            ))
+        }
+    };
+    let user = User::with_key(&key.key, &db).await.unwrap();
+    let submission = match headers.get("x-signature") {
+        Some(sig) => Some(signed(&user, &body, sig.to_str().unwrap_or(""))?),
+        None => None,
+    };
+
+    let batch = batch
+        .verify(&config)
+        .dedup()
+        .tag(
+            user.uuid.clone(),
+            submission.as_ref().map(|s| s.uuid.clone()),
+        )
+        .process_queue(&db, &config)
         .await;
-    let data_coll: Collection<Data> = db.collection("data");
-    // We need to merge existing Data::Content here.
-    // If it already exists, check if the new data is a superset of the
-    // existing content and replace it. If not, merge the data so that it
-    // is a union of the two. We should only ever be turning Option::None
-    // into Option::Some(T) in this case.
-    // Additionally, deleted can become true.
+    let data = batch.accepted();
+    // Existing Data::Content is merged rather than duplicated, see
+    // Data::merge.
     if !data.data.is_empty() {
-        data_coll.insert_many(data.data, None).await.unwrap();
-        Ok((StatusCode::OK, Json(Ok::new())))
+        reputation::assess(&data.data, &db).await;
+        let results = data.insert(&db).await.unwrap();
+        // Only newly inserted data refers to the submission, see Data::merge.
+        let inserted = results.iter().any(|r| r.result == Ingest::Inserted);
+        if let (Some(submission), true) = (&submission, inserted) {
+            db.insert_submission(submission).await.unwrap();
+        }
+        queue::promote(&data.data, &results, &db, &config.queue).await;
+        if batch.completed {
+            leaderboard::credit(&user.uuid, &results, Utc::now(), &db).await;
+        }
+        let results = batch.results(results);
+        Ok((StatusCode::OK, Json(AddResponse::new(results))))
     } else {
-        Err((
+        let results = batch.results(Vec::new());
+        Ok((
             StatusCode::NOT_ACCEPTABLE,
+            Json(AddResponse::rejected(results)),
+        ))
+    }
+}
+
+// The submission if `signature` is a valid signature of `body` by `user`.
+fn signed(
+    user: &User,
+    body: &[u8],
+    signature: &str,
+) -> Result<Submission, (StatusCode, Json<Error>)> {
+    let invalid = || {
+        (
+            StatusCode::UNAUTHORIZED,
             Json(Error::new(
-                "No valid data was submitted. Ensure the given platforms and 
-            content/presence types are supported by this server. Ensure all data
-             was correctly labeled for queue jobs.",
+                "Invalid signature. Ensure the whole request body was signed \
+                with the private key matching your registered public key.",
             )),
-        ))
+        )
+    };
+    let public_key = user.public_key.as_ref().ok_or_else(invalid)?;
+    let body = std::str::from_utf8(body).map_err(|_| invalid())?;
+    if signature::verify(public_key, body.as_bytes(), signature) {
+        Ok(Submission::new(&user.uuid, public_key, signature, body))
+    } else {
+        Err(invalid())
     }
 }
@@ -0,0 +1,114 @@
+//! Route for viewing the broadcasts of a subject.
+//!
+//! The /broadcasts route is implemented here.
+//!
+//! Broadcasts are paired from the content and presence of every profile of
+//! the subject on a platform with a rule under `[broadcasts]`, see
+//! [`crate::broadcast`]. Pass `from` and `to` to only include broadcasts that
+//! could have been live between them.
+//!
+//! As in /view, data from untrusted data providers is left out.
+
+use crate::broadcast::{self, Broadcast};
+use crate::config::IConfig;
+use crate::data::Data;
+use crate::database::Store;
+use crate::key::Key;
+use crate::reputation::{self, added_by_of};
+use crate::response::{BroadcastsResponse, Error};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// The maximum number of content and presence items per profile paired into
+// broadcasts.
+const BROADCAST_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct BroadcastsQuery {
+    subject: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+pub async fn broadcasts(
+    query: Option<Query<BroadcastsQuery>>,
+    db: Store,
+    _key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let query = match query {
+        Some(Query(query)) => query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("You must provide a subject.")),
+            ))
+        }
+    };
+    let subject = match db
+        .subjects(std::slice::from_ref(&query.subject))
+        .await
+        .unwrap()
+        .pop()
+    {
+        Some(subject) => subject,
+        None => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(Error::new("No such subject.")),
+            ))
+        }
+    };
+
+    let mut broadcasts: Vec<Broadcast> = Vec::new();
+    for (platform, ids) in &subject.profiles {
+        let rule = match config.broadcasts.get(platform) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        for platform_id in ids {
+            let mut content = db
+                .content(platform_id, platform, BROADCAST_LIMIT)
+                .await
+                .unwrap();
+            let mut presence = db
+                .presence(platform_id, platform, None, None, BROADCAST_LIMIT)
+                .await
+                .unwrap();
+
+            let providers: Vec<String> = content
+                .iter()
+                .chain(&presence)
+                .filter_map(|d| added_by_of(d).clone())
+                .collect();
+            let untrusted =
+                reputation::untrusted(&providers, &db, &config.reputation)
+                    .await;
+            let trusted = |d: &Data| {
+                added_by_of(d)
+                    .as_ref()
+                    .map_or(true, |a| !untrusted.contains(a))
+            };
+            content.retain(trusted);
+            presence.retain(trusted);
+
+            broadcasts.extend(broadcast::broadcasts(
+                platform_id,
+                platform,
+                &content,
+                &presence,
+                rule,
+                &config.sessions,
+            ));
+        }
+    }
+    broadcasts.retain(|b| b.overlaps(query.from, query.to));
+    broadcasts.sort_by_key(|b| b.started_at);
+
+    Ok((
+        StatusCode::OK,
+        Json(BroadcastsResponse::new(subject.uuid, broadcasts)),
+    ))
+}
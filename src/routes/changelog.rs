@@ -0,0 +1,70 @@
+//! Route for viewing changes to a profile's metadata.
+//!
+//! The /changelog route is implemented here.
+//!
+//! Changes are worked out from the [`Data::Meta`](crate::data::Data::Meta)
+//! stored for the profile, see [`crate::changelog`], a page of the latest
+//! metadata at a time. Where there is more, `next` is given: pass it as
+//! `until` for the changes before.
+
+use crate::changelog::meta_changes;
+use crate::data::Data;
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{ChangelogResponse, Error};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// The number of metadata compared for each page of changes.
+const CHANGELOG_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct ChangelogQuery {
+    platform: String,
+    id: String,
+    until: Option<DateTime<Utc>>,
+}
+
+pub async fn changelog(
+    query: Option<Query<ChangelogQuery>>,
+    db: Store,
+    _key: Key,
+) -> impl IntoResponse {
+    let query =
+        match query {
+            Some(Query(query)) => query,
+            None => return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new(
+                    "You must provide a platform, an id and any until as a \
+                    date and time.",
+                )),
+            )),
+        };
+    let metas = db
+        .metas(&query.id, &query.platform, query.until, CHANGELOG_LIMIT)
+        .await
+        .unwrap();
+    let changes = meta_changes(&metas);
+    // The oldest metadata is compared again with what came before it.
+    let next = match metas.first() {
+        Some(Data::Meta { retrieved_at, .. })
+            if metas.len() as i64 == CHANGELOG_LIMIT =>
+        {
+            Some(*retrieved_at)
+        }
+        _ => None,
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(ChangelogResponse::new(
+            query.platform,
+            query.id,
+            changes,
+            next,
+        )),
+    ))
+}
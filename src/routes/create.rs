@@ -6,7 +6,7 @@
 //! <https://docs.berserksystems.com/endpoints/create/>.
 
 use crate::config::IConfig;
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::group::Group;
 use crate::key::Key;
 use crate::response::{CreateResponse, Error};
@@ -16,8 +16,6 @@ use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use chrono::Utc;
-use mongodb::bson::doc;
-use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -39,17 +37,16 @@ pub enum CreateData {
 
 pub async fn create(
     Json(data): Json<CreateData>,
-    db: DBHandle,
+    db: Store,
     key: Key,
     config: IConfig,
 ) -> impl IntoResponse {
     match data {
         CreateData::CreateSubject { .. } => {
-            let subj_coll: Collection<Subject> = db.collection("subjects");
             if let Some(subject) = subject_from_create(data, &db, key).await {
                 for platform in subject.profiles.keys() {
-                    if config.content_types.get(platform).is_none()
-                        && config.presence_types.get(platform).is_none()
+                    if !config.content_types.contains_key(platform)
+                        && !config.presence_types.contains_key(platform)
                     {
                         return Err((
                             StatusCode::BAD_REQUEST,
@@ -59,7 +56,7 @@ pub async fn create(
                         ));
                     }
                 }
-                if subj_coll.insert_one(&subject, None).await.is_ok() {
+                if db.insert_subject(&subject).await.is_ok() {
                     for platform in subject.profiles.keys() {
                         for id in subject.profiles.get(platform).unwrap() {
                             queue::add_queue_item(id, platform, &db, false)
@@ -86,14 +83,9 @@ pub async fn create(
             }
         }
         CreateData::CreateGroup { .. } => {
-            let group_coll: Collection<Group> = db.collection("groups");
             if let Some(group) = group_from_create(data, &db, key).await {
-                let subj_coll: Collection<Subject> = db.collection("subjects");
                 for s in &group.subjects {
-                    let subject = subj_coll
-                        .find_one(doc! {"uuid": s}, None)
-                        .await
-                        .unwrap();
+                    let subject = db.subject(s).await.unwrap();
                     if subject.is_none() {
                         return Err((
                             StatusCode::BAD_REQUEST,
@@ -103,7 +95,7 @@ pub async fn create(
                         ));
                     }
                 }
-                if group_coll.insert_one(&group, None).await.is_ok() {
+                if db.insert_group(&group).await.is_ok() {
                     Ok((StatusCode::OK, Json(CreateResponse::new(&group.uuid))))
                 } else {
                     Err((
@@ -123,7 +115,7 @@ pub async fn create(
 
 pub async fn group_from_create(
     cs: CreateData,
-    db: &DBHandle,
+    db: &Store,
     key: Key,
 ) -> Option<Group> {
     match cs {
@@ -145,7 +137,7 @@ pub async fn group_from_create(
 
 pub async fn subject_from_create(
     cs: CreateData,
-    db: &DBHandle,
+    db: &Store,
     key: Key,
 ) -> Option<Subject> {
     match cs {
@@ -5,18 +5,14 @@
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/delete/>.
 
-use crate::database::DBHandle;
-use crate::group::Group;
+use crate::database::Store;
 use crate::key::Key;
 use crate::response::Error;
 use crate::response::Ok;
 use crate::routes::queue;
-use crate::subject::*;
 use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use mongodb::bson::doc;
-use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,33 +23,20 @@ pub struct DeleteData {
 // This is ugly. Can probably do better than an if-else.
 pub async fn delete(
     Json(data): Json<DeleteData>,
-    db: DBHandle,
+    db: Store,
     key: Key,
 ) -> impl IntoResponse {
     // UUID of the requester.
     let req_uuid = User::with_key(&key.key, &db).await.unwrap().uuid;
-    let subj_coll: Collection<Subject> = db.collection("subjects");
-    if let Ok(Some(subject)) = subj_coll
-        .find_one(doc! {"uuid": &data.uuid, "created_by": &req_uuid}, None)
+    if let Ok(Some(subject)) = db
+        .subject(&data.uuid)
         .await
+        .map(|s| s.filter(|s| s.created_by == req_uuid))
     {
-        let group_coll: Collection<Group> = db.collection("groups");
-        let result = group_coll
-            .update_many(
-                doc! {"subjects": &data.uuid},
-                doc! {"$pull": {"subjects": &data.uuid}},
-                None,
-            )
-            .await;
+        let result = db.remove_subject_from_groups(&data.uuid).await;
 
         if result.is_ok() {
-            subj_coll
-                .delete_one(
-                    doc! {"uuid": &data.uuid, "created_by": &req_uuid},
-                    None,
-                )
-                .await
-                .unwrap();
+            db.delete_subject(&data.uuid).await.unwrap();
 
             for platform in subject.profiles.keys() {
                 for id in subject.profiles.get(platform).unwrap() {
@@ -69,18 +52,12 @@ pub async fn delete(
             ))
         }
     } else {
-        let group_coll: Collection<Group> = db.collection("groups");
-        if let Ok(Some(_)) = group_coll
-            .find_one(doc! {"uuid": &data.uuid, "created_by": &req_uuid}, None)
+        if let Ok(Some(_)) = db
+            .group(&data.uuid)
             .await
+            .map(|g| g.filter(|g| g.created_by == req_uuid))
         {
-            group_coll
-                .delete_one(
-                    doc! {"uuid": &data.uuid, "created_by": &req_uuid},
-                    None,
-                )
-                .await
-                .unwrap();
+            db.delete_group(&data.uuid).await.unwrap();
             Ok((StatusCode::OK, Json(Ok::new())))
         } else {
             Err((
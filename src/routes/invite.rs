@@ -5,15 +5,13 @@
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/invite/>.
 
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::key::Key;
 use crate::response::{Error, InviteResponse};
 use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use chrono::{DateTime, Utc};
-use mongodb::bson::doc;
-use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 use std::fmt::Write;
 
@@ -22,11 +20,11 @@ pub struct InviteError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Referral {
-    created_by: String,
-    created_at: DateTime<Utc>,
-    code: String,
-    used: bool,
-    used_by: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub code: String,
+    pub used: bool,
+    pub used_by: Option<String>,
 }
 
 impl Referral {
@@ -51,18 +49,17 @@ impl Referral {
     }
 }
 
-pub async fn invite(key: Key, db: DBHandle) -> impl IntoResponse {
+pub async fn invite(key: Key, db: Store) -> impl IntoResponse {
     create_invite(key, &db).await
 }
 
 async fn create_invite(
     key: Key,
-    db: &DBHandle,
+    db: &Store,
 ) -> Result<(StatusCode, Json<InviteResponse>), (StatusCode, Json<Error>)> {
     let referral =
         Referral::new(User::with_key(&key.key, db).await.unwrap().uuid);
-    let refer_coll: Collection<Referral> = db.collection("referrals");
-    refer_coll.insert_one(&referral, None).await.unwrap();
+    db.insert_referral(&referral).await.unwrap();
 
     Ok((StatusCode::OK, Json(InviteResponse::new(referral.code))))
 }
@@ -0,0 +1,136 @@
+//! Route for the leaderboard.
+//!
+//! The /leaderboard route is implemented here.
+//!
+//! Data providers are credited with every queue job they complete and every
+//! piece of data they submitted for it that was stored or merged into
+//! existing content, see [`crate::routes::queue`]. Data that changed nothing
+//! isn't credited, so resubmitting what is already known earns no more than
+//! the job itself. Credits are kept per calendar month (in UTC), so the
+//! leaderboard resets at the start of each month while past months remain
+//! available.
+//!
+//! Without a `period`, the current month is shown. Pass `period=YYYY-MM` for
+//! any other. Every period with credits is listed in `periods`, newest first.
+//!
+//! Providers are ranked by jobs completed and then by data submitted. Equal
+//! providers share a rank.
+
+use crate::data::{DataResult, Ingest};
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, LeaderboardResponse};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// The most providers shown for a period.
+const LEADERBOARD_LIMIT: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Credit {
+    pub period: String,
+    pub user: String, // UUID
+    pub jobs: u64,
+    pub items: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeaderboardEntry {
+    pub rank: u64,
+    pub name: String,
+    pub jobs: u64,
+    pub items: u64,
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    period: Option<String>,
+}
+
+/// The leaderboard period that `at` falls in.
+pub fn period(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+/// Credits `user` with a completed job and every piece of data in `results`
+/// that was inserted or merged, in the period of `at`.
+pub async fn credit(
+    user: &str,
+    results: &[DataResult],
+    at: DateTime<Utc>,
+    db: &Store,
+) {
+    let items = results
+        .iter()
+        .filter(|r| matches!(r.result, Ingest::Inserted | Ingest::Merged))
+        .count();
+    db.record_credit(&period(at), user, 1, items as u64)
+        .await
+        .unwrap();
+}
+
+// Whether `p` is a month written as YYYY-MM, as credits are stored by.
+fn valid_period(p: &str) -> bool {
+    NaiveDate::parse_from_str(&format!("{p}-01"), "%Y-%m-%d")
+        .map_or(false, |d| d.format("%Y-%m").to_string() == p)
+}
+
+pub async fn leaderboard(
+    leaderboard_query: Option<Query<LeaderboardQuery>>,
+    db: Store,
+    _key: Key,
+) -> impl IntoResponse {
+    let leaderboard_query = match leaderboard_query {
+        Some(Query(leaderboard_query)) => leaderboard_query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("Periods must be given as YYYY-MM.")),
+            ));
+        }
+    };
+    let period = match leaderboard_query.period {
+        Some(p) if !valid_period(&p) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("Periods must be given as YYYY-MM.")),
+            ));
+        }
+        Some(p) => p,
+        None => period(Utc::now()),
+    };
+
+    let mut credits = db.credits(&period).await.unwrap();
+    credits.truncate(LEADERBOARD_LIMIT);
+    let uuids: Vec<String> = credits.iter().map(|c| c.user.clone()).collect();
+    let names: HashMap<String, String> = db
+        .users(&uuids)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|u| (u.uuid, u.name))
+        .collect();
+
+    let mut entries: Vec<LeaderboardEntry> = Vec::new();
+    for (i, c) in credits.into_iter().enumerate() {
+        let rank = match entries.last() {
+            Some(l) if (l.jobs, l.items) == (c.jobs, c.items) => l.rank,
+            _ => i as u64 + 1,
+        };
+        entries.push(LeaderboardEntry {
+            rank,
+            name: names.get(&c.user).cloned().unwrap_or(c.user),
+            jobs: c.jobs,
+            items: c.items,
+        });
+    }
+
+    let periods = db.credit_periods().await.unwrap();
+    Ok((
+        StatusCode::OK,
+        Json(LeaderboardResponse::new(period, periods, entries)),
+    ))
+}
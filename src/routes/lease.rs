@@ -0,0 +1,132 @@
+//! Routes for managing queue leases.
+//!
+//! The /queue/heartbeat, /queue/release and /queue/fail routes are implemented
+//! here.
+//!
+//! A data provider that is handed a job by /queue holds a lease on it until it
+//! posts the job's data to /add or the lease expires. In the meantime it can:
+//! - heartbeat, extending the lease by the lease length for the platform.
+//! - release, giving the job back to be handed to someone else straight away.
+//! - fail, reporting why the profile couldn't be fetched. The job is backed
+//!   off and may be quarantined, see [`crate::routes::queue`]. Being rate
+//!   limited backs the job off too, but never quarantines it.
+
+use crate::config::IConfig;
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, FailResponse, LeaseResponse, Ok};
+use crate::routes::queue::{self, FailureReason, InternalQueueItem};
+use crate::user::User;
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeaseData {
+    pub queue_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FailData {
+    pub queue_id: String,
+    pub reason: FailureReason,
+}
+
+pub async fn heartbeat(
+    Json(data): Json<LeaseData>,
+    db: Store,
+    key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let lock_holder = User::with_key(&key.key, &db).await.unwrap().uuid;
+    let q_item = held_queue_item(&data.queue_id, &lock_holder, &db).await?;
+
+    let expires_at = Utc::now() + config.queue.lease(&q_item.platform);
+    if db
+        .extend_lock(&data.queue_id, &lock_holder, expires_at)
+        .await
+        .unwrap()
+    {
+        Ok((
+            StatusCode::OK,
+            Json(LeaseResponse::new(data.queue_id, expires_at)),
+        ))
+    } else {
+        Err(not_held())
+    }
+}
+
+pub async fn release(
+    Json(data): Json<LeaseData>,
+    db: Store,
+    key: Key,
+) -> impl IntoResponse {
+    let lock_holder = User::with_key(&key.key, &db).await.unwrap().uuid;
+    if db
+        .release_queue_item(&data.queue_id, &lock_holder)
+        .await
+        .unwrap()
+    {
+        db.notify_queue();
+        Ok((StatusCode::OK, Json(Ok::new())))
+    } else {
+        Err(not_held())
+    }
+}
+
+pub async fn fail(
+    Json(data): Json<FailData>,
+    db: Store,
+    key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let lock_holder = User::with_key(&key.key, &db).await.unwrap().uuid;
+    let q_item = held_queue_item(&data.queue_id, &lock_holder, &db).await?;
+
+    let failures = q_item.failures + 1;
+    let due_at = Utc::now() + queue::backoff(failures, &config.queue);
+    // Nothing is known about a rate limited profile, so it isn't quarantined.
+    let quarantine = data.reason != FailureReason::RateLimited
+        && failures >= config.queue.quarantine_after;
+    if db
+        .fail_queue_item(
+            &data.queue_id,
+            &lock_holder,
+            data.reason,
+            due_at,
+            quarantine,
+        )
+        .await
+        .unwrap()
+    {
+        db.notify_queue();
+        Ok((
+            StatusCode::OK,
+            Json(FailResponse::new(data.queue_id, due_at, quarantine)),
+        ))
+    } else {
+        Err(not_held())
+    }
+}
+
+async fn held_queue_item(
+    queue_id: &str,
+    lock_holder: &str,
+    db: &Store,
+) -> Result<InternalQueueItem, (StatusCode, Json<Error>)> {
+    db.queue_item(queue_id)
+        .await
+        .unwrap()
+        .filter(|q| q.lock_holder.as_deref() == Some(lock_holder))
+        .ok_or_else(not_held)
+}
+
+fn not_held() -> (StatusCode, Json<Error>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(Error::new(
+            "You do not hold a lease on this job. It may have expired.",
+        )),
+    )
+}
@@ -0,0 +1,91 @@
+//! Route for looking up profiles by username.
+//!
+//! The /lookup route is implemented here.
+//!
+//! Any username a profile has had can be looked up, see [`crate::username`].
+//! Each profile that has used the username is returned along with its current
+//! username and the caller's subjects that track it.
+
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, LookupResponse};
+use crate::subject::Subject;
+use crate::user::User;
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LookupProfile {
+    pub platform_id: String,
+    pub current_username: Option<String>,
+    /// When the profile was first and last seen with the username looked up.
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub subjects: Vec<Subject>,
+}
+
+#[derive(Deserialize)]
+pub struct LookupQuery {
+    platform: String,
+    username: String,
+}
+
+pub async fn lookup(
+    query: Option<Query<LookupQuery>>,
+    db: Store,
+    key: Key,
+) -> impl IntoResponse {
+    let query = match query {
+        Some(Query(query)) => query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("You must provide a platform and a username.")),
+            ))
+        }
+    };
+    let user: User = User::with_key(&key.key, &db).await.unwrap();
+    let subjects = User::subjects(&user, &db).await.unwrap_or_default();
+
+    let owners = db
+        .username_owners(&query.username, &query.platform)
+        .await
+        .unwrap();
+    let mut profiles = Vec::new();
+    for owner in owners {
+        let current_username = db
+            .username(&owner.platform_id, &owner.platform)
+            .await
+            .unwrap();
+        // Subjects may still refer to a profile by username if its ID hasn't
+        // been confirmed yet.
+        let tracking = subjects
+            .iter()
+            .filter(|s| {
+                s.profiles.get(&query.platform).map_or(false, |ids| {
+                    ids.contains(&owner.platform_id)
+                        || ids.contains(&query.username)
+                })
+            })
+            .cloned()
+            .collect();
+        profiles.push(LookupProfile {
+            platform_id: owner.platform_id,
+            current_username,
+            first_seen: owner.first_seen,
+            last_seen: owner.last_seen,
+            subjects: tracking,
+        });
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(LookupResponse::new(
+            query.platform,
+            query.username,
+            profiles,
+        )),
+    ))
+}
@@ -0,0 +1,83 @@
+//! Route for viewing the metrics of a profile or its content.
+//!
+//! The /metrics route is implemented here.
+//!
+//! Returns a series per metric type of the profile, or of a piece of its
+//! content if `content_id` is given, see [`crate::metric`]. Pass `resolution`
+//! as `hourly` or `daily` to downsample the points into buckets, and `from`
+//! and `to` to only include points retrieved between them.
+//!
+//! As in /view, data from untrusted data providers is left out.
+
+use crate::config::IConfig;
+use crate::database::Store;
+use crate::key::Key;
+use crate::metric::{self, MetricPoint, Resolution};
+use crate::reputation;
+use crate::response::{Error, MetricsResponse};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// The maximum number of points read for a query, the latest being kept.
+const METRIC_LIMIT: i64 = 10000;
+
+#[derive(Deserialize)]
+pub struct MetricsQuery {
+    platform: String,
+    id: String,
+    content_id: Option<String>,
+    #[serde(default)]
+    resolution: Resolution,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+pub async fn metrics(
+    query: Option<Query<MetricsQuery>>,
+    db: Store,
+    _key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let query = match query {
+        Some(Query(query)) => query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("You must provide a platform and an id.")),
+            ))
+        }
+    };
+    let mut points = db
+        .metrics(
+            &query.id,
+            &query.platform,
+            query.content_id.as_deref(),
+            query.from,
+            query.to,
+            METRIC_LIMIT,
+        )
+        .await
+        .unwrap();
+
+    let providers: Vec<String> =
+        points.iter().filter_map(|p| p.added_by.clone()).collect();
+    let untrusted =
+        reputation::untrusted(&providers, &db, &config.reputation).await;
+    points.retain(|p: &MetricPoint| {
+        p.added_by.as_ref().map_or(true, |a| !untrusted.contains(a))
+    });
+
+    let series = metric::downsample(&points, query.resolution);
+    Ok((
+        StatusCode::OK,
+        Json(MetricsResponse::new(
+            query.platform,
+            query.id,
+            query.content_id,
+            query.resolution,
+            series,
+        )),
+    ))
+}
@@ -1,15 +1,26 @@
 //! Routes for Axum.
 
 pub mod add;
+pub mod broadcasts;
+pub mod changelog;
 pub mod create;
 pub mod default;
 pub mod delete;
 pub mod frontpage;
 pub mod invite;
+pub mod leaderboard;
+pub mod lease;
 pub mod login;
+pub mod lookup;
+pub mod metrics;
+pub mod publickey;
+pub mod quarantine;
 pub mod queue;
 pub mod register;
+pub mod relationships;
 pub mod reset;
+pub mod stats;
+pub mod submission;
 pub mod types;
 pub mod update;
 pub mod view;
@@ -0,0 +1,47 @@
+//! Route for registering a public key for signed submissions.
+//!
+//! The /publickey route is implemented here.
+//!
+//! Once registered, submissions to /add may be signed with the matching
+//! private key, see [`crate::signature`]. Posting a null key removes it.
+
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, Ok};
+use crate::signature;
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicKeyData {
+    pub public_key: Option<String>,
+}
+
+pub async fn publickey(
+    Json(data): Json<PublicKeyData>,
+    db: Store,
+    key: Key,
+) -> impl IntoResponse {
+    if let Some(public_key) = &data.public_key {
+        if !signature::valid_public_key(public_key) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new(
+                    "Public key must be a hex encoded Ed25519 key.",
+                )),
+            ));
+        }
+    }
+
+    let result = db
+        .set_public_key(&key.key, data.public_key.as_deref())
+        .await;
+    match result {
+        Ok(Some(_)) => Ok((StatusCode::OK, Json(Ok::new()))),
+        _ => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Error::new("Could not set public key. Please try again.")),
+        )),
+    }
+}
@@ -0,0 +1,97 @@
+//! Routes for reviewing quarantined profiles.
+//!
+//! The /queue/quarantine and /queue/restore routes are implemented here.
+//!
+//! Profiles that repeatedly fail to be fetched are quarantined and left out of
+//! the queue, see [`crate::routes::queue`]. Anyone tracking a quarantined
+//! profile under one of their subjects can see it and, once the profile has
+//! been checked (or the subject corrected), restore it to the queue.
+
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, Ok, QuarantineResponse};
+use crate::routes::lease::LeaseData;
+use crate::routes::queue::FailureReason;
+use crate::user::User;
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuarantinedProfile {
+    pub queue_id: String,
+    pub platform: String,
+    pub platform_id: String,
+    pub failures: u32,
+    pub last_failure: Option<FailureReason>,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+pub async fn quarantine(db: Store, key: Key) -> impl IntoResponse {
+    let user = User::with_key(&key.key, &db).await.unwrap();
+    let profiles = tracked_profiles(&user, &db).await;
+
+    let q_items = db.quarantined_queue_items(&profiles).await.unwrap();
+    let profiles = q_items
+        .into_iter()
+        .filter_map(|q| {
+            Some(QuarantinedProfile {
+                queue_id: q.queue_id,
+                platform: q.platform,
+                platform_id: q.platform_id,
+                failures: q.failures,
+                last_failure: q.last_failure,
+                quarantined_at: q.quarantined_at?,
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(QuarantineResponse::new(profiles)))
+}
+
+pub async fn restore(
+    Json(data): Json<LeaseData>,
+    db: Store,
+    key: Key,
+) -> impl IntoResponse {
+    let user = User::with_key(&key.key, &db).await.unwrap();
+    let profiles = tracked_profiles(&user, &db).await;
+
+    let tracked = db.queue_item(&data.queue_id).await.unwrap().filter(|q| {
+        profiles
+            .get(&q.platform)
+            .map_or(false, |ids| ids.contains(&q.platform_id))
+    });
+    if tracked.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(Error::new("None of your subjects track this profile.")),
+        ));
+    }
+
+    if db.restore_queue_item(&data.queue_id).await.unwrap() {
+        db.notify_queue();
+        Ok((StatusCode::OK, Json(Ok::new())))
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("This profile is not quarantined.")),
+        ))
+    }
+}
+
+// Every profile tracked by the user's subjects, by platform.
+async fn tracked_profiles(
+    user: &User,
+    db: &Store,
+) -> HashMap<String, Vec<String>> {
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    for subject in user.subjects(db).await.unwrap_or_default() {
+        for (platform, ids) in subject.profiles {
+            profiles.entry(platform).or_default().extend(ids);
+        }
+    }
+    profiles
+}
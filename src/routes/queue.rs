@@ -1,6 +1,6 @@
 //! Route for the queue.
 //!
-//! See endpoint documentation at 
+//! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/queue/>.
 //!
 //! The queue is a looping structure containing all the profiles currently
@@ -13,11 +13,46 @@
 //! another. This massively increases the system throughput given that each
 //! fetch has an opportunity cost.
 //!
+//! A lock is a lease that expires `lease` seconds after it was taken, or
+//! after the time given for its platform under `[queue.leases]`, since some
+//! platforms take far longer to fetch than others. Expired leases are cleared
+//! by a background task every `reap_interval` seconds (see [`reap_locks`]),
+//! after which the job can be handed to another data provider.
+//!
+//! While working on a job, a data provider can extend its lease, give the job
+//! back or report that the profile couldn't be fetched, see
+//! [`crate::routes::lease`].
+//!
+//! # Failures
+//! A failed job is not due again until an exponential backoff has passed,
+//! starting at `backoff` seconds and doubling with each failure in a row up to
+//! `max_backoff`. A profile that fails `quarantine_after` times in a row is
+//! quarantined when it last failed because it couldn't be found or is
+//! private, and is no longer handed out until it is restored, see
+//! [`crate::routes::quarantine`]. Being rate limited says nothing about the
+//! profile, so it never quarantines it, but it is backed off all the same so
+//! that the platform isn't asked again straight away. Any successfully
+//! completed job clears its failures. Promotion never brings a job with
+//! failures forward, so it still waits out its backoff.
+//!
+//! # Batches
+//! Data providers running many scrapers at once can take up to `max_batch`
+//! jobs in one request with `count`. Each job in the batch has its own lease,
+//! which expires, is extended and is completed independently of the others.
+//!
+//! # Waiting
+//! When no job is available, /queue responds with `NO_JOBS` straight away
+//! unless `wait` is given, in which case the request is held open for up to
+//! that many seconds (capped at `max_wait`) until a job can be handed out.
+//! Waiting requests are woken whenever a job may have become available: a
+//! lease is released or expires, a job fails or is restored, a profile is
+//! promoted or a new profile is tracked.
+//!
 //! # Incentives
 //! Doing jobs in the queue should be preferable to simply posting whatever
-//! data the provider cares to. Ideally there is a leaderboard that awards
-//! points based on work done. This would have to reset monthly to allow for
-//! fair competition to new providers.
+//! data the provider cares to. Every completed job is credited to the data
+//! provider on the leaderboard, which resets monthly to allow for fair
+//! competition to new providers, see [`crate::routes::leaderboard`].
 //!
 //! # Username changes
 //! A fundamental problem with a queue is that we store all our data in terms
@@ -27,9 +62,9 @@
 //! we need to be able to turn an ID into a username that may have changed.
 //! This is assuming that the platform doesn't allow outside lookups by ID.
 //!
-//! The most simple way of doing this is taking the ID from the subject, going
-//! to metadata under our data collection in MongoDB and searching for that
-//! user's most recent 'username' for that platform. Then we trust that a data
+//! The most simple way of doing this is taking the ID from the subject and
+//! looking up that user's most recently seen username for that platform in the
+//! username history (see [`crate::username`]). Then we trust that a data
 //! provider is going to be able to turn a (platform, username) pair into fresh
 //! content/presence/meta data. This will succeed _most_ of the time.
 //!
@@ -62,50 +97,110 @@
 //! queue in order to ensure new hot profiles are still being detected.
 //!
 //! Additionally, profiles under a single subject become hot by association.
+//!
+//! # Scheduling
+//! Every queue item is due at some point in time and only items that are due
+//! are handed out, earliest first. Once processed, an item is next due after
+//! `hot_interval` if it is hot and after `max_staleness` otherwise, both set
+//! under `[queue]` in the configuration file (see
+//! [`QueueConfig`](crate::config::QueueConfig)). New items are due
+//! immediately. Items processed by an untrusted data provider are next due
+//! after `hot_interval` either way, see [`crate::reputation`].
+//!
+//! When new content is added that was created within the last
+//! `promotion_window`, its profile and every other profile of the subjects
+//! tracking it become hot for `hot_decay`. A profile that becomes hot is due
+//! `hot_interval` after it was last processed, so it is brought forward
+//! without waiting out the rest of its cold interval.
+//!
+//! A profile with content suspected deleted also becomes hot, so that the
+//! deletion is confirmed or ruled out soon, see [`crate::tombstone`].
+//!
+//! Since the earliest due item is always handed out first, a cold item that
+//! has been due for longer than a hot one is still processed before it. Hot
+//! profiles are fetched more often but can never hold back the rest of the
+//! queue, so given enough data providers no profile goes unprocessed for much
+//! longer than `max_staleness`.
 
-use crate::data::Data;
-use crate::database::DBHandle;
+use crate::config::{IConfig, QueueConfig};
+use crate::data::{Data, DataResult, Datas, Ingest};
+use crate::database::Store;
 use crate::key::Key;
-use crate::response::{Error, QueueResponse};
-use crate::subject::Subject;
+use crate::reputation;
+use crate::response::{
+    Error, NoJobsResponse, QueueBatchResponse, QueueResponse,
+};
+use crate::tombstone;
 use crate::user::User;
 use crate::utils::deserialise_array::deserialise_array;
 
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 use chrono::offset::TimeZone;
 use chrono::{DateTime, Duration, Utc};
-use mongodb::bson::doc;
-use mongodb::bson::Bson;
-use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions};
-use mongodb::Collection;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::time::{timeout_at, Instant};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct InternalQueueItem {
     pub queue_id: String, // Queue ID.
     pub platform_id: String,
     pub platform: String,
-    pub last_processed: DateTime<Utc>,
-    pub lock_holder: Option<String>, // None means not locked.
+    pub created_at: DateTime<Utc>,
+    pub last_processed: DateTime<Utc>, // The epoch means never processed.
+    pub due_at: DateTime<Utc>,
+    pub hot_until: Option<DateTime<Utc>>, // None means never hot.
+    pub lock_holder: Option<String>,      // None means not locked.
     pub lock_acquired_at: Option<DateTime<Utc>>,
+    pub lock_expires_at: Option<DateTime<Utc>>,
     pub references: u64,
     pub confirmed_id: bool,
+    pub failures: u32, // Failures in a row.
+    pub last_failure: Option<FailureReason>,
+    pub quarantined_at: Option<DateTime<Utc>>, // None means not quarantined.
+}
+
+/// Why a data provider couldn't fetch a profile.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    NotFound,
+    Private,
+    RateLimited,
 }
 
 impl InternalQueueItem {
-    fn new(platform_id: String, platform: String) -> Self {
+    pub fn new(platform_id: String, platform: String) -> Self {
+        let epoch = never();
         Self {
             queue_id: Uuid::new_v4().to_string(),
             platform_id,
             platform,
-            last_processed: Utc.ymd(1970, 1, 1).and_hms(0, 0, 1),
+            created_at: Utc::now(),
+            last_processed: epoch,
+            due_at: epoch,
+            hot_until: None,
             lock_holder: None,
             lock_acquired_at: None,
+            lock_expires_at: None,
             references: 1,
             confirmed_id: false,
+            failures: 0,
+            last_failure: None,
+            quarantined_at: None,
         }
     }
+
+    /// Whether the item has been processed since it was created.
+    pub fn processed(&self) -> bool {
+        self.last_processed > never()
+    }
+}
+
+/// The time new queue items are treated as last processed and due at.
+pub fn never() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 1).unwrap()
 }
 
 // https://github.com/tokio-rs/axum/issues/434#issuecomment-954924025
@@ -114,12 +209,17 @@ impl InternalQueueItem {
 pub struct QueueQuery {
     #[serde(deserialize_with = "deserialise_array")]
     platforms: Vec<String>,
+    // When given, up to this many jobs are returned as a list.
+    count: Option<usize>,
+    // Seconds to wait for a job if none are available straight away.
+    wait: Option<u64>,
 }
 
 pub async fn queue(
     queue_query: Option<Query<QueueQuery>>,
-    db: DBHandle,
+    db: Store,
     key: Key,
+    config: IConfig,
 ) -> impl IntoResponse {
     if queue_query.is_none() {
         return Err((
@@ -129,60 +229,75 @@ pub async fn queue(
             )),
         ));
     }
-    let platforms = &queue_query.unwrap().platforms;
+    let Query(QueueQuery {
+        platforms,
+        count,
+        wait,
+    }) = queue_query.unwrap();
     if platforms.is_empty() {
-        Err((
+        return Err((
             StatusCode::BAD_REQUEST,
             Json(Error::new(
                 "You must specify which platforms you are performing jobs for.",
             )),
-        ))
-    } else {
-        // This is not optimal for performance. 
-        // Should be running as a scheduled task in a thread.
-        clear_old_locks(&db).await;
+        ));
+    }
+    if matches!(count, Some(c) if c == 0 || c > config.queue.max_batch) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new(&format!(
+                "You may take between 1 and {} jobs at once.",
+                config.queue.max_batch
+            ))),
+        ));
+    }
 
-        let filter_builder = FindOneAndUpdateOptions::builder()
-            .sort(doc! {"last_processed": -1_i32});
+    let wait = wait.unwrap_or(0).min(config.queue.max_wait);
+    let deadline = Instant::now() + std::time::Duration::from_secs(wait);
 
-        let filter = filter_builder.build();
+    let lock_holder = User::with_key(&key.key, &db).await.unwrap().uuid;
+    let q_items = loop {
+        // Registered before looking so that a job freed up in between isn't
+        // missed.
+        let changed = db.queue_changed();
 
-        let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-        let result = q_coll
-            .find_one_and_update(
-                doc! {"lock_holder": Bson::Null, "platform": {"$in": &platforms}},
-                doc! {"$set": 
-                                {
-                                "lock_holder": User::with_key(&key.key, &db)
-                                    .await.unwrap().uuid, 
-                                "lock_acquired_at": Utc::now().to_string()
-                                }
-                            },
-                filter,
-            )
+        let now = Utc::now();
+        let leases: HashMap<String, DateTime<Utc>> = platforms
+            .iter()
+            .map(|p| (p.clone(), now + config.queue.lease(p)))
+            .collect();
+        let q_items = db
+            .lock_queue_items(&leases, &lock_holder, count.unwrap_or(1))
             .await
             .unwrap();
-        if let Some(q_item) = result {
-            let username: String =
-                get_username(&q_item.platform_id, &q_item.platform, &db).await;
-
-            Ok((
-                StatusCode::OK,
-                Json(QueueResponse::new(
-                    q_item.queue_id,
-                    username,
-                    q_item.platform,
-                    q_item.platform_id,
-                )),
-            ))
-        } else {
-            Err((
-                StatusCode::OK,
-                Json(Error::new(
-                    "There are no jobs available. Please try again later.",
-                )),
-            ))
+        if !q_items.is_empty() || timeout_at(deadline, changed).await.is_err() {
+            break q_items;
         }
+    };
+    if q_items.is_empty() {
+        return Ok(
+            (StatusCode::OK, Json(NoJobsResponse::new())).into_response()
+        );
+    }
+
+    let mut jobs = Vec::new();
+    for q_item in q_items {
+        let username: String =
+            get_username(&q_item.platform_id, &q_item.platform, &db).await;
+        jobs.push(QueueResponse::new(
+            q_item.queue_id,
+            username,
+            q_item.platform,
+            q_item.platform_id,
+            q_item.lock_expires_at.unwrap(),
+        ));
+    }
+
+    if count.is_some() {
+        Ok((StatusCode::OK, Json(QueueBatchResponse::new(jobs)))
+            .into_response())
+    } else {
+        Ok((StatusCode::OK, Json(jobs.pop().unwrap())).into_response())
     }
 }
 
@@ -192,188 +307,194 @@ pub async fn queue(
 // - Is the queue item's lock held by the user submitting the data?
 // - Does the queue item contain a username instead of a platform id?
 // -
+// Whether the job was completed is returned so that the data provider can be
+// credited on the leaderboard once `data` is stored, see
+// `crate::routes::leaderboard::credit`. Jobs completed by untrusted data
+// providers are due again as if hot, so that someone else checks their work.
+// The content returned is compared with what is stored to detect deletions,
+// see `crate::tombstone`.
 pub async fn process(
     queue_id: &str,
-    id: &str,
-    platform: &str,
-    added_by: &Option<String>,
-    username: Option<&String>,
-    db: &DBHandle,
+    data: &[Data],
+    db: &Store,
+    config: &IConfig,
 ) -> bool {
+    let (id, platform, added_by, username) = Datas::get_info(data);
     let added_by = added_by.as_ref().unwrap();
-    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-    // If this is a metadata update...
-    if let Some(username) = username {
-        let find_result = q_coll
-        .find_one(
-            // It's possible we haven't found an ID for this user yet.
-            doc! {"queue_id" : queue_id, 
-                        "platform": platform, 
-                        "platform_id": &username, 
-                        "lock_holder": added_by, 
-                        "confirmed_id": false},
-            None,
+    // If this is a metadata update, it's possible we haven't found an ID for
+    // this user yet.
+    let unconfirmed = match username {
+        Some(username) => db
+            .queue_item(queue_id)
+            .await
+            .unwrap()
+            .filter(|q| {
+                &q.platform == platform
+                    && &q.platform_id == username
+                    && q.lock_holder.as_ref() == Some(added_by)
+                    && !q.confirmed_id
+            })
+            .is_some(),
+        None => false,
+    };
+    let untrusted = reputation::untrusted(
+        std::slice::from_ref(added_by),
+        db,
+        &config.reputation,
+    )
+    .await;
+    let cold_interval = if untrusted.is_empty() {
+        config.queue.max_staleness
+    } else {
+        config.queue.hot_interval
+    };
+
+    // The job is completed before its item can be swapped out below.
+    let now = Utc::now();
+    let completed = db
+        .complete_queue_item(
+            queue_id,
+            added_by,
+            now + Duration::seconds(config.queue.hot_interval),
+            now + Duration::seconds(cold_interval),
         )
         .await
         .unwrap();
-        // and if so...
-        if find_result.is_some() {
-            // Remove the temporary username queue item...
-            remove_queue_item(username, platform, db).await;
-            // and either merge the username with the already existing queue item
-            // with that name or create a new one with the platform id.
-            add_queue_item(id, platform, db, true).await;
-
-            // and update the subject to use the platform ID instead of a username.
-            let subj_coll: Collection<Subject> = db.collection("subjects");
-            let platform_query_string = format!("profiles.{}", platform);
-            let platform_set_string = format!("profiles.{}.$", platform);
-            let _subj_update_result = subj_coll
-                .update_one(
-                    doc! {&platform_query_string: &username},
-                    doc! {"$set": {&platform_set_string: id}},
-                    None,
-                )
-                .await
-                .unwrap();
+    if let Some(username) = username.filter(|_| unconfirmed) {
+        let q_item = db.queue_item(queue_id).await.unwrap();
+        // Remove the temporary username queue item...
+        remove_queue_item(username, platform, db).await;
+        // and either merge the username with the already existing queue item
+        // with that name or create a new one with the platform id, due when
+        // the completed job would have been.
+        db.add_queue_item(id, platform, true).await.unwrap();
+        if let Some(q_item) = q_item {
+            db.copy_queue_schedule(&q_item, id).await.unwrap();
         }
+
+        // and update the subject to use the platform ID instead of a username.
+        db.replace_profile_id(platform, username, id).await.unwrap();
     }
-    let q_update_result = q_coll
-        .update_one(
-            doc! {"queue_id" : queue_id, "lock_holder": added_by},
-            doc! {"$set": 
-                {"lock_holder": Bson::Null, 
-                "lock_acquired_at": Bson::Null, 
-                "last_processed": Utc::now().to_string()
-                }
-            },
-            None,
-        )
-        .await
-        .unwrap();
-    q_update_result.modified_count == 1
+    if completed {
+        tombstone::detect(id, platform, data, added_by, db, &config.queue)
+            .await;
+    }
+    completed
 }
 
-pub async fn add_queue_item(
-    platform_id: &str,
-    platform: &str,
-    db: &DBHandle,
-    confirmed_id: bool,
+/// Makes every profile with newly added content created within the
+/// promotion window hot, along with every other profile of the subjects
+/// tracking it. `results` are the results of storing `data`, in order.
+pub async fn promote(
+    data: &[Data],
+    results: &[DataResult],
+    db: &Store,
+    config: &QueueConfig,
 ) {
-    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-    let q_item = q_coll
-        .find_one(
-            doc! {"platform_id": platform_id, "platform": platform},
-            None,
-        )
-        .await
-        .unwrap();
-    if q_item.is_some() {
-        q_coll
-            .update_one(
-                doc! {"platform_id": platform_id, "platform": platform},
-                doc! {"$inc": {"references": 1_u32}},
-                None,
-            )
-            .await
-            .unwrap();
-        if confirmed_id {
-            q_coll
-                .update_one(
-                    doc! {"platform_id": platform_id, "platform": platform},
-                    doc! {"$set": {"confirmed_id": true}},
-                    None,
-                )
-                .await
-                .unwrap();
+    let now = Utc::now();
+    let window_start = now - Duration::seconds(config.promotion_window);
+    let mut active = HashSet::new();
+    for (d, r) in data.iter().zip(results) {
+        if let Data::Content {
+            id,
+            platform,
+            created_at,
+            retrieved_at,
+            ..
+        } = d
+        {
+            // Content without a creation time is at least as old as the time
+            // it was retrieved.
+            if r.result == Ingest::Inserted
+                && created_at.unwrap_or(*retrieved_at) >= window_start
+            {
+                active.insert((id, platform));
+            }
         }
-    } else {
-        let q_item: InternalQueueItem = InternalQueueItem::new(
-            platform_id.to_string(),
-            platform.to_string(),
-        );
-        q_coll.insert_one(q_item, None).await.unwrap();
     }
-}
 
-pub async fn remove_queue_item(
-    platform_id: &str,
-    platform: &str,
-    db: &DBHandle,
-) {
-    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-    let result = q_coll
-        .delete_one(
-            doc! {"platform_id": platform_id, 
-                        "platform": platform, 
-                        "references": 1},
-            None,
-        )
-        .await
-        .unwrap();
-    if result.deleted_count == 0 {
-        q_coll
-            .update_one(
-                doc! {"platform_id": platform_id, "platform": platform},
-                doc! {"$inc": {"references": -1_i32}},
-                None,
-            )
+    let mut profiles: HashMap<String, HashSet<String>> = HashMap::new();
+    for (platform_id, platform) in active {
+        let subjects = db
+            .subjects_with_profile(platform_id, platform)
             .await
             .unwrap();
+        for subject in subjects {
+            for (platform, ids) in subject.profiles {
+                profiles.entry(platform).or_default().extend(ids);
+            }
+        }
     }
-}
 
-pub async fn clear_old_locks(db: &DBHandle) {
-    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-    let thirty_seconds_ago: DateTime<Utc> = Utc::now() - Duration::seconds(30);
-    q_coll
-        .update_many(
-            doc! {"lock_acquired_at": {"$lt": thirty_seconds_ago.to_string()}},
-            doc! {"$set": 
-                {"lock_acquired_at": Bson::Null, 
-                "lock_holder": Bson::Null}
-            },
-            None,
+    if !profiles.is_empty() {
+        let profiles: HashMap<String, Vec<String>> = profiles
+            .into_iter()
+            .map(|(platform, ids)| (platform, ids.into_iter().collect()))
+            .collect();
+        db.promote_queue_items(
+            &profiles,
+            now + Duration::seconds(config.hot_decay),
+            Duration::seconds(config.hot_interval),
         )
         .await
         .unwrap();
+        db.notify_queue();
+    }
 }
 
-pub async fn get_username(
+pub async fn add_queue_item(
     platform_id: &str,
     platform: &str,
-    db: &DBHandle,
-) -> String {
-    async fn from_meta(
-        platform_id: &str,
-        platform: &str,
-        db: &DBHandle,
-    ) -> Option<String> {
-        let filter_builder =
-            FindOneOptions::builder().projection(doc! {"username": 1_u32});
-
-        let filter = filter_builder.build();
+    db: &Store,
+    confirmed_id: bool,
+) {
+    db.add_queue_item(platform_id, platform, confirmed_id)
+        .await
+        .unwrap();
+    db.notify_queue();
+}
 
-        #[derive(Debug, Serialize, Deserialize)]
-        struct Username {
-            username: String,
-        }
+pub async fn remove_queue_item(platform_id: &str, platform: &str, db: &Store) {
+    db.remove_queue_item(platform_id, platform).await.unwrap();
+}
 
-        let data_coll: Collection<Data> = db.collection("data");
-        let username = data_coll
-            .clone_with_type::<Username>()
-            .find_one(doc! {"id": &platform_id, "platform": &platform}, filter)
-            .await
-            .unwrap();
+/// How long a profile that has failed `failures` times in a row waits before
+/// it is due again.
+pub fn backoff(failures: u32, config: &QueueConfig) -> Duration {
+    let factor = 2_i64.saturating_pow(failures.saturating_sub(1));
+    Duration::seconds(
+        config
+            .backoff
+            .saturating_mul(factor)
+            .min(config.max_backoff),
+    )
+}
 
-        if let Some(username) = username {
-            Some(username.username)
-        } else {
-            None
+/// Clears expired leases every `reap_interval` seconds, forever. Waiting
+/// requests are woken on every tick, since jobs also become due with time.
+pub async fn reap_locks(db: Store, config: QueueConfig) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        config.reap_interval.max(1),
+    ));
+    loop {
+        interval.tick().await;
+        // An error here must not stop the task, the next tick will retry.
+        match db.expire_locks(Utc::now()).await {
+            Ok(0) => (),
+            Ok(expired) => tracing::info!("Expired {expired} queue leases."),
+            Err(e) => tracing::warn!("Couldn't expire queue leases: {e}"),
         }
+        db.notify_queue();
     }
+}
 
-    from_meta(platform_id, platform, db)
+pub async fn get_username(
+    platform_id: &str,
+    platform: &str,
+    db: &Store,
+) -> String {
+    db.username(platform_id, platform)
         .await
+        .unwrap()
         .unwrap_or_else(|| platform_id.to_string())
 }
@@ -2,17 +2,15 @@
 //!
 //! The /register route is implemented here.
 //!
-//! See endpoint documentation at 
+//! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/register/>.
 
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::response::{Error, RegisterResponse};
 use crate::routes::invite::Referral;
 use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use mongodb::bson::doc;
-use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,7 +26,7 @@ pub struct RegisterError;
 // /register wrt invite_valid and use_invite.
 pub async fn register(
     Json(req): Json<RegisterRequest>,
-    db: DBHandle,
+    db: Store,
 ) -> impl IntoResponse {
     if invite_valid(&req, &db).await && username_not_taken(&req, &db).await {
         let result = register_user(&req, &db).await;
@@ -49,32 +47,24 @@ pub async fn register(
     }
 }
 
-async fn invite_valid(req: &RegisterRequest, db: &DBHandle) -> bool {
-    let refs_coll: Collection<Referral> = db.collection("referrals");
-    let result = refs_coll
-        .find_one(doc! {"code": req.code.as_str(), "used" : false}, None)
-        .await;
+async fn invite_valid(req: &RegisterRequest, db: &Store) -> bool {
+    let result = db.unused_referral(&req.code).await;
     matches!(result, Ok(Some(_)))
 }
 
-async fn username_not_taken(req: &RegisterRequest, db: &DBHandle) -> bool {
-    let users_coll: Collection<User> = db.collection("users");
-    let result = users_coll
-        .find_one(doc! {"user": req.name.as_str()}, None)
-        .await;
+async fn username_not_taken(req: &RegisterRequest, db: &Store) -> bool {
+    let result = db.user_with_name(&req.name).await;
     matches!(result, Ok(None))
 }
 
 async fn register_user(
     req: &RegisterRequest,
-    db: &DBHandle,
+    db: &Store,
 ) -> Result<User, RegisterError> {
     let user = User::new(&req.name);
     let result = use_invite(&user, req, db).await;
     if result.is_ok() {
-        let users_coll: Collection<User> = db.collection("users");
-
-        let result = users_coll.insert_one(&user, None).await;
+        let result = db.insert_user(&user).await;
         match result {
             Ok(_) => Ok(user),
             _ => Err(RegisterError),
@@ -87,17 +77,9 @@ async fn register_user(
 async fn use_invite(
     user: &User,
     req: &RegisterRequest,
-    db: &DBHandle,
+    db: &Store,
 ) -> Result<Referral, RegisterError> {
-    let refs_coll: Collection<Referral> = db.collection("referrals");
-    let result = refs_coll
-        .find_one_and_update(
-            doc! {"code": req.code.as_str(), "used": false},
-            doc! {"$set": {"used": true, "used_by": &user.uuid}},
-            None,
-        )
-        .await
-        .unwrap();
+    let result = db.use_referral(&req.code, &user.uuid).await.unwrap();
     match result {
         Some(entry) => Ok(entry),
         _ => Err(RegisterError),
@@ -0,0 +1,123 @@
+//! Routes for viewing and snapshotting the relationships of a profile.
+//!
+//! The /relationships route is implemented here.
+//!
+//! GET lists who a profile is currently related to and every relationship it
+//! is known to have started or stopped, earliest first, see
+//! [`crate::relationship`]. Both are given per data provider, and each change
+//! as the two snapshots it happened between. Pass `from` and `to` to only
+//! include changes that could have happened between them.
+//!
+//! As with /view, relationships seen by untrusted data providers (see
+//! [`crate::reputation`]) are held back, with the number of edges held back
+//! given. Pass `untrusted=true` to include them.
+//!
+//! POST takes a [`Snapshot`], the complete list of a relation type for a
+//! profile. Relationships it leaves out are taken to have stopped, so only
+//! post complete lists here and add partial ones to /add.
+
+use crate::config::IConfig;
+use crate::database::Store;
+use crate::key::Key;
+use crate::relationship::{self, Edge, Snapshot};
+use crate::reputation;
+use crate::response::{Error, Ok, RelationshipsResponse};
+use crate::user::User;
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct RelationshipsQuery {
+    platform: String,
+    id: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    untrusted: bool,
+}
+
+pub async fn relationships(
+    query: Option<Query<RelationshipsQuery>>,
+    db: Store,
+    _key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let query = match query {
+        Some(Query(query)) => query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("You must provide a platform and an id.")),
+            ))
+        }
+    };
+
+    let mut edges = db.edges(&query.id, &query.platform).await.unwrap();
+    let mut held_back = 0;
+    if !query.untrusted {
+        let providers: Vec<String> =
+            edges.iter().map(|e| e.added_by.clone()).collect();
+        let untrusted =
+            reputation::untrusted(&providers, &db, &config.reputation).await;
+        let before = edges.len();
+        edges.retain(|e| !untrusted.contains(&e.added_by));
+        held_back = (before - edges.len()) as u64;
+    }
+
+    let mut changes = relationship::changes(&edges);
+    changes.retain(|c| c.overlaps(query.from, query.to));
+    let current: Vec<Edge> = edges.into_iter().filter(Edge::current).collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(RelationshipsResponse::new(
+            query.platform,
+            query.id,
+            current,
+            changes,
+            held_back,
+        )),
+    ))
+}
+
+pub async fn snapshot(
+    snapshot: Option<Json<Snapshot>>,
+    db: Store,
+    key: Key,
+    config: IConfig,
+) -> impl IntoResponse {
+    let snapshot = match snapshot {
+        Some(Json(snapshot)) => snapshot,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new(
+                    "You must provide a platform, from_id, relation_type, \
+                    observed_at and to_ids.",
+                )),
+            ))
+        }
+    };
+    let supported = config
+        .relationship_types
+        .get(&snapshot.platform)
+        .map_or(false, |types| types.contains(&snapshot.relation_type));
+    if !supported {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("Unknown platform or relation type.")),
+        ));
+    }
+
+    let user = User::with_key(&key.key, &db).await.unwrap();
+    let data = snapshot.data(&user.uuid);
+    if !data.is_empty() {
+        db.insert_data(&data).await.unwrap();
+    }
+    relationship::observe_snapshot(&snapshot, &user.uuid, &db)
+        .await
+        .unwrap();
+    Ok((StatusCode::OK, Json(Ok::new())))
+}
@@ -5,24 +5,16 @@
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/reset/>.
 
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::key::Key;
 use crate::response::{Error, ResetResponse};
 use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use mongodb::bson::doc;
 
-pub async fn reset(key: Key, db: DBHandle) -> impl IntoResponse {
+pub async fn reset(key: Key, db: Store) -> impl IntoResponse {
     let new_key = User::new_key();
-    let u_coll = db.collection::<User>("users");
-    let result = u_coll
-        .find_one_and_update(
-            doc! {"key": &key.key},
-            doc! { "$set": {"key": &new_key}},
-            None,
-        )
-        .await;
+    let result = db.reset_key(&key.key, &new_key).await;
     match result {
         Ok(Some(_)) => Ok((StatusCode::OK, Json(ResetResponse::new(new_key)))),
         _ => Err((
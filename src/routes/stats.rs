@@ -0,0 +1,325 @@
+//! Route for queue statistics.
+//!
+//! The /queue/stats route is implemented here.
+//!
+//! Without `subjects` or `groups`, the statistics cover the whole queue, and
+//! are counted by the database rather than by loading every queue item.
+//! Given either, they only cover the profiles tracked by those subjects and
+//! the subjects of those groups, and the freshness of each of those profiles
+//! is listed too. Only subjects and groups created by the caller are
+//! counted, others are ignored, so that what others track isn't revealed.
+//!
+//! Staleness is the number of seconds since a profile was last processed.
+//! Profiles that have never been processed have no staleness and are counted
+//! separately rather than skewing the percentiles, with those that have been
+//! waiting the longest listed by platform.
+
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, QueueStatsResponse};
+use crate::routes::queue::InternalQueueItem;
+use crate::user::User;
+use crate::utils::deserialise_array::deserialise_array;
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// The most never processed profiles listed per platform.
+const NEVER_PROCESSED_LIMIT: usize = 10;
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(default, deserialize_with = "deserialise_array")]
+    subjects: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    groups: Vec<String>,
+}
+
+/// How many queue items a platform has in total and in each state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QueueCounts {
+    pub platform: String,
+    pub total: u64,
+    pub locked: u64,
+    pub unconfirmed: u64,
+    pub quarantined: u64,
+    pub never_processed: u64,
+}
+
+impl QueueCounts {
+    pub fn new(platform: &str) -> Self {
+        Self {
+            platform: platform.to_string(),
+            total: 0,
+            locked: 0,
+            unconfirmed: 0,
+            quarantined: 0,
+            never_processed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlatformStats {
+    pub platform: String,
+    pub total: u64,
+    pub locked: u64,
+    pub unconfirmed: u64,
+    pub quarantined: u64,
+    pub never_processed: u64,
+    /// None if no profile on the platform has been processed.
+    pub staleness: Option<Staleness>,
+    /// The never processed profiles that have been waiting the longest.
+    pub oldest_never_processed: Vec<UnprocessedProfile>,
+}
+
+/// Nearest-rank percentiles of staleness, in seconds.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Staleness {
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnprocessedProfile {
+    pub queue_id: String,
+    pub platform_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UnprocessedProfile {
+    fn new(q_item: &InternalQueueItem) -> Self {
+        Self {
+            queue_id: q_item.queue_id.clone(),
+            platform_id: q_item.platform_id.clone(),
+            created_at: q_item.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileFreshness {
+    pub queue_id: String,
+    pub platform: String,
+    pub platform_id: String,
+    /// None if the profile has never been processed.
+    pub last_processed: Option<DateTime<Utc>>,
+    /// Seconds since the profile was last processed.
+    pub staleness: Option<i64>,
+    pub due_at: DateTime<Utc>,
+    pub locked: bool,
+    pub quarantined: bool,
+}
+
+impl ProfileFreshness {
+    fn new(q_item: &InternalQueueItem, now: DateTime<Utc>) -> Self {
+        let last_processed =
+            Some(q_item.last_processed).filter(|_| q_item.processed());
+        Self {
+            queue_id: q_item.queue_id.clone(),
+            platform: q_item.platform.clone(),
+            platform_id: q_item.platform_id.clone(),
+            last_processed,
+            staleness: last_processed.map(|l| (now - l).num_seconds()),
+            due_at: q_item.due_at,
+            locked: q_item.lock_holder.is_some(),
+            quarantined: q_item.quarantined_at.is_some(),
+        }
+    }
+}
+
+pub async fn stats(
+    stats_query: Option<Query<StatsQuery>>,
+    db: Store,
+    key: Key,
+) -> impl IntoResponse {
+    let stats_query = match stats_query {
+        Some(Query(stats_query)) => stats_query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("Subjects and groups must be given as lists.")),
+            ));
+        }
+    };
+    let now = Utc::now();
+    if stats_query.subjects.is_empty() && stats_query.groups.is_empty() {
+        let platforms = queue_stats(&db, now).await;
+        return Ok((
+            StatusCode::OK,
+            Json(QueueStatsResponse::new(platforms, None)),
+        ));
+    }
+
+    let user = User::with_key(&key.key, &db).await.unwrap();
+    let profiles = tracked_profiles(&stats_query, &user.uuid, &db).await;
+    let q_items = db.queue_items(&profiles).await.unwrap();
+    let platforms = platform_stats(&q_items, now);
+    let mut profiles: Vec<ProfileFreshness> = q_items
+        .iter()
+        .map(|q| ProfileFreshness::new(q, now))
+        .collect();
+    // Stalest first, with never processed profiles before any others.
+    profiles.sort_by_key(|p| p.last_processed);
+
+    Ok((
+        StatusCode::OK,
+        Json(QueueStatsResponse::new(platforms, Some(profiles))),
+    ))
+}
+
+// Summarises the whole queue by platform, in platform order, without loading
+// it. Each percentile is read as the queue item with that rank.
+async fn queue_stats(db: &Store, now: DateTime<Utc>) -> Vec<PlatformStats> {
+    let mut platforms = Vec::new();
+    for counts in db.queue_counts().await.unwrap() {
+        let processed = (counts.total - counts.never_processed) as usize;
+        let staleness = if processed > 0 {
+            let platform = counts.platform.as_str();
+            Some(Staleness {
+                p50: nth_staleness(db, platform, rank(processed, 50), now)
+                    .await,
+                p90: nth_staleness(db, platform, rank(processed, 90), now)
+                    .await,
+                p99: nth_staleness(db, platform, rank(processed, 99), now)
+                    .await,
+                max: nth_staleness(db, platform, processed, now).await,
+            })
+        } else {
+            None
+        };
+        let unprocessed = db
+            .unprocessed_queue_items(&counts.platform, NEVER_PROCESSED_LIMIT)
+            .await
+            .unwrap();
+
+        platforms.push(PlatformStats {
+            platform: counts.platform,
+            total: counts.total,
+            locked: counts.locked,
+            unconfirmed: counts.unconfirmed,
+            quarantined: counts.quarantined,
+            never_processed: counts.never_processed,
+            staleness,
+            oldest_never_processed: unprocessed
+                .iter()
+                .map(UnprocessedProfile::new)
+                .collect(),
+        });
+    }
+    platforms
+}
+
+// The staleness of the `n`th least stale processed queue item on `platform`.
+// Zero if the queue changed and there no longer is one.
+async fn nth_staleness(
+    db: &Store,
+    platform: &str,
+    n: usize,
+    now: DateTime<Utc>,
+) -> i64 {
+    db.nth_processed(platform, n as u64)
+        .await
+        .unwrap()
+        .map_or(0, |l| (now - l).num_seconds())
+}
+
+/// Summarises `q_items` by platform, in platform order.
+fn platform_stats(
+    q_items: &[InternalQueueItem],
+    now: DateTime<Utc>,
+) -> Vec<PlatformStats> {
+    let mut by_platform: BTreeMap<&str, Vec<&InternalQueueItem>> =
+        BTreeMap::new();
+    for q_item in q_items {
+        by_platform
+            .entry(&q_item.platform)
+            .or_default()
+            .push(q_item);
+    }
+
+    by_platform
+        .into_iter()
+        .map(|(platform, q_items)| {
+            let count = |f: fn(&InternalQueueItem) -> bool| {
+                q_items.iter().filter(|q| f(q)).count() as u64
+            };
+
+            let mut staleness: Vec<i64> = q_items
+                .iter()
+                .filter(|q| q.processed())
+                .map(|q| (now - q.last_processed).num_seconds())
+                .collect();
+            staleness.sort_unstable();
+
+            let mut unprocessed: Vec<&&InternalQueueItem> =
+                q_items.iter().filter(|q| !q.processed()).collect();
+            unprocessed.sort_by_key(|q| q.created_at);
+
+            PlatformStats {
+                platform: platform.to_string(),
+                total: q_items.len() as u64,
+                locked: count(|q| q.lock_holder.is_some()),
+                unconfirmed: count(|q| !q.confirmed_id),
+                quarantined: count(|q| q.quarantined_at.is_some()),
+                never_processed: unprocessed.len() as u64,
+                staleness: (!staleness.is_empty()).then(|| Staleness {
+                    p50: percentile(&staleness, 50),
+                    p90: percentile(&staleness, 90),
+                    p99: percentile(&staleness, 99),
+                    max: staleness[staleness.len() - 1],
+                }),
+                oldest_never_processed: unprocessed
+                    .into_iter()
+                    .take(NEVER_PROCESSED_LIMIT)
+                    .map(|q| UnprocessedProfile::new(q))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+// The nearest rank of the `p`th percentile of `n` values, counting from 1.
+fn rank(n: usize, p: usize) -> usize {
+    ((p * n + 99) / 100).max(1)
+}
+
+// The nearest-rank percentile of non-empty, ascending `sorted`.
+fn percentile(sorted: &[i64], p: usize) -> i64 {
+    sorted[rank(sorted.len(), p) - 1]
+}
+
+// Every profile tracked by the queried subjects and the subjects of the
+// queried groups, by platform. Only subjects and groups created by `user` are
+// included.
+async fn tracked_profiles(
+    stats_query: &StatsQuery,
+    user: &str,
+    db: &Store,
+) -> HashMap<String, Vec<String>> {
+    let mut uuids: HashSet<String> =
+        stats_query.subjects.iter().cloned().collect();
+    for group in &stats_query.groups {
+        let group = db.group(group).await.unwrap();
+        if let Some(group) = group.filter(|g| g.created_by == user) {
+            uuids.extend(group.subjects);
+        }
+    }
+    let uuids: Vec<String> = uuids.into_iter().collect();
+
+    let mut profiles: HashMap<String, HashSet<String>> = HashMap::new();
+    let subjects = db.subjects(&uuids).await.unwrap();
+    for subject in subjects.into_iter().filter(|s| s.created_by == user) {
+        for (platform, ids) in subject.profiles {
+            profiles.entry(platform).or_default().extend(ids);
+        }
+    }
+    profiles
+        .into_iter()
+        .map(|(platform, ids)| (platform, ids.into_iter().collect()))
+        .collect()
+}
@@ -0,0 +1,44 @@
+//! Route for fetching signed submissions.
+//!
+//! The /submission route is implemented here.
+//!
+//! Stored data signed by its data provider refers to a submission by UUID.
+//! The submission holds everything a third party needs to check the signature
+//! themselves, see [`crate::signature`].
+
+use crate::database::Store;
+use crate::key::Key;
+use crate::response::{Error, SubmissionResponse};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct SubmissionQuery {
+    uuid: String,
+}
+
+pub async fn submission(
+    query: Option<Query<SubmissionQuery>>,
+    db: Store,
+    _key: Key,
+) -> impl IntoResponse {
+    let query = match query {
+        Some(Query(query)) => query,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(Error::new("You must provide a submission UUID.")),
+            ))
+        }
+    };
+    match db.submission(&query.uuid).await.unwrap() {
+        Some(submission) => {
+            Ok((StatusCode::OK, Json(SubmissionResponse::new(submission))))
+        }
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(Error::new("No such submission.")),
+        )),
+    }
+}
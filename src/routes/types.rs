@@ -4,6 +4,10 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/types/>.
+//!
+//! Any rules declared for a type under `[content_schemas]` or
+//! `[presence_schemas]` are published alongside it, see
+//! [`crate::config::TypeSchema`].
 
 use crate::config::IConfig;
 use crate::response::TypesResponse;
@@ -11,7 +15,7 @@ use crate::response::TypesResponse;
 use axum::{http::StatusCode, response::IntoResponse, Json};
 
 pub async fn types(config: IConfig) -> impl IntoResponse {
-    let resp = TypesResponse::new(config.content_types, config.presence_types);
+    let resp = TypesResponse::new(config);
 
     (StatusCode::OK, Json(resp))
 }
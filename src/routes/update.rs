@@ -5,7 +5,7 @@
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/register/>.
 
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::group::Group;
 use crate::key::Key;
 use crate::response::{Error, Ok};
@@ -14,8 +14,6 @@ use crate::subject::*;
 use crate::user::User;
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use mongodb::bson::doc;
-use mongodb::{bson, Collection};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -38,7 +36,7 @@ pub enum UpdateData {
 
 pub async fn update(
     Json(data): Json<UpdateData>,
-    db: DBHandle,
+    db: Store,
     key: Key,
 ) -> impl IntoResponse {
     match data {
@@ -51,7 +49,7 @@ pub async fn update(
 
 async fn update_subject(
     data: &UpdateData,
-    db: &DBHandle,
+    db: &Store,
     key: &Key,
 ) -> Result<(StatusCode, Json<Ok>), (StatusCode, Json<Error>)> {
     let (uuid, name, profiles, description) = match data {
@@ -64,10 +62,10 @@ async fn update_subject(
         _ => panic!("Expected UpdateSubject."),
     };
     let req_uuid = User::with_key(&key.key, db).await.unwrap().uuid;
-    let subj_coll: Collection<Subject> = db.collection("subjects");
-    if let Ok(Some(subject)) = subj_coll
-        .find_one(doc! {"uuid": &uuid, "created_by": &req_uuid}, None)
+    if let Ok(Some(subject)) = db
+        .subject(uuid)
         .await
+        .map(|s| s.filter(|s| s.created_by == req_uuid))
     {
         let mut old_profiles: Vec<(&String, &String)> = Vec::new();
         for platform in subject.profiles.keys() {
@@ -98,18 +96,14 @@ async fn update_subject(
             queue::remove_queue_item(id, platform, db).await;
         }
 
-        subj_coll
-            .update_one(
-                doc! {"uuid": &uuid, "created_by": &req_uuid},
-                doc! {"$set":
-                    {"name": name,
-                    "profiles": bson::to_bson(&profiles).unwrap(),
-                    "description": description}
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        db.update_subject(&Subject {
+            name: name.to_string(),
+            profiles: profiles.clone(),
+            description: description.clone(),
+            ..subject.clone()
+        })
+        .await
+        .unwrap();
         Ok((StatusCode::OK, Json(Ok::new())))
     } else {
         Err((
@@ -123,7 +117,7 @@ async fn update_subject(
 
 async fn update_group(
     data: &UpdateData,
-    db: &DBHandle,
+    db: &Store,
     key: &Key,
 ) -> Result<(StatusCode, Json<Ok>), (StatusCode, Json<Error>)> {
     let (uuid, name, subjects, description) = match data {
@@ -136,15 +130,13 @@ async fn update_group(
         _ => panic!("Expected UpdateGroup."),
     };
     let req_uuid = User::with_key(&key.key, db).await.unwrap().uuid;
-    let group_coll: Collection<Group> = db.collection("groups");
-    if let Ok(Some(_)) = group_coll
-        .find_one(doc! {"uuid": &uuid, "created_by": &req_uuid}, None)
+    if let Ok(Some(group)) = db
+        .group(uuid)
         .await
+        .map(|g| g.filter(|g| g.created_by == req_uuid))
     {
-        let subj_coll: Collection<Subject> = db.collection("subjects");
         for s in subjects {
-            let subject =
-                subj_coll.find_one(doc! {"uuid": s}, None).await.unwrap();
+            let subject = db.subject(s).await.unwrap();
             if subject.is_none() {
                 return Err((
                     StatusCode::BAD_REQUEST,
@@ -154,18 +146,14 @@ async fn update_group(
                 ));
             }
         }
-        group_coll
-            .update_one(
-                doc! {"uuid": &uuid, "created_by": &req_uuid},
-                doc! {"$set":
-                    {"name": name,
-                    "subjects": bson::to_bson(&subjects).unwrap(),
-                    "description": description}
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        db.update_group(&Group {
+            name: name.to_string(),
+            subjects: subjects.clone(),
+            description: description.clone(),
+            ..group
+        })
+        .await
+        .unwrap();
         Ok((StatusCode::OK, Json(Ok::new())))
     } else {
         Err((
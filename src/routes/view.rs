@@ -4,21 +4,56 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/view/>.
+//!
+//! Pass `revisions=true` to include the revision chain of each piece of
+//! content, see [`crate::revision`].
+//!
+//! Data from untrusted data providers (see [`crate::reputation`]) is held
+//! back, with the number of items held back given for each profile. Pass
+//! `untrusted=true` to include it.
+//!
+//! Where data providers disagree, metadata fields and whether content is
+//! deleted are shown as agreed on (see [`crate::consensus`]) rather than as
+//! last retrieved. Anything short of unanimous is listed under `disputes`
+//! with the dissenting values.
+//!
+//! Content suspected or confirmed deleted from repeated fetches (see
+//! [`crate::tombstone`]) is listed under `tombstones`, leaving out suspicions
+//! raised by untrusted data providers. Pass `deleted=true` to return only
+//! content that is deleted or suspected deleted, however long ago it was
+//! retrieved.
+//!
+//! Presence is also joined into sessions (see [`crate::session`]), listed
+//! under `sessions`. Pass `from` and `to` to only include sessions that could
+//! have taken place between them, e.g. to see when a subject was live last
+//! week. Only presence in or within a gap of the range is joined, so sessions
+//! running past either end are cut short there.
 
+use crate::changelog::{meta_changes, MetaChange};
+use crate::config::IConfig;
+use crate::consensus::{
+    agreed_meta, meta_consensus, status_consensus, Consensus, StatusClaim,
+};
 use crate::data::Data;
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::key::Key;
+use crate::reputation::{self, added_by_of};
 use crate::response::{Error, ViewResponse};
+use crate::revision::{ContentHistory, Revision};
+use crate::session::{self, Session};
 use crate::subject::Subject;
+use crate::tombstone::Tombstone;
 use crate::utils::deserialise_array::deserialise_array;
 
 use axum::{extract::Query, http::StatusCode, Json};
-use mongodb::bson::doc;
-use mongodb::bson::Document;
-use mongodb::options::FindOptions;
-use mongodb::Collection;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tokio_stream::StreamExt;
+use std::collections::HashSet;
+
+// The maximum number of content and presence items returned per profile.
+const VIEW_LIMIT: i64 = 100;
+// The maximum number of presence items per profile joined into sessions.
+const SESSION_LIMIT: i64 = 1000;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ViewData {
@@ -70,6 +105,16 @@ struct ProfileData {
     meta: Option<Data>,
     content: Vec<Data>,
     presence: Vec<Data>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sessions: Vec<Session>,
+    changes: Vec<MetaChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<RevisionData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    disputes: Vec<Dispute>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<Tombstone>,
+    held_back: u64,
 }
 
 impl ProfileData {
@@ -78,6 +123,51 @@ impl ProfileData {
             meta,
             content: Vec::new(),
             presence: Vec::new(),
+            sessions: Vec::new(),
+            changes: Vec::new(),
+            revisions: Vec::new(),
+            disputes: Vec::new(),
+            tombstones: Vec::new(),
+            held_back: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RevisionData {
+    content_id: String,
+    content_type: String,
+    revisions: Vec<Revision>,
+}
+
+impl RevisionData {
+    fn new(history: ContentHistory) -> Self {
+        Self {
+            content_id: history.content_id.clone(),
+            content_type: history.content_type.clone(),
+            revisions: history.chain(),
+        }
+    }
+}
+
+// A disagreement about a metadata field or, if it has a content ID, about
+// whether content is deleted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Dispute {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(flatten)]
+    consensus: Consensus,
+}
+
+impl Dispute {
+    fn new(content: Option<(&String, &String)>, consensus: Consensus) -> Self {
+        Self {
+            content_id: content.map(|(id, _)| id.clone()),
+            content_type: content.map(|(_, content_type)| content_type.clone()),
+            consensus,
         }
     }
 }
@@ -86,12 +176,21 @@ impl ProfileData {
 pub struct ViewQuery {
     #[serde(deserialize_with = "deserialise_array")]
     subjects: Vec<String>,
+    #[serde(default)]
+    revisions: bool,
+    #[serde(default)]
+    untrusted: bool,
+    #[serde(default)]
+    deleted: bool,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
 }
 
 pub async fn view(
     view_query: Option<Query<ViewQuery>>,
-    db: DBHandle,
+    db: Store,
     _key: Key,
+    config: IConfig,
 ) -> Result<(StatusCode, Json<ViewResponse>), (StatusCode, Json<Error>)> {
     if view_query.is_none() {
         return Err((
@@ -100,22 +199,10 @@ pub async fn view(
         ));
     }
 
-    let subjects = &view_query.unwrap().subjects;
-
-    let data_coll: Collection<Data> = db.collection("data");
-    let filter_builder = FindOptions::builder()
-        .limit(100)
-        .sort(doc! {"retrieved_at": -1_i32})
-        .batch_size(100);
-    let filter = filter_builder.build();
+    let view_query = view_query.unwrap();
+    let subjects = &view_query.subjects;
 
-    let subj_coll: Collection<Subject> = db.collection("subjects");
-    let doc: Document = doc! {"uuid": {"$in": &subjects}};
-    let subj_cursor = subj_coll.find(doc, None).await.unwrap();
-    let results: Vec<Result<Subject, mongodb::error::Error>> =
-        subj_cursor.collect().await;
-    let subjects: Vec<Subject> =
-        results.into_iter().map(|d| d.unwrap()).collect();
+    let subjects: Vec<Subject> = db.subjects(subjects).await.unwrap();
 
     let mut view_data = ViewData::new();
 
@@ -125,48 +212,214 @@ pub async fn view(
             let mut platform_data =
                 PlatformData::new(platform_name.to_string());
             for platform_id in s.profiles.get(platform_name).unwrap() {
-                let f = filter.clone();
-                let meta_data = data_coll
-                    .find_one(
-                        doc! {"id": &platform_id,
-                            "platform": &platform_name,
-                            "profile_picture": {"$exists": true}
-                        },
+                let mut metas = db
+                    .metas(platform_id, platform_name, None, VIEW_LIMIT)
+                    .await
+                    .unwrap();
+                let mut presence = db
+                    .presence(
+                        platform_id,
+                        platform_name,
                         None,
+                        None,
+                        VIEW_LIMIT,
                     )
                     .await
                     .unwrap();
-                let mut profile_data: ProfileData = ProfileData::new(meta_data);
-
-                let presence_cursor = data_coll
-                    .find(
-                        doc! {"id": &platform_id,
-                            "platform": &platform_name,
-                            "presence_type": {"$exists": true}
-                        },
-                        f.clone(),
+                let mut statuses =
+                    db.statuses(platform_id, platform_name).await.unwrap();
+                let mut tombstones =
+                    db.tombstones(platform_id, platform_name).await.unwrap();
+                let mut content = if view_query.deleted {
+                    // Anything claimed or suspected deleted, however old.
+                    let mut ids: Vec<(String, String)> = statuses
+                        .iter()
+                        .filter(|s| s.deleted)
+                        .map(|s| (s.content_type.clone(), s.content_id.clone()))
+                        .chain(tombstones.iter().map(|t| {
+                            (t.content_type.clone(), t.content_id.clone())
+                        }))
+                        .collect();
+                    ids.sort();
+                    ids.dedup();
+                    db.content_with_ids(
+                        platform_id,
+                        platform_name,
+                        &ids,
+                        VIEW_LIMIT,
                     )
                     .await
-                    .unwrap();
-                let presence_data: Vec<Result<Data, mongodb::error::Error>> =
-                    presence_cursor.collect().await;
-                profile_data.presence =
-                    presence_data.into_iter().map(|d| d.unwrap()).collect();
-
-                let content_cursor = data_coll
-                    .find(
-                        doc! {"id": &platform_id,
-                            "platform": &platform_name,
-                            "content_type": {"$exists": true}
-                        },
-                        f.clone(),
+                    .unwrap()
+                } else {
+                    db.content(platform_id, platform_name, VIEW_LIMIT)
+                        .await
+                        .unwrap()
+                };
+
+                let mut held_back = 0;
+                let mut untrusted = HashSet::new();
+                if !view_query.untrusted {
+                    let providers: Vec<String> = metas
+                        .iter()
+                        .chain(&presence)
+                        .chain(&content)
+                        .filter_map(|d| added_by_of(d).clone())
+                        .chain(statuses.iter().map(|s| s.added_by.clone()))
+                        .chain(
+                            tombstones.iter().map(|t| t.suspected_by.clone()),
+                        )
+                        .chain(
+                            tombstones
+                                .iter()
+                                .filter_map(|t| t.confirmed_by.clone()),
+                        )
+                        .collect();
+                    untrusted = reputation::untrusted(
+                        &providers,
+                        &db,
+                        &config.reputation,
+                    )
+                    .await;
+                    for data in [&mut metas, &mut presence, &mut content] {
+                        let before = data.len();
+                        data.retain(|d| {
+                            added_by_of(d)
+                                .as_ref()
+                                .map_or(true, |a| !untrusted.contains(a))
+                        });
+                        held_back += (before - data.len()) as u64;
+                    }
+                    statuses.retain(|s| !untrusted.contains(&s.added_by));
+                    // Suspicions are only as good as whoever raised them, and
+                    // untrusted confirmations leave them only suspected.
+                    tombstones.retain(|t| !untrusted.contains(&t.suspected_by));
+                    for t in &mut tombstones {
+                        if t.confirmed_by
+                            .as_ref()
+                            .map_or(false, |c| untrusted.contains(c))
+                        {
+                            t.confirmed_at = None;
+                            t.confirmed_by = None;
+                        }
+                    }
+                }
+
+                // Metadata is the latest trusted, with any agreed fields.
+                let consensus = meta_consensus(&metas, &config.consensus);
+                let meta_data =
+                    metas.last().cloned().map(|m| agreed_meta(m, &consensus));
+                let mut disputes: Vec<Dispute> = consensus
+                    .into_iter()
+                    .filter(Consensus::disputed)
+                    .map(|c| Dispute::new(None, c))
+                    .collect();
+
+                for c in &mut content {
+                    if let Data::Content {
+                        content_id,
+                        content_type,
+                        deleted,
+                        ..
+                    } = c
+                    {
+                        let claims: Vec<&StatusClaim> = statuses
+                            .iter()
+                            .filter(|s| {
+                                &s.content_id == content_id
+                                    && &s.content_type == content_type
+                            })
+                            .collect();
+                        let consensus =
+                            status_consensus(&claims, &config.consensus);
+                        if let Some(consensus) = consensus {
+                            if consensus.agreed {
+                                *deleted = consensus.value.as_bool();
+                            }
+                            if consensus.disputed() {
+                                disputes.push(Dispute::new(
+                                    Some((content_id, content_type)),
+                                    consensus,
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                if view_query.deleted {
+                    content.retain(|c| match c {
+                        Data::Content {
+                            content_id,
+                            content_type,
+                            deleted,
+                            ..
+                        } => {
+                            *deleted == Some(true)
+                                || tombstones
+                                    .iter()
+                                    .any(|t| t.is_for(content_id, content_type))
+                        }
+                        _ => false,
+                    });
+                }
+
+                // Sessions are joined from further back than presence is
+                // shown. Observations up to a gap outside the range can still
+                // belong to sessions overlapping it.
+                let gap = config.sessions.max_gap();
+                let observations: Vec<Data> = db
+                    .presence(
+                        platform_id,
+                        platform_name,
+                        view_query.from.map(|from| from - gap),
+                        view_query.to.map(|to| to + gap),
+                        SESSION_LIMIT,
                     )
                     .await
-                    .unwrap();
-                let content_data: Vec<Result<Data, mongodb::error::Error>> =
-                    content_cursor.collect().await;
-                profile_data.content =
-                    content_data.into_iter().map(|d| d.unwrap()).collect();
+                    .unwrap()
+                    .into_iter()
+                    .filter(|d| {
+                        added_by_of(d)
+                            .as_ref()
+                            .map_or(true, |a| !untrusted.contains(a))
+                    })
+                    .collect();
+                let mut sessions =
+                    session::sessions(&observations, &config.sessions);
+                sessions.retain(|s| s.overlaps(view_query.from, view_query.to));
+
+                let mut profile_data: ProfileData = ProfileData::new(meta_data);
+                profile_data.presence = presence;
+                profile_data.sessions = sessions;
+                profile_data.content = content;
+                profile_data.changes = meta_changes(&metas);
+                profile_data.disputes = disputes;
+                profile_data.tombstones = tombstones;
+                profile_data.held_back = held_back;
+
+                if view_query.revisions {
+                    for c in &profile_data.content {
+                        if let Data::Content {
+                            content_id,
+                            content_type,
+                            ..
+                        } = c
+                        {
+                            let history = db
+                                .content_history(
+                                    content_id,
+                                    platform_name,
+                                    content_type,
+                                )
+                                .await
+                                .unwrap();
+                            if let Some(history) = history {
+                                profile_data
+                                    .revisions
+                                    .push(RevisionData::new(history));
+                            }
+                        }
+                    }
+                }
 
                 platform_data.profiles.push(profile_data);
             }
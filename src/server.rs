@@ -4,17 +4,28 @@
 
 use crate::config::IConfig;
 use crate::database;
-use crate::database::DBPool;
+use crate::database::Store;
 use crate::response::Error;
 use crate::routes::add::*;
+use crate::routes::broadcasts::*;
+use crate::routes::changelog::*;
 use crate::routes::create::*;
 use crate::routes::default::*;
 use crate::routes::frontpage::*;
 use crate::routes::invite::*;
+use crate::routes::leaderboard::*;
+use crate::routes::lease::*;
 use crate::routes::login::*;
+use crate::routes::lookup::*;
+use crate::routes::metrics::*;
+use crate::routes::publickey::*;
+use crate::routes::quarantine::*;
 use crate::routes::queue::*;
 use crate::routes::register::*;
+use crate::routes::relationships::*;
 use crate::routes::reset::*;
+use crate::routes::stats::*;
+use crate::routes::submission::*;
 use crate::routes::types::*;
 use crate::routes::update::*;
 use crate::routes::view::*;
@@ -41,10 +52,12 @@ use tracing_subscriber::{prelude::*, EnvFilter};
 pub async fn build_server(
     config: &IConfig,
 ) -> (Router, RustlsConfig, SocketAddr) {
-    let db_pool = database::open(config).await.unwrap();
-    tracing::info!("Connected to MongoDB.");
+    let store = database::open(config).await.unwrap();
 
-    let app = build_app(config.clone(), db_pool);
+    tokio::spawn(reap_locks(store.clone(), config.queue.clone()));
+    tracing::info!("Queue lease reaper started.");
+
+    let app = build_app(config.clone(), store);
 
     tracing::info!("Application built.");
 
@@ -56,6 +69,14 @@ pub async fn build_server(
     (app, tls_config, addr)
 }
 
+/// Logs the migrations that have been applied and those that would be applied
+/// on the next start, without running them.
+pub async fn report_migrations(config: &IConfig) {
+    let store = database::connect(config).await.unwrap();
+    let report = database::migrations::report(&*store).await.unwrap();
+    report.log();
+}
+
 pub fn build_tracing() {
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
@@ -63,36 +84,45 @@ pub fn build_tracing() {
         .init();
 }
 
-fn build_app(config: IConfig, db_pool: DBPool) -> Router {
+pub fn build_app(config: IConfig, store: Store) -> Router {
+    // Requests to /queue may be held open for up to max_wait seconds, so only
+    // it is given longer than the rest.
+    let queue_timeout = Duration::from_secs(5 + config.queue.max_wait);
     let service_builder = ServiceBuilder::new()
         .layer(middleware::from_fn(error_transformer))
-        .layer(HandleErrorLayer::new(|error: BoxError| async move {
-            if error.is::<tower::timeout::error::Elapsed>() {
-                Ok(StatusCode::REQUEST_TIMEOUT)
-            } else {
-                Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(Error::new("Internal server error.")),
-                ))
-            }
-        }))
         .layer(Extension(config))
-        .layer(Extension(db_pool))
+        .layer(Extension(store))
         .layer(SetResponseHeaderLayer::overriding(
             header::SERVER,
             HeaderValue::from_static("instrumentality"),
-        ))
-        // .layer(from_extractor::<ContentLengthLimit<(), 10_000_000>>())
-        // Need a content length limit, but this breaks integration tests.
-        // <()... doesn't remove headers but breaks POSTs and vice versa.
-        .timeout(Duration::from_secs(5));
+        ));
+    // .layer(from_extractor::<ContentLengthLimit<(), 10_000_000>>())
+    // Need a content length limit, but this breaks integration tests.
+    // <()... doesn't remove headers but breaks POSTs and vice versa.
+
+    let waiting = Router::new().route("/queue", get(queue)).layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_error))
+            .timeout(queue_timeout),
+    );
 
     Router::new()
         .route("/", get(frontpage))
         .route("/types", get(types))
         .route("/login", get(login))
         .route("/view", get(view))
-        .route("/queue", get(queue))
+        .route("/changelog", get(changelog))
+        .route("/broadcasts", get(broadcasts))
+        .route("/relationships", get(relationships).post(snapshot))
+        .route("/metrics", get(metrics))
+        .route("/lookup", get(lookup))
+        .route("/queue/heartbeat", post(heartbeat))
+        .route("/queue/release", post(release))
+        .route("/queue/fail", post(fail))
+        .route("/queue/quarantine", get(quarantine))
+        .route("/queue/restore", post(restore))
+        .route("/queue/stats", get(stats))
+        .route("/leaderboard", get(leaderboard))
         .route("/invite", get(invite))
         .route("/register", post(register))
         .route("/create", post(create))
@@ -100,10 +130,31 @@ fn build_app(config: IConfig, db_pool: DBPool) -> Router {
         .route("/update", post(update))
         .route("/add", post(add))
         .route("/reset", get(reset))
+        .route("/publickey", post(publickey))
+        .route("/submission", get(submission))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_error))
+                .timeout(Duration::from_secs(5)),
+        )
+        .merge(waiting)
         .layer(service_builder)
         .fallback(default.into_service())
 }
 
+async fn handle_error(
+    error: BoxError,
+) -> Result<StatusCode, (StatusCode, Json<Error>)> {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        Ok(StatusCode::REQUEST_TIMEOUT)
+    } else {
+        Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Error::new("Internal server error.")),
+        ))
+    }
+}
+
 fn build_address(address: &str, port: &str) -> SocketAddr {
     format!("{}:{}", address, port).parse().unwrap()
 }
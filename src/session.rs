@@ -0,0 +1,193 @@
+//! Presence sessions reconstructed from observations.
+//!
+//! Presence is made up of discrete observations of continuous behaviour (see
+//! [`crate::data`]), so a profile being live for an hour shows up as however
+//! many times data providers happened to look. Observations of the same
+//! presence type no more than `gap` seconds apart are joined into a
+//! [`Session`], on the assumption that nobody looked away for long enough to
+//! miss the profile leaving and coming back.
+//!
+//! A session can't be known exactly. The profile was certainly present at its
+//! first and last observations and is assumed to have been in between, giving
+//! `start` and `end`. It may have been present for up to `gap` either side of
+//! them, giving `earliest_start` and `latest_end`. A `latest_end` in the
+//! future means the session may still be going.
+//!
+//! The gap is set under `[sessions]` in the configuration file, and can be
+//! set for particular presence types under `[sessions.gaps]`, since how often
+//! a type is observed varies.
+
+use crate::config::SessionConfig;
+use crate::data::Data;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub presence_type: String,
+    /// The first observation.
+    pub start: DateTime<Utc>,
+    /// The last observation.
+    pub end: DateTime<Utc>,
+    /// The earliest the session could have started.
+    pub earliest_start: DateTime<Utc>,
+    /// The latest the session could have ended.
+    pub latest_end: DateTime<Utc>,
+    pub observations: u64,
+}
+
+impl Session {
+    fn new(
+        presence_type: &str,
+        retrieved_at: DateTime<Utc>,
+        config: &SessionConfig,
+    ) -> Self {
+        let gap = config.gap(presence_type);
+        Self {
+            presence_type: presence_type.to_string(),
+            start: retrieved_at,
+            end: retrieved_at,
+            earliest_start: retrieved_at - gap,
+            latest_end: retrieved_at + gap,
+            observations: 1,
+        }
+    }
+
+    /// Whether the session could have taken place at any point between
+    /// `from` and `to`. Either may be left open.
+    pub fn overlaps(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> bool {
+        from.map_or(true, |from| self.latest_end >= from)
+            && to.map_or(true, |to| self.earliest_start <= to)
+    }
+}
+
+/// The sessions made up by `presence`, a profile's presence observations in
+/// any order, earliest first. Data other than [`Data::Presence`] is ignored.
+pub fn sessions(presence: &[Data], config: &SessionConfig) -> Vec<Session> {
+    let mut observations: BTreeMap<&str, Vec<DateTime<Utc>>> = BTreeMap::new();
+    for p in presence {
+        if let Data::Presence {
+            presence_type,
+            retrieved_at,
+            ..
+        } = p
+        {
+            observations
+                .entry(presence_type)
+                .or_default()
+                .push(*retrieved_at);
+        }
+    }
+
+    let mut sessions = Vec::new();
+    for (presence_type, mut times) in observations {
+        times.sort();
+        let gap = config.gap(presence_type);
+        let mut current: Option<Session> = None;
+        for t in times {
+            match &mut current {
+                Some(session) if t - session.end <= gap => {
+                    session.end = t;
+                    session.latest_end = t + gap;
+                    session.observations += 1;
+                }
+                _ => {
+                    sessions.extend(current.take());
+                    current = Some(Session::new(presence_type, t, config));
+                }
+            }
+        }
+        sessions.extend(current);
+    }
+    sessions.sort_by_key(|s| s.start);
+    sessions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use std::collections::HashMap;
+
+    fn presence(presence_type: &str, minute: i64) -> Data {
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        Data::Presence {
+            id: "1".to_string(),
+            platform: "PLATFORM_3".to_string(),
+            presence_type: presence_type.to_string(),
+            retrieved_at: start + Duration::minutes(minute),
+            added_by: None,
+            added_at: None,
+            submission: None,
+        }
+    }
+
+    fn config() -> SessionConfig {
+        let mut gaps = HashMap::new();
+        gaps.insert("listening_now".to_string(), 60);
+        SessionConfig { gap: 600, gaps }
+    }
+
+    #[test]
+    fn test_sessions() {
+        let presence = vec![
+            presence("streaming", 25),
+            presence("streaming", 0),
+            presence("streaming", 10),
+            presence("streaming", 33),
+            presence("listening_now", 0),
+            presence("listening_now", 5),
+        ];
+        let sessions = sessions(&presence, &config());
+        assert_eq!(sessions.len(), 4);
+
+        let streaming: Vec<&Session> = sessions
+            .iter()
+            .filter(|s| s.presence_type == "streaming")
+            .collect();
+        assert_eq!(streaming.len(), 2);
+        assert_eq!(streaming[0].observations, 2);
+        assert_eq!(
+            streaming[0].end - streaming[0].start,
+            Duration::minutes(10)
+        );
+        assert_eq!(
+            streaming[0].start - streaming[0].earliest_start,
+            Duration::minutes(10)
+        );
+        assert_eq!(streaming[1].observations, 2);
+        assert_eq!(
+            streaming[1].latest_end - streaming[1].end,
+            Duration::minutes(10)
+        );
+
+        let listening = sessions
+            .iter()
+            .filter(|s| s.presence_type == "listening_now")
+            .count();
+        assert_eq!(listening, 2);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let sessions = sessions(&[presence("streaming", 60)], &config());
+        let at = |minute| {
+            Some(
+                Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap()
+                    + Duration::minutes(minute),
+            )
+        };
+
+        assert!(sessions[0].overlaps(None, None));
+        assert!(sessions[0].overlaps(at(65), None));
+        assert!(sessions[0].overlaps(None, at(55)));
+        assert!(!sessions[0].overlaps(at(71), at(80)));
+        assert!(!sessions[0].overlaps(at(0), at(49)));
+    }
+}
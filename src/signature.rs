@@ -0,0 +1,124 @@
+//! Signed submissions from data providers.
+//!
+//! Data is normally attributed to a data provider only through the API key it
+//! was posted with, so anyone reading it must trust the server operator. A data
+//! provider can instead register an Ed25519 public key with /publickey and sign
+//! each submission to /add, sending the hex encoded signature of the request
+//! body in the `X-SIGNATURE` header.
+//!
+//! A valid signature is stored alongside the exact body that was signed as a
+//! [`Submission`], and every item stored from it refers to the submission by
+//! its UUID. To confirm that a data provider vouched for a record, a third
+//! party fetches the submission from /submission, checks the signature against
+//! the data provider's public key and finds the record within the body. The
+//! public key is kept with the submission as it was when signed, but a third
+//! party should compare it with one they obtained from the data provider
+//! rather than trusting the server for it.
+//!
+//! Content merged with data from another submission, signed or not, stops
+//! referring to a submission, see [`crate::data::Data::merge`].
+//!
+//! Signing is optional. Unsigned submissions are stored as before, with no
+//! submission.
+
+use chrono::{DateTime, Utc};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// The length of an Ed25519 public key in bytes.
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub uuid: String,
+    pub added_by: String, // UUID
+    pub added_at: DateTime<Utc>,
+    /// The hex encoded public key the submission was signed with.
+    pub public_key: String,
+    /// The hex encoded signature of `body`.
+    pub signature: String,
+    /// The request body exactly as it was signed.
+    pub body: String,
+}
+
+impl Submission {
+    pub fn new(
+        added_by: &str,
+        public_key: &str,
+        signature: &str,
+        body: &str,
+    ) -> Self {
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            added_by: added_by.to_string(),
+            added_at: Utc::now(),
+            public_key: public_key.to_string(),
+            signature: signature.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    /// Whether the signature is valid for the body under the public key.
+    pub fn verify(&self) -> bool {
+        verify(&self.public_key, self.body.as_bytes(), &self.signature)
+    }
+}
+
+/// Whether `public_key` is a hex encoded Ed25519 public key.
+pub fn valid_public_key(public_key: &str) -> bool {
+    matches!(hex::decode(public_key), Ok(k) if k.len() == PUBLIC_KEY_LENGTH)
+}
+
+/// Whether the hex encoded `signature` of `message` is valid under the hex
+/// encoded Ed25519 `public_key`.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> bool {
+    match (hex::decode(public_key), hex::decode(signature)) {
+        (Ok(public_key), Ok(signature)) => {
+            UnparsedPublicKey::new(&ED25519, public_key)
+                .verify(message, &signature)
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 =
+            Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_valid_public_key() {
+        let public_key = hex::encode(key_pair().public_key());
+
+        assert!(valid_public_key(&public_key));
+        assert!(!valid_public_key(&public_key[2..]));
+        assert!(!valid_public_key("not a key"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let key_pair = key_pair();
+        let public_key = hex::encode(key_pair.public_key());
+        let body = r#"{"data":[],"queue_id":null}"#;
+        let signature = hex::encode(key_pair.sign(body.as_bytes()));
+
+        let submission = Submission::new("user", &public_key, &signature, body);
+        assert!(submission.verify());
+
+        let forged = Submission {
+            body: r#"{"data":[]}"#.to_string(),
+            ..submission
+        };
+        assert!(!forged.verify());
+        assert!(!verify(&public_key, body.as_bytes(), "00"));
+    }
+}
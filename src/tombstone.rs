@@ -0,0 +1,249 @@
+//! Detecting deleted content from repeated profile fetches.
+//!
+//! Data providers rarely see content being deleted, they only see that it is
+//! no longer there. When a queue job is completed (see
+//! [`crate::routes::queue`]), the content it returned is taken as everything
+//! the profile had of each content type between the oldest and newest
+//! creation times returned for that type. Stored content of the profile
+//! created within that window but missing from the job is suspected deleted
+//! and gets a [`Tombstone`].
+//!
+//! A single fetch can miss content for reasons that have nothing to do with
+//! deletion, so a suspected deletion makes the profile hot (see
+//! [`crate::routes::queue#scheduling`]) and is confirmed by the next job that
+//! also finds it missing, as long as it was fetched after the suspicion was
+//! raised. With `independent_confirmation` set under `[queue]`, that job must
+//! also have been completed by a different data provider than the one that
+//! raised the suspicion, so that no one data provider can have content marked
+//! deleted on their own.
+//!
+//! Confirming a tombstone marks the content deleted and records a
+//! [`StatusClaim`] from the confirming data provider, so that it takes part in
+//! consensus like any other observation. Content that turns up again before
+//! its tombstone is confirmed clears it.
+//!
+//! Content without a creation time can't be placed in a window and is never
+//! suspected deleted. Neither is content of a type the job returned none of.
+
+use crate::config::QueueConfig;
+use crate::consensus::StatusClaim;
+use crate::data::Data;
+use crate::database::Store;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// The number of a profile's most recently retrieved content compared against
+// each job.
+const DETECTION_LIMIT: i64 = 100;
+
+/// Content suspected or confirmed to have been deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    pub content_id: String,
+    pub platform: String,
+    pub content_type: String,
+    pub platform_id: String,
+    /// When the content was first found missing.
+    pub suspected_at: DateTime<Utc>,
+    pub suspected_by: String, // UUID
+    /// When a later job found the content still missing.
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub confirmed_by: Option<String>, // UUID
+}
+
+impl Tombstone {
+    pub fn new(content: &Data, suspected_by: &str) -> Option<Self> {
+        match content {
+            Data::Content {
+                id,
+                platform,
+                content_type,
+                content_id,
+                ..
+            } => Some(Self {
+                content_id: content_id.clone(),
+                platform: platform.clone(),
+                content_type: content_type.clone(),
+                platform_id: id.clone(),
+                suspected_at: Utc::now(),
+                suspected_by: suspected_by.to_string(),
+                confirmed_at: None,
+                confirmed_by: None,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    pub fn is_for(&self, content_id: &str, content_type: &str) -> bool {
+        self.content_id == content_id && self.content_type == content_type
+    }
+}
+
+/// Compares the content returned by a completed queue job for the profile
+/// `platform_id` on `platform` with what is stored, suspecting, confirming
+/// and clearing tombstones as described above. `added_by` is the data
+/// provider that completed the job.
+pub async fn detect(
+    platform_id: &str,
+    platform: &str,
+    data: &[Data],
+    added_by: &str,
+    db: &Store,
+    config: &QueueConfig,
+) {
+    let mut windows: HashMap<&str, (DateTime<Utc>, DateTime<Utc>)> =
+        HashMap::new();
+    let mut returned = HashSet::new();
+    let mut observed_at = None;
+    for d in data {
+        if let Data::Content {
+            id,
+            platform: p,
+            content_type,
+            content_id,
+            created_at,
+            retrieved_at,
+            ..
+        } = d
+        {
+            if id != platform_id || p != platform {
+                continue;
+            }
+            returned.insert((content_type.as_str(), content_id.as_str()));
+            observed_at = observed_at.max(Some(*retrieved_at));
+            if let Some(created_at) = created_at {
+                let window = windows
+                    .entry(content_type)
+                    .or_insert((*created_at, *created_at));
+                window.0 = window.0.min(*created_at);
+                window.1 = window.1.max(*created_at);
+            }
+        }
+    }
+    let observed_at = match observed_at {
+        Some(observed_at) => observed_at,
+        None => return,
+    };
+
+    let tombstones = db.tombstones(platform_id, platform).await.unwrap();
+    for t in &tombstones {
+        let reappeared = returned
+            .contains(&(t.content_type.as_str(), t.content_id.as_str()));
+        if reappeared && !t.confirmed() {
+            db.remove_tombstone(&t.content_id, &t.platform, &t.content_type)
+                .await
+                .unwrap();
+        }
+    }
+
+    let stored = db
+        .content(platform_id, platform, DETECTION_LIMIT)
+        .await
+        .unwrap();
+    let mut suspected = false;
+    for content in stored {
+        let (content_type, content_id, created_at) = match &content {
+            Data::Content {
+                content_type,
+                content_id,
+                created_at: Some(created_at),
+                deleted,
+                ..
+            } if *deleted != Some(true) => {
+                (content_type.clone(), content_id.clone(), *created_at)
+            }
+            _ => continue,
+        };
+        let in_window = windows
+            .get(content_type.as_str())
+            .map_or(false, |(start, end)| {
+                *start <= created_at && created_at <= *end
+            });
+        if !in_window
+            || returned.contains(&(content_type.as_str(), content_id.as_str()))
+        {
+            continue;
+        }
+
+        match tombstones
+            .iter()
+            .find(|t| t.is_for(&content_id, &content_type))
+        {
+            None => {
+                let tombstone = Tombstone::new(&content, added_by).unwrap();
+                db.record_tombstone(&tombstone).await.unwrap();
+                suspected = true;
+            }
+            Some(t)
+                if !t.confirmed()
+                    && observed_at > t.suspected_at
+                    && !(config.independent_confirmation
+                        && t.suspected_by == added_by) =>
+            {
+                let tombstone = Tombstone {
+                    confirmed_at: Some(Utc::now()),
+                    confirmed_by: Some(added_by.to_string()),
+                    ..t.clone()
+                };
+                db.record_tombstone(&tombstone).await.unwrap();
+                confirm(content, added_by, observed_at, db).await;
+            }
+            Some(_) => (),
+        }
+    }
+
+    // Bring the follow-up job forward rather than waiting out the interval.
+    if suspected {
+        let mut profiles = HashMap::new();
+        profiles.insert(platform.to_string(), vec![platform_id.to_string()]);
+        db.promote_queue_items(
+            &profiles,
+            Utc::now() + Duration::seconds(config.hot_decay),
+            Duration::seconds(config.hot_interval),
+        )
+        .await
+        .unwrap();
+        db.notify_queue();
+    }
+}
+
+// Marks content deleted as observed by `added_by` at `observed_at`. No
+// submission said so, so the content no longer refers to one, see
+// `Data::merge`.
+async fn confirm(
+    mut content: Data,
+    added_by: &str,
+    observed_at: DateTime<Utc>,
+    db: &Store,
+) {
+    if let Data::Content {
+        id,
+        platform,
+        content_type,
+        content_id,
+        deleted,
+        submission,
+        ..
+    } = &mut content
+    {
+        *deleted = Some(true);
+        *submission = None;
+        let claim = StatusClaim {
+            content_id: content_id.clone(),
+            platform: platform.clone(),
+            content_type: content_type.clone(),
+            platform_id: id.clone(),
+            added_by: added_by.to_string(),
+            deleted: true,
+            retrieved_at: observed_at,
+        };
+        db.record_status(&claim).await.unwrap();
+    }
+    db.merge_content(&content).await.unwrap();
+}
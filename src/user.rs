@@ -1,13 +1,11 @@
 //! Basic user concepts for Instrumentality.
 
-use crate::database::DBHandle;
+use crate::database::Store;
 use crate::group::Group;
 use crate::subject::Subject;
 
-use mongodb::{bson::doc, Collection, Cursor};
 use serde::{Deserialize, Serialize};
 use std::fmt::Write;
-use tokio_stream::StreamExt;
 use uuid::Uuid;
 
 #[derive(Eq, PartialEq, Clone, Debug, Deserialize, Serialize)]
@@ -16,6 +14,10 @@ pub struct User {
     pub name: String,
     pub key: String,
     pub banned: bool,
+    /// Hex encoded Ed25519 key for signed submissions, see
+    /// [`crate::signature`].
+    #[serde(default)]
+    pub public_key: Option<String>,
 }
 
 impl User {
@@ -25,6 +27,7 @@ impl User {
             name: name.to_string(),
             key: Self::new_key(),
             banned: false,
+            public_key: None,
         }
     }
 
@@ -38,17 +41,8 @@ impl User {
         key
     }
 
-    pub async fn subjects(&self, db: &DBHandle) -> Option<Vec<Subject>> {
-        let subj_coll: Collection<Subject> = db.collection("subjects");
-        let cursor: Cursor<Subject> = subj_coll
-            .find(doc! {"created_by": &self.uuid}, None)
-            .await
-            .unwrap();
-
-        let results: Vec<Result<Subject, mongodb::error::Error>> =
-            cursor.collect().await;
-        let subjects: Vec<Subject> =
-            results.into_iter().map(|d| d.unwrap()).collect();
+    pub async fn subjects(&self, db: &Store) -> Option<Vec<Subject>> {
+        let subjects = db.subjects_created_by(&self.uuid).await.unwrap();
         if subjects.is_empty() {
             None
         } else {
@@ -56,17 +50,8 @@ impl User {
         }
     }
 
-    pub async fn groups(&self, db: &DBHandle) -> Option<Vec<Group>> {
-        let group_coll: Collection<Group> = db.collection("groups");
-        let cursor: Cursor<Group> = group_coll
-            .find(doc! {"created_by": &self.uuid}, None)
-            .await
-            .unwrap();
-
-        let results: Vec<Result<Group, mongodb::error::Error>> =
-            cursor.collect().await;
-        let groups: Vec<Group> =
-            results.into_iter().map(|d| d.unwrap()).collect();
+    pub async fn groups(&self, db: &Store) -> Option<Vec<Group>> {
+        let groups = db.groups_created_by(&self.uuid).await.unwrap();
         if groups.is_empty() {
             None
         } else {
@@ -74,11 +59,8 @@ impl User {
         }
     }
 
-    pub async fn with_key(key: &str, db: &DBHandle) -> Option<Self> {
-        let users_coll: Collection<User> = db.collection("users");
-        let result =
-            users_coll.find_one(doc! {"key": key}, None).await.unwrap();
-        result
+    pub async fn with_key(key: &str, db: &Store) -> Option<Self> {
+        db.user_with_key(key).await.unwrap()
     }
 }
 
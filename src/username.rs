@@ -0,0 +1,46 @@
+//! Username history.
+//!
+//! Profiles are tracked by platform ID, but data providers generally need a
+//! username to fetch a profile and usernames change. Every username seen in a
+//! [`Data::Meta`](crate::data::Data::Meta) is recorded against its profile
+//! with when it was first and last seen, so that the queue can hand out the
+//! most recent username and any past username can be traced back to the
+//! profile that used it.
+//!
+//! Usernames are often released and reused, so one username may have
+//! belonged to several profiles.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UsernameRecord {
+    pub platform: String,
+    pub platform_id: String,
+    pub username: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl UsernameRecord {
+    pub fn new(
+        platform_id: &str,
+        platform: &str,
+        username: &str,
+        retrieved_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            platform: platform.to_string(),
+            platform_id: platform_id.to_string(),
+            username: username.to_string(),
+            first_seen: retrieved_at,
+            last_seen: retrieved_at,
+        }
+    }
+
+    /// Widens the time the username was seen to include `retrieved_at`.
+    pub fn observe(&mut self, retrieved_at: DateTime<Utc>) {
+        self.first_seen = self.first_seen.min(retrieved_at);
+        self.last_seen = self.last_seen.max(retrieved_at);
+    }
+}
@@ -2,53 +2,83 @@
 //! doing. Using it twice will not create another user. Use the given user
 //! to create an invite via /invite and register that user.
 //!
-//! `setup_client` starts instrumentality, which will connect to mongodb on
-//! startup and check if the user collection is empty (shorthand for is this
-//! a fresh database). If so, a root account is created as are some indexes.
+//! `setup_server` opens the storage backend named in the test config, which
+//! runs any pending migrations (creating the indexes) and then checks if the
+//! user collection is empty (shorthand for is this a fresh database). If so, a
+//! root account is created. Set `backend = "memory"` under `[settings]` to run
+//! the tests without MongoDB or PostgreSQL.
+//!
+//! Every backend must behave the same, so the suite should pass against each
+//! of them. Setting `INSTRUMENTALITY_TEST_BACKEND` to "mongodb", "postgresql"
+//! or "memory" overrides the backend in the test config, for example:
+//! `INSTRUMENTALITY_TEST_BACKEND=postgresql cargo test`. The config must still
+//! have a `[mongodb]` or `[postgres]` section for the backend chosen.
 //!
 //! It is VITAL that you do not call `inject_test_account` before setting up
-//! the client. If you do this, the indexes enforcing uniqueness on collections
-//! will not be created and your test environment will yield subtly different
-//! outcomes making debugging difficult.
+//! the client. If you do this, no root account will be created and your test
+//! environment will yield subtly different outcomes making debugging
+//! difficult.
+
+// Every test binary includes this module but only uses some of it.
+#![allow(dead_code)]
+
 use instrumentality::config;
 use instrumentality::config::IConfig;
+use instrumentality::data::{Data, Datas};
 use instrumentality::database;
-use instrumentality::response::LoginResponse;
+use instrumentality::database::Store;
+use instrumentality::response::{AddResponse, CreateResponse, LoginResponse};
+use instrumentality::routes::create::CreateData;
 use instrumentality::server;
 use instrumentality::user::User;
 
 use axum::Router;
 use hyper::{Body, Request, StatusCode};
+use serde_json::Value;
+use std::collections::HashMap;
 use tower::Service;
 use uuid::Uuid;
 
 pub const TEST_ENVIRONMENT_CONFIG: &str = "InstrumentalityTest.toml";
+pub const TEST_BACKEND_VAR: &str = "INSTRUMENTALITY_TEST_BACKEND";
 
 pub struct Environment {
     pub app: Router,
     pub user: User,
     pub config: IConfig,
+    pub store: Store,
 }
 
 impl Environment {
     pub async fn new(config_path: &str) -> Self {
         let mut config = config::open(config_path).unwrap();
+        if let Ok(backend) = std::env::var(TEST_BACKEND_VAR) {
+            config.settings.backend =
+                serde_json::from_value(Value::String(backend)).unwrap();
+        }
         let test_db_id = Uuid::new_v4().to_string();
-        config.mongodb.database = test_db_id.clone();
-        let app = Self::setup_server(&config).await;
+        if let Some(mongodb) = config.mongodb.as_mut() {
+            mongodb.database = test_db_id.clone();
+        }
+        if let Some(postgres) = config.postgres.as_mut() {
+            postgres.schema = Some(test_db_id.clone());
+        }
+        let (app, store) = Self::setup_server(&config).await;
 
-        let user = Self::inject_test_account(&config).await;
+        let user = Self::inject_test_account(&store).await;
 
-        Self { app, user, config }
+        Self {
+            app,
+            user,
+            config,
+            store,
+        }
     }
 
     pub async fn cleanup(self) {
-        let database = database::open(&self.config).await.unwrap();
-        database::drop_database(&database.handle()).await;
+        database::drop_database(&self.store).await;
     }
 
-    // This is only used in tests, so it flags as dead code.
-    #[allow(dead_code)]
     pub async fn login(&mut self) -> LoginResponse {
         let res = self
             .app
@@ -71,24 +101,167 @@ impl Environment {
         lr
     }
 
-    // Provides a client to process requests to Instrumentality without going over
-    // a network.
-    async fn setup_server(iconfig: &IConfig) -> Router {
-        let (app, _, _) = server::build_server(iconfig).await;
+    // Sends `request` to the app, returning the status and the JSON body.
+    pub async fn call(
+        &mut self,
+        request: Request<Body>,
+    ) -> (StatusCode, Value) {
+        let res = self.app.call(request).await.unwrap();
+        let status = res.status();
 
-        app
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
     }
 
-    async fn inject_test_account(iconfig: &IConfig) -> User {
-        let database = database::open(&iconfig).await.unwrap();
+    pub async fn add(&mut self, data: Vec<Data>) -> AddResponse {
+        let key = self.user.key.clone();
+        self.add_as(&key, data, None).await
+    }
 
-        let user = User::new("test");
-        let _user_coll = database
-            .handle()
-            .collection::<User>("users")
-            .insert_one(&user, None)
+    // Posts `data` to /add for the job `queue_id`, if any, as the user with
+    // `key`.
+    pub async fn add_as(
+        &mut self,
+        key: &str,
+        data: Vec<Data>,
+        queue_id: Option<String>,
+    ) -> AddResponse {
+        let datas = Datas { data, queue_id };
+        let (status, ar) = self
+            .call(
+                Request::builder()
+                    .method("POST")
+                    .header("X-API-KEY", key)
+                    .header(
+                        axum::http::header::CONTENT_TYPE,
+                        mime::APPLICATION_JSON.as_ref(),
+                    )
+                    .uri("/add")
+                    .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_value(ar).unwrap()
+    }
+
+    pub async fn get(&mut self, uri: &str) -> Value {
+        let key = self.user.key.clone();
+        self.get_as(&key, uri).await
+    }
+
+    // GETs `uri` as the user with `key`, expecting it to succeed.
+    pub async fn get_as(&mut self, key: &str, uri: &str) -> Value {
+        let (status, body) = self.try_get_as(key, uri).await;
+
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    // GETs `uri`, returning the status and the JSON body whether or not it
+    // succeeds.
+    pub async fn try_get(&mut self, uri: &str) -> (StatusCode, Value) {
+        let key = self.user.key.clone();
+        self.try_get_as(&key, uri).await
+    }
+
+    // GETs `uri` as the user with `key`, returning the status and the JSON
+    // body whether or not it succeeds.
+    pub async fn try_get_as(
+        &mut self,
+        key: &str,
+        uri: &str,
+    ) -> (StatusCode, Value) {
+        self.call(
+            Request::builder()
+                .method("GET")
+                .uri(uri)
+                .header("X-API-KEY", key)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+    }
+
+    // Posts `body` to /add as is, for data that can't be built from `Data`.
+    pub async fn add_json(&mut self, body: &Value) -> (StatusCode, Value) {
+        self.post("/add", body).await
+    }
+
+    // Posts `body` to `uri`, returning the status and the JSON body whether
+    // or not it succeeds.
+    pub async fn post(
+        &mut self,
+        uri: &str,
+        body: &Value,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("X-API-KEY", &self.user.key)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                mime::APPLICATION_JSON.as_ref(),
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        self.call(request).await
+    }
+
+    // The first profile of the first subject in /view for `query`.
+    pub async fn view_profile(&mut self, query: &str) -> Value {
+        let vr = self.get(&format!("/view?{query}")).await;
+        vr["view_data"]["subject_data"][0]["platforms"][0]["profiles"][0]
+            .clone()
+    }
+
+    pub async fn create_subject(
+        &mut self,
+        name: &str,
+        profiles: HashMap<String, Vec<String>>,
+    ) -> String {
+        let new_subject = CreateData::CreateSubject {
+            name: name.to_string(),
+            profiles,
+            description: None,
+        };
+        let res = self
+            .app
+            .call(
+                Request::builder()
+                    .method("POST")
+                    .uri("/create")
+                    .header("X-API-KEY", &self.user.key)
+                    .header(
+                        axum::http::header::CONTENT_TYPE,
+                        mime::APPLICATION_JSON.as_ref(),
+                    )
+                    .body(Body::from(serde_json::to_vec(&new_subject).unwrap()))
+                    .unwrap(),
+            )
             .await
             .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+        cr.uuid
+    }
+
+    // Provides a client to process requests to Instrumentality without going over
+    // a network.
+    async fn setup_server(iconfig: &IConfig) -> (Router, Store) {
+        let store = database::open(iconfig).await.unwrap();
+        let app = server::build_app(iconfig.clone(), store.clone());
+
+        (app, store)
+    }
+
+    async fn inject_test_account(store: &Store) -> User {
+        let user = User::new("test");
+        store.insert_user(&user).await.unwrap();
         user
     }
 }
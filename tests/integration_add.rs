@@ -1,50 +1,344 @@
-// //! Ensure you have read the doc comments in common.rs if you are having
-// //! difficulty getting tests to work.
-
-// mod common;
-// use common::Environment;
-// use common::TEST_ENVIRONMENT_CONFIG;
-
-// use axum::http::StatusCode;
-// use hyper::Body;
-// use hyper::Request;
-// use tower::Service;
-
-// /// test_authorised_add tests:
-// /// - Authentication of the test user works as expected.
-// /// - Upon receiving valid data the add route returns:
-// ///     - an OK.
-// #[tokio::test]
-// async fn test_authorised_add() {
-//     use instrumentality::data::Data;
-//     use instrumentality::response::Ok;
-
-//     let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
-
-//     let data = Data::Content {};
-
-//     let res = env
-//         .app
-//         .call(
-//             Request::builder()
-//                 .method("POST")
-//                 .header("X-API-KEY", &env.user.key)
-//                 .header(
-//                     axum::http::header::CONTENT_TYPE,
-//                     mime::APPLICATION_JSON.as_ref(),
-//                 )
-//                 .uri("/add")
-//                 .body(Body::from(serde_json::to_vec(&data).unwrap()))
-//                 .unwrap(),
-//         )
-//         .await
-//         .unwrap();
-
-//     assert_eq!(res.status(), StatusCode::OK);
-
-//     let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
-//     let ok: Ok = serde_json::from_slice(&body).unwrap();
-
-//     assert_eq!(ok.response, "OK".to_string());
-//     env.cleanup().await;
-// }
+//! Ensure you have read the doc comments in common.rs if you are having
+//! difficulty getting tests to work.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::response::AddResponse;
+use instrumentality::server;
+
+use axum::http::StatusCode;
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+fn content(body: Option<&str>, deleted: Option<bool>) -> Data {
+    Data::Content {
+        id: "123456789".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+        content_id: "987654321".to_string(),
+        deleted,
+        retrieved_from: None,
+        created_at: None,
+        body: body.map(|b| b.to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+/// test_add_merges_content tests:
+/// - New content is inserted.
+/// - Resubmitted content only has missing fields filled in.
+/// - Content can become deleted.
+/// - Resubmitting known content changes nothing.
+#[tokio::test]
+async fn test_add_merges_content() {
+    use instrumentality::data::Ingest;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let ar = env.add(vec![content(None, None)]).await;
+    assert_eq!(ar.response, "OK".to_string());
+    assert_eq!(ar.results[0].result, Ingest::Inserted);
+    assert_eq!(ar.results[0].content_id, Some("987654321".to_string()));
+
+    let ar = env.add(vec![content(Some("Hello."), Some(true))]).await;
+    assert_eq!(ar.results[0].result, Ingest::Merged);
+
+    let ar = env.add(vec![content(Some("Edited."), Some(false))]).await;
+    assert_eq!(ar.results[0].result, Ingest::Unchanged);
+
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    let stored = stored.unwrap();
+    assert_eq!(stored.len(), 1);
+    match &stored[0] {
+        Data::Content { body, deleted, .. } => {
+            assert_eq!(body, &Some("Hello.".to_string()));
+            assert_eq!(deleted, &Some(true));
+        }
+        _ => panic!("Expected Data::Content."),
+    }
+
+    env.cleanup().await;
+}
+
+/// test_add_duplicate_content_in_batch tests:
+/// - The same content twice in one submission is stored once, the second is
+///   rejected as a duplicate and the batch is not lost.
+#[tokio::test]
+async fn test_add_duplicate_content_in_batch() {
+    use instrumentality::data::{Ingest, Rejection};
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let data = vec![content(None, None), content(None, None)];
+    let ar = env.add(data).await;
+    let results: Vec<Ingest> = ar.results.iter().map(|r| r.result).collect();
+    assert_eq!(results, vec![Ingest::Inserted, Ingest::Rejected]);
+    assert_eq!(ar.results[1].rejection, Some(Rejection::Duplicate));
+
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    assert_eq!(stored.unwrap().len(), 1);
+
+    env.cleanup().await;
+}
+
+/// test_add_rejections tests:
+/// - Every submitted item gets a result, in order.
+/// - Unknown platforms and types, items for another job, malformed timestamps
+///   and malformed items are rejected with their own codes.
+/// - Submissions with no accepted items are not acceptable but still list
+///   every rejection.
+#[tokio::test]
+async fn test_add_rejections() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["123456789".to_string()]);
+    env.create_subject("test", profiles).await;
+    let job = env.get("/queue?platforms=[PLATFORM_1]").await;
+
+    let valid = serde_json::to_value(content(Some("Hello."), None)).unwrap();
+    let mut unknown_platform = valid.clone();
+    unknown_platform["platform"] = json!("PLATFORM_0");
+    let mut unknown_type = valid.clone();
+    unknown_type["content_type"] = json!("video");
+    let mut wrong_job = valid.clone();
+    wrong_job["platform"] = json!("PLATFORM_2");
+    wrong_job["content_type"] = json!("scrobble");
+    let mut malformed_timestamp = valid.clone();
+    malformed_timestamp["retrieved_at"] = json!("yesterday");
+    let malformed = json!({"id": "123456789", "platform": "PLATFORM_1"});
+
+    let body = json!({
+        "data": [
+            unknown_platform,
+            valid,
+            unknown_type,
+            wrong_job,
+            malformed_timestamp,
+            malformed,
+        ],
+        "queue_id": job["queue_id"],
+    });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::OK);
+    let ar: AddResponse = serde_json::from_value(ar).unwrap();
+    let results: Vec<Value> = ar
+        .results
+        .iter()
+        .map(|r| json!([r.result, r.rejection]))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            json!(["rejected", "unknown_platform"]),
+            json!(["inserted", null]),
+            json!(["rejected", "unknown_type"]),
+            json!(["rejected", "wrong_job"]),
+            json!(["rejected", "malformed_timestamp"]),
+            json!(["rejected", "malformed"]),
+        ]
+    );
+    assert_eq!(ar.results[5].id, "123456789");
+
+    let body = json!({"data": [malformed], "queue_id": null});
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(ar["results"][0]["rejection"], "malformed");
+
+    env.cleanup().await;
+}
+
+/// test_add_schema tests:
+/// - Content breaking the rules declared for its type is rejected with a code
+///   for the rule it breaks.
+/// - Content following them is stored.
+/// - Schemas with misspelt rules fail to load.
+#[tokio::test]
+async fn test_add_schema() {
+    use instrumentality::config::TypeSchema;
+    use instrumentality::data::Rejection;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let schema = toml::from_str(
+        r#"
+        required = ["body"]
+        optional = ["media", "references"]
+        references = ["reply_to"]
+        max_media = 1
+        id = "^[0-9]+$"
+        content_id = "^[0-9]+$"
+        "#,
+    )
+    .unwrap();
+    let mut schemas = HashMap::new();
+    schemas.insert("post".to_string(), schema);
+    env.config
+        .content_schemas
+        .insert("PLATFORM_1".to_string(), schemas);
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let with = |f: &dyn Fn(&mut Value)| {
+        let mut c =
+            serde_json::to_value(content(Some("Hello."), None)).unwrap();
+        f(&mut c);
+        c
+    };
+    let body = json!({
+        "data": [
+            with(&|c| c["body"] = Value::Null),
+            with(&|c| c["created_at"] = json!("2022-01-01T00:00:00Z")),
+            with(&|c| c["references"] = json!({"quote_of": "1"})),
+            with(&|c| c["media"] = json!(["https://a", "https://b"])),
+            with(&|c| c["content_id"] = json!("abc")),
+            with(&|c| c["references"] = json!({"reply_to": "1"})),
+        ],
+        "queue_id": null,
+    });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::OK);
+    let ar: AddResponse = serde_json::from_value(ar).unwrap();
+    let rejections: Vec<Option<Rejection>> =
+        ar.results.iter().map(|r| r.rejection).collect();
+    assert_eq!(
+        rejections,
+        vec![
+            Some(Rejection::MissingField),
+            Some(Rejection::UnexpectedField),
+            Some(Rejection::UnknownReference),
+            Some(Rejection::MediaCount),
+            Some(Rejection::InvalidId),
+            None,
+        ]
+    );
+
+    for rule in ["max_medias = 10", r#"contentid = "^[0-9]+$""#] {
+        assert!(toml::from_str::<TypeSchema>(rule).is_err());
+    }
+
+    env.cleanup().await;
+}
+
+/// test_add_presence_schema tests:
+/// - Presence can require the fields it always has.
+/// - Presence breaking the rules declared for its type is rejected.
+/// - Schemas naming fields their kind of data doesn't have are rejected.
+#[tokio::test]
+async fn test_add_presence_schema() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let schema = toml::from_str(
+        r#"
+        required = ["retrieved_at"]
+        id = "^[0-9]+$"
+        "#,
+    )
+    .unwrap();
+    let mut schemas = HashMap::new();
+    schemas.insert("listening_now".to_string(), schema);
+    env.config
+        .presence_schemas
+        .insert("PLATFORM_2".to_string(), schemas);
+    assert!(env.config.check_schemas().is_ok());
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let presence = |id: &str| {
+        json!({
+            "id": id,
+            "platform": "PLATFORM_2",
+            "presence_type": "listening_now",
+            "retrieved_at": "2022-01-01T00:00:00Z",
+        })
+    };
+    let body = json!({
+        "data": [presence("123456789"), presence("abc")],
+        "queue_id": null,
+    });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ar["results"][0]["result"], "inserted");
+    assert_eq!(ar["results"][1]["rejection"], "invalid_id");
+
+    for field in ["username", "body"] {
+        let mut config = env.config.clone();
+        let schemas = config.presence_schemas.get_mut("PLATFORM_2").unwrap();
+        schemas.get_mut("listening_now").unwrap().required =
+            vec![field.to_string()];
+        assert!(config.check_schemas().is_err());
+    }
+
+    env.cleanup().await;
+}
+
+/// test_add_tagged tests:
+/// - Tagged data is accepted alongside untagged data.
+/// - Tagged data with a misspelt field is rejected with an error naming it.
+/// - Unknown kinds and schema versions are rejected.
+/// - Malformed submissions say what is wrong with them.
+#[tokio::test]
+async fn test_add_tagged() {
+    use instrumentality::data::Rejection;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let presence = json!({
+        "kind": "presence",
+        "schema_version": 1,
+        "id": "123456789",
+        "platform": "PLATFORM_2",
+        "presence_type": "listening_now",
+        "retrieved_at": "2022-01-01T00:00:00Z",
+    });
+    let mut misspelt = presence.clone();
+    misspelt["presense_type"] = misspelt["presence_type"].take();
+    let mut unknown_kind = presence.clone();
+    unknown_kind["kind"] = json!("follow");
+    let mut unknown_version = presence.clone();
+    unknown_version["schema_version"] = json!(2);
+    let untagged = serde_json::to_value(content(None, None)).unwrap();
+
+    let body = json!({
+        "data": [presence, misspelt, unknown_kind, unknown_version, untagged],
+        "queue_id": null,
+    });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::OK);
+    let ar: AddResponse = serde_json::from_value(ar).unwrap();
+    let rejections: Vec<Option<Rejection>> =
+        ar.results.iter().map(|r| r.rejection).collect();
+    assert_eq!(
+        rejections,
+        vec![
+            None,
+            Some(Rejection::Malformed),
+            Some(Rejection::Malformed),
+            Some(Rejection::UnsupportedVersion),
+            None,
+        ]
+    );
+    assert!(ar.results[1]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("presense_type"));
+    assert!(ar.results[2].error.as_ref().unwrap().contains("follow"));
+
+    let stored = env
+        .store
+        .presence("123456789", "PLATFORM_2", None, None, 10)
+        .await;
+    assert_eq!(stored.unwrap().len(), 1);
+
+    let (status, er) = env.add_json(&json!({"data": {}})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(er["text"].as_str().unwrap().contains("invalid type"));
+
+    env.cleanup().await;
+}
@@ -0,0 +1,156 @@
+//! Tests for broadcasts paired from livestream events.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::config::BroadcastRule;
+use instrumentality::data::Data;
+use instrumentality::server;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+fn event(
+    content_type: &str,
+    content_id: &str,
+    created_at: DateTime<Utc>,
+    references: Option<HashMap<String, String>>,
+) -> Data {
+    Data::Content {
+        id: "1".to_string(),
+        platform: "PLATFORM_3".to_string(),
+        content_type: content_type.to_string(),
+        retrieved_at: created_at,
+        content_id: content_id.to_string(),
+        deleted: None,
+        retrieved_from: None,
+        created_at: Some(created_at),
+        body: None,
+        media: None,
+        references,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn presence(retrieved_at: DateTime<Utc>) -> Data {
+    Data::Presence {
+        id: "1".to_string(),
+        platform: "PLATFORM_3".to_string(),
+        presence_type: "streaming".to_string(),
+        retrieved_at,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+/// test_broadcasts tests:
+/// - A start event is paired with the next end event, the presence observed
+///   between them and a recording made while live.
+/// - Stretches without observations longer than the gap are reported.
+/// - A start event without an end leaves the broadcast open, and its recording
+///   can be found by reference.
+/// - Only broadcasts that could have been live in the range are returned.
+/// - An end event at the same time as the next start event ends the next
+///   broadcast rather than the one before.
+/// - Unknown subjects are not found and queries without one are rejected.
+#[tokio::test]
+async fn test_broadcasts() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let types = ["stream_start", "video", "stream_end"];
+    env.config.content_types.insert(
+        "PLATFORM_3".to_string(),
+        types.iter().map(|t| t.to_string()).collect(),
+    );
+    env.config.broadcasts.insert(
+        "PLATFORM_3".to_string(),
+        BroadcastRule {
+            start: "stream_start".to_string(),
+            end: "stream_end".to_string(),
+            presence: Some("streaming".to_string()),
+            recording: Some("video".to_string()),
+            max_duration: None,
+            recording_window: None,
+        },
+    );
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_3".to_string(), vec!["1".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+
+    let t = |d, h, m| Utc.with_ymd_and_hms(2022, 1, d, h, m, 0).unwrap();
+    let mut references = HashMap::new();
+    references.insert("stream".to_string(), "start_2".to_string());
+    env.add(vec![
+        event("stream_start", "start_1", t(1, 20, 0), None),
+        event("video", "video_1", t(1, 20, 0), None),
+        event("stream_end", "end_1", t(1, 20, 45), None),
+        event("stream_start", "start_2", t(2, 18, 0), None),
+        event("video", "video_2", t(5, 12, 0), Some(references)),
+    ])
+    .await;
+    let observed = [t(1, 20, 5), t(1, 20, 10), t(1, 20, 40), t(2, 18, 5)];
+    env.add(observed.into_iter().map(presence).collect()).await;
+
+    let br = env.get(&format!("/broadcasts?subject={subject}")).await;
+    let all = br["broadcasts"].as_array().unwrap();
+    assert_eq!(all.len(), 2);
+
+    assert_eq!(all[0]["start_id"], "start_1");
+    assert_eq!(all[0]["end_id"], "end_1");
+    assert_eq!(all[0]["recording_id"], "video_1");
+    assert_eq!(all[0]["duration"], 2700);
+    assert_eq!(all[0]["observations"], 3);
+    let gaps = all[0]["gaps"].as_array().unwrap();
+    assert_eq!(gaps.len(), 1);
+    assert_eq!(gaps[0]["from"], "2022-01-01T20:10:00Z");
+    assert_eq!(gaps[0]["to"], "2022-01-01T20:40:00Z");
+
+    assert_eq!(all[1]["start_id"], "start_2");
+    assert!(all[1]["ended_at"].is_null());
+    assert!(all[1]["duration"].is_null());
+    assert_eq!(all[1]["recording_id"], "video_2");
+    assert_eq!(all[1]["observations"], 1);
+
+    let query = format!("subject={subject}&from=2022-01-02T00:00:00Z");
+    let br = env.get(&format!("/broadcasts?{query}")).await;
+    let later = br["broadcasts"].as_array().unwrap();
+    assert_eq!(later.len(), 1);
+    assert_eq!(later[0]["start_id"], "start_2");
+
+    let query = format!("subject={subject}&to=2022-01-01T23:00:00Z");
+    let br = env.get(&format!("/broadcasts?{query}")).await;
+    let earlier = br["broadcasts"].as_array().unwrap();
+    assert_eq!(earlier.len(), 1);
+    assert_eq!(earlier[0]["start_id"], "start_1");
+
+    env.add(vec![
+        event("stream_start", "start_3", t(6, 20, 0), None),
+        event("stream_start", "start_4", t(6, 21, 0), None),
+        event("stream_end", "end_4", t(6, 21, 0), None),
+    ])
+    .await;
+    let query = format!("subject={subject}&from=2022-01-06T00:00:00Z");
+    let br = env.get(&format!("/broadcasts?{query}")).await;
+    let colliding = br["broadcasts"].as_array().unwrap();
+    assert_eq!(colliding.len(), 3);
+    assert_eq!(colliding[1]["start_id"], "start_3");
+    assert!(colliding[1]["end_id"].is_null());
+    assert_eq!(colliding[2]["start_id"], "start_4");
+    assert_eq!(colliding[2]["end_id"], "end_4");
+
+    let (status, _) = env.try_get("/broadcasts?subject=nobody").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, br) =
+        env.try_get("/broadcasts?from=2022-01-01T00:00:00Z").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(br["response"], "ERROR");
+
+    env.cleanup().await;
+}
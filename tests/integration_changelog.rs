@@ -0,0 +1,118 @@
+//! Tests for the profile metadata change log.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hyper::Body;
+use hyper::Request;
+use tower::Service;
+
+fn meta(username: &str, verified: Option<bool>, at: DateTime<Utc>) -> Data {
+    Data::Meta {
+        id: "123456789".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: username.to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: at,
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+/// test_changelog tests:
+/// - Changes are reported per field between consecutive profiles.
+/// - Profiles submitted out of order are compared in the order retrieved.
+/// - Unchanged profiles produce no changes.
+#[tokio::test]
+async fn test_changelog() {
+    use instrumentality::response::ChangelogResponse;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let t = |h| Utc.with_ymd_and_hms(2022, 1, 1, h, 0, 0).unwrap();
+    env.add(vec![meta("new_name", Some(true), t(3))]).await;
+    env.add(vec![meta("old_name", None, t(1))]).await;
+    env.add(vec![meta("old_name", None, t(2))]).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method("GET")
+                .header("X-API-KEY", &env.user.key)
+                .uri("/changelog?platform=PLATFORM_1&id=123456789")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let cr: ChangelogResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(cr.response, "OK".to_string());
+    assert_eq!(cr.changes.len(), 2);
+    assert_eq!(cr.changes[0].field, "username");
+    assert_eq!(cr.changes[0].from, "old_name");
+    assert_eq!(cr.changes[0].to, "new_name");
+    assert_eq!(cr.changes[0].previous_retrieved_at, t(2));
+    assert_eq!(cr.changes[0].retrieved_at, t(3));
+    assert_eq!(cr.changes[0].added_by, Some(env.user.uuid.clone()));
+    assert_eq!(cr.changes[1].field, "verified");
+    assert_eq!(cr.changes[1].from, serde_json::Value::Null);
+    assert_eq!(cr.changes[1].to, true);
+
+    env.cleanup().await;
+}
+
+/// test_changelog_pages tests:
+/// - Only the latest metadata is compared, with `next` given for the rest.
+/// - The page before includes the change across the two pages.
+/// - Malformed pages are rejected.
+#[tokio::test]
+async fn test_changelog_pages() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let t = |m| {
+        Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap()
+            + Duration::minutes(m)
+    };
+    let mut metas = vec![meta("old_name", None, t(0))];
+    metas.extend((1..=100).map(|m| meta("new_name", None, t(m))));
+    env.add(metas).await;
+
+    let uri = "/changelog?platform=PLATFORM_1&id=123456789";
+    let cr = env.get(uri).await;
+    assert!(cr["changes"].as_array().unwrap().is_empty());
+    assert_eq!(cr["next"], "2022-01-01T00:01:00Z");
+
+    let cr = env.get(&format!("{uri}&until=2022-01-01T00:01:00Z")).await;
+    let changes = cr["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["from"], "old_name");
+    assert_eq!(changes[0]["retrieved_at"], "2022-01-01T00:01:00Z");
+    assert!(cr["next"].is_null());
+
+    let key = env.user.key.clone();
+    let uri = format!("{uri}&until=yesterday");
+    let (status, cr) = env.try_get_as(&key, &uri).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(cr["response"], "ERROR");
+
+    env.cleanup().await;
+}
@@ -0,0 +1,126 @@
+//! Tests for consensus between data providers.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::server;
+use instrumentality::user::User;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn meta(bio: &str, retrieved_at: DateTime<Utc>) -> Data {
+    Data::Meta {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: "user1".to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at,
+        display_name: Some("User One".to_string()),
+        profile_picture: None,
+        bio: Some(bio.to_string()),
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn content(deleted: Option<bool>, retrieved_at: DateTime<Utc>) -> Data {
+    Data::Content {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at,
+        content_id: "987654321".to_string(),
+        deleted,
+        retrieved_from: None,
+        created_at: None,
+        body: Some("Hello.".to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn dispute<'a>(profile: &'a Value, field: &str) -> Option<&'a Value> {
+    profile["disputes"]
+        .as_array()?
+        .iter()
+        .find(|d| d["field"] == field)
+}
+
+/// test_consensus tests:
+/// - /view shows the value most data providers agree on, not the newest.
+/// - Dissenting values are listed with their data providers.
+/// - Content one data provider says is deleted but most see live is live.
+/// - Observations from outside the window don't count.
+/// - Values short of the quorum aren't agreed on.
+#[tokio::test]
+async fn test_consensus() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    env.config.consensus.quorum = 2;
+    env.config.consensus.window = 3600 * 12;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let second = User::new("second");
+    let third = User::new("third");
+    let stale = User::new("stale");
+    for user in [&second, &third, &stale] {
+        env.store.insert_user(user).await.unwrap();
+    }
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+
+    let t = |d, h| Utc.with_ymd_and_hms(2022, 1, d, h, 0, 0).unwrap();
+    let old = vec![meta("Old.", t(1, 0))];
+    env.add_as(&stale.key, old, None).await;
+    let first = vec![meta("Hello.", t(2, 1)), content(Some(true), t(2, 1))];
+    env.add(first).await;
+    let agreeing = vec![meta("Hello.", t(2, 2)), content(None, t(2, 2))];
+    env.add_as(&second.key, agreeing, None).await;
+    let dissenting = vec![meta("Spam.", t(2, 3)), content(None, t(2, 3))];
+    env.add_as(&third.key, dissenting, None).await;
+
+    let profile = env.view_profile(&format!("subjects={subject}")).await;
+    assert_eq!(profile["meta"]["bio"], "Hello.");
+    assert_eq!(profile["meta"]["display_name"], "User One");
+    assert!(dispute(&profile, "display_name").is_none());
+
+    let bio = dispute(&profile, "bio").unwrap();
+    assert_eq!(bio["value"], "Hello.");
+    assert_eq!(bio["agreed"], true);
+    assert_eq!(bio["providers"].as_array().unwrap().len(), 2);
+    let dissent = bio["dissent"].as_array().unwrap();
+    assert_eq!(dissent.len(), 1);
+    assert_eq!(dissent[0]["value"], "Spam.");
+    assert_eq!(dissent[0]["providers"][0], third.uuid.as_str());
+
+    assert_eq!(profile["content"][0]["deleted"], false);
+    let deleted = dispute(&profile, "deleted").unwrap();
+    assert_eq!(deleted["content_id"], "987654321");
+    assert_eq!(deleted["value"], false);
+    assert_eq!(deleted["dissent"][0]["value"], true);
+    assert_eq!(
+        deleted["dissent"][0]["providers"][0],
+        env.user.uuid.as_str()
+    );
+
+    env.config.consensus.quorum = 3;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+    let profile = env.view_profile(&format!("subjects={subject}")).await;
+    let bio = dispute(&profile, "bio").unwrap();
+    assert_eq!(bio["agreed"], false);
+    assert_eq!(profile["meta"]["bio"], "Spam.");
+
+    env.cleanup().await;
+}
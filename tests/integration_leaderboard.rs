@@ -0,0 +1,113 @@
+//! Tests for the leaderboard.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::routes::leaderboard::period;
+
+use axum::http::StatusCode;
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashMap;
+
+async fn complete(env: &mut Environment, queue_id: &str) {
+    let meta = Data::Meta {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: "user1".to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: Utc::now(),
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    };
+    let content = Data::Content {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc::now(),
+        content_id: "2".to_string(),
+        deleted: None,
+        retrieved_from: None,
+        created_at: None,
+        body: None,
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    };
+    let key = env.user.key.clone();
+    env.add_as(&key, vec![meta, content], Some(queue_id.to_string()))
+        .await;
+}
+
+/// test_leaderboard tests:
+/// - Completing a queue job credits the provider with the job and its data.
+/// - Data that was already stored isn't credited.
+/// - Submitting data for a job that isn't held earns nothing.
+/// - Past periods are kept and can be viewed.
+/// - Providers with the same score share a rank.
+#[tokio::test]
+async fn test_leaderboard() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.create_subject("test", profiles).await;
+
+    let job = env.get("/queue?platforms=[PLATFORM_1]").await;
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+    // The content is stored before the job is done, so only the metadata is
+    // new.
+    complete(&mut env, "not a job").await;
+    complete(&mut env, &queue_id).await;
+    complete(&mut env, &queue_id).await;
+
+    let (status, lr) = env.try_get("/leaderboard").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lr["period"], period(Utc::now()));
+    assert_eq!(lr["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(lr["entries"][0]["rank"], 1);
+    assert_eq!(lr["entries"][0]["name"], env.user.name.as_str());
+    assert_eq!(lr["entries"][0]["jobs"], 1);
+    assert_eq!(lr["entries"][0]["items"], 1);
+
+    for user in ["a", "b", "c"] {
+        let items = if user == "c" { 1 } else { 5 };
+        env.store
+            .record_credit("2022-01", user, 2, items)
+            .await
+            .unwrap();
+    }
+    let lr = env.get("/leaderboard?period=2022-01").await;
+    let ranks: Vec<&Value> = lr["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| &e["rank"])
+        .collect();
+    assert_eq!(ranks, vec![1, 1, 3]);
+    assert_eq!(lr["entries"][2]["name"], "c");
+    assert_eq!(lr["periods"][1], "2022-01");
+
+    for period in ["2022-13", "2022-1", "22-01"] {
+        let uri = format!("/leaderboard?period={period}");
+        let (status, _) = env.try_get(&uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (status, lr) = env.try_get("/leaderboard?period=a&period=b").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(lr["response"], "ERROR");
+
+    env.cleanup().await;
+}
@@ -0,0 +1,180 @@
+//! Tests for queue leases and quarantine.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::routes::queue::backoff;
+use instrumentality::server;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+async fn take_job(env: &mut Environment) -> Option<String> {
+    let job = env.get("/queue?platforms=[PLATFORM_1]").await;
+    job["queue_id"].as_str().map(|q| q.to_string())
+}
+
+async fn setup(env: &mut Environment) {
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.create_subject("test", profiles).await;
+}
+
+fn datetime(value: &Value) -> DateTime<Utc> {
+    serde_json::from_value(value.clone()).unwrap()
+}
+
+/// test_lease_heartbeat_and_release tests:
+/// - A heartbeat extends the lease.
+/// - A released job is handed out again straight away.
+/// - Leases that aren't held can't be extended or released.
+#[tokio::test]
+async fn test_lease_heartbeat_and_release() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    setup(&mut env).await;
+
+    let job = env.get("/queue?platforms=[PLATFORM_1]").await;
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+    let expires_at = datetime(&job["lease_expires_at"]);
+
+    let (status, lease) = env
+        .post("/queue/heartbeat", &json!({ "queue_id": queue_id }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(datetime(&lease["lease_expires_at"]) >= expires_at);
+
+    let (status, _) = env
+        .post("/queue/release", &json!({ "queue_id": queue_id }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = env
+        .post("/queue/heartbeat", &json!({ "queue_id": queue_id }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    assert_eq!(take_job(&mut env).await, Some(queue_id.clone()));
+    let (status, _) = env
+        .post("/queue/release", &json!({ "queue_id": "unknown" }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
+
+/// test_lease_fail_and_quarantine tests:
+/// - Failed jobs are backed off.
+/// - Promoting a backed off profile doesn't bring it forward.
+/// - Rate limiting never quarantines a profile by itself, but counts towards
+///   quarantine when followed by another failure.
+/// - Repeated failures quarantine a profile, leaving it out of the queue.
+/// - Quarantined profiles can be reviewed and restored.
+#[tokio::test]
+async fn test_lease_fail_and_quarantine() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    setup(&mut env).await;
+
+    let queue_id = take_job(&mut env).await.unwrap();
+    let (status, failed) = env
+        .post(
+            "/queue/fail",
+            &json!({ "queue_id": queue_id, "reason": "not_found" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let backoff = Duration::seconds(env.config.queue.backoff);
+    assert!(datetime(&failed["due_at"]) > Utc::now() + backoff / 2);
+    assert!(take_job(&mut env).await.is_none());
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.store
+        .promote_queue_items(&profiles, Utc::now(), Duration::zero())
+        .await
+        .unwrap();
+    assert!(take_job(&mut env).await.is_none());
+
+    // Stop backing off from here on and move on to a fresh profile.
+    env.config.queue.backoff = 0;
+    env.config.queue.quarantine_after = 2;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["2".to_string()]);
+    env.create_subject("test_2", profiles).await;
+    let queue_id = take_job(&mut env).await.unwrap();
+    env.post("/queue/release", &json!({ "queue_id": queue_id }))
+        .await;
+
+    let failures = [
+        ("rate_limited", false),
+        ("rate_limited", false),
+        ("private", true),
+    ];
+    for (reason, quarantined) in failures {
+        assert_eq!(take_job(&mut env).await, Some(queue_id.clone()));
+        let (_, failed) = env
+            .post(
+                "/queue/fail",
+                &json!({ "queue_id": queue_id, "reason": reason }),
+            )
+            .await;
+        assert_eq!(failed["quarantined"], quarantined);
+    }
+    assert!(take_job(&mut env).await.is_none());
+
+    let quarantine = env.get("/queue/quarantine").await;
+    assert_eq!(quarantine["profiles"][0]["queue_id"], queue_id.as_str());
+    assert_eq!(quarantine["profiles"][0]["last_failure"], "private");
+
+    let (status, _) = env
+        .post("/queue/restore", &json!({ "queue_id": queue_id }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let quarantine = env.get("/queue/quarantine").await;
+    assert!(quarantine["profiles"].as_array().unwrap().is_empty());
+    assert_eq!(take_job(&mut env).await, Some(queue_id));
+
+    env.cleanup().await;
+}
+
+/// test_lease_rate_limited tests:
+/// - Rate limited jobs are backed off rather than handed out again straight
+///   away.
+#[tokio::test]
+async fn test_lease_rate_limited() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    setup(&mut env).await;
+
+    let queue_id = take_job(&mut env).await.unwrap();
+    let (status, failed) = env
+        .post(
+            "/queue/fail",
+            &json!({ "queue_id": queue_id, "reason": "rate_limited" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(failed["quarantined"], false);
+    let backoff = Duration::seconds(env.config.queue.backoff);
+    assert!(datetime(&failed["due_at"]) > Utc::now() + backoff / 2);
+    assert!(take_job(&mut env).await.is_none());
+
+    env.cleanup().await;
+}
+
+/// test_backoff tests:
+/// - Backoff doubles with each failure in a row, up to the maximum.
+#[test]
+fn test_backoff() {
+    let config = instrumentality::config::QueueConfig {
+        backoff: 60,
+        max_backoff: 200,
+        ..Default::default()
+    };
+    assert_eq!(backoff(1, &config), Duration::seconds(60));
+    assert_eq!(backoff(2, &config), Duration::seconds(120));
+    assert_eq!(backoff(3, &config), Duration::seconds(200));
+    assert_eq!(backoff(100, &config), Duration::seconds(200));
+}
@@ -0,0 +1,92 @@
+//! Tests for looking up profiles by past usernames.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, TimeZone, Utc};
+use hyper::Body;
+use hyper::Request;
+use std::collections::HashMap;
+use tower::Service;
+
+fn meta(id: &str, username: &str, at: DateTime<Utc>) -> Data {
+    Data::Meta {
+        id: id.to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: username.to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: at,
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+/// test_lookup_past_username tests:
+/// - A username is resolved to every profile that has used it, most recent
+///   first.
+/// - Each profile comes with its current username.
+/// - Only the caller's subjects tracking the profile are returned.
+/// - Queries without a username are rejected.
+#[tokio::test]
+async fn test_lookup_past_username() {
+    use instrumentality::response::LookupResponse;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let t = |h| Utc.with_ymd_and_hms(2022, 1, 1, h, 0, 0).unwrap();
+    env.add(vec![meta("1", "alice", t(1)), meta("1", "alice_2", t(2))])
+        .await;
+    env.add(vec![meta("2", "alice", t(3))]).await;
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    let uuid = env.create_subject("alice", profiles).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method("GET")
+                .header("X-API-KEY", &env.user.key)
+                .uri("/lookup?platform=PLATFORM_1&username=alice")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let lr: LookupResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(lr.response, "OK".to_string());
+    assert_eq!(lr.profiles.len(), 2);
+    assert_eq!(lr.profiles[0].platform_id, "2");
+    assert_eq!(lr.profiles[0].current_username, Some("alice".to_string()));
+    assert!(lr.profiles[0].subjects.is_empty());
+    assert_eq!(lr.profiles[1].platform_id, "1");
+    assert_eq!(lr.profiles[1].current_username, Some("alice_2".to_string()));
+    assert_eq!(lr.profiles[1].last_seen, t(1));
+    assert_eq!(lr.profiles[1].subjects[0].uuid, uuid);
+
+    let key = env.user.key.clone();
+    let uri = "/lookup?platform=PLATFORM_1";
+    let (status, lr) = env.try_get_as(&key, uri).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(lr["response"], "ERROR");
+
+    env.cleanup().await;
+}
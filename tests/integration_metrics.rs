@@ -0,0 +1,107 @@
+//! Tests for metric time series of profiles and content.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+fn metric(metric_type: &str, value: u64, retrieved_at: &str) -> Value {
+    json!({
+        "kind": "metric",
+        "schema_version": 1,
+        "id": "1",
+        "platform": "PLATFORM_1",
+        "metric_type": metric_type,
+        "value": value,
+        "retrieved_at": retrieved_at,
+    })
+}
+
+async fn metrics(env: &mut Environment, query: &str) -> Value {
+    env.get(&format!("/metrics?platform=PLATFORM_1&id=1{query}"))
+        .await
+}
+
+/// test_metrics tests:
+/// - Profile metrics are returned as a series per metric type, without the
+///   metrics of content.
+/// - Content metrics are returned when asked for by content ID.
+/// - Points can be downsampled into hourly and daily buckets.
+/// - Only points retrieved in the range are returned.
+/// - Untagged metrics and unknown metric types are rejected.
+/// - Queries without a profile or with an unknown resolution are rejected.
+#[tokio::test]
+async fn test_metrics() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let mut likes = metric("likes", 7, "2022-01-01T00:30:00Z");
+    likes["content_id"] = json!("post_1");
+    let body = json!({ "data": [
+        metric("followers", 100, "2022-01-01T00:00:00Z"),
+        metric("followers", 110, "2022-01-01T00:30:00Z"),
+        metric("followers", 120, "2022-01-01T01:00:00Z"),
+        metric("followers", 150, "2022-01-02T00:00:00Z"),
+        likes,
+    ]});
+    let (status, _) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mr = metrics(&mut env, "").await;
+    assert_eq!(mr["resolution"], "raw");
+    let series = mr["series"].as_array().unwrap();
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0]["metric_type"], "followers");
+    let buckets = series[0]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 4);
+    assert_eq!(buckets[0]["start"], "2022-01-01T00:00:00Z");
+    assert_eq!(buckets[3]["last"], 150.0);
+
+    let mr = metrics(&mut env, "&content_id=post_1").await;
+    let series = mr["series"].as_array().unwrap();
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0]["metric_type"], "likes");
+    assert_eq!(series[0]["buckets"][0]["last"], 7.0);
+
+    let mr = metrics(&mut env, "&resolution=hourly").await;
+    let buckets = mr["series"][0]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 3);
+    assert_eq!(buckets[0]["observations"], 2);
+    assert_eq!(buckets[0]["mean"], 105.0);
+
+    let mr = metrics(&mut env, "&resolution=daily").await;
+    let buckets = mr["series"][0]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0]["first"], 100.0);
+    assert_eq!(buckets[0]["last"], 120.0);
+    assert_eq!(buckets[0]["max"], 120.0);
+    assert_eq!(buckets[1]["start"], "2022-01-02T00:00:00Z");
+
+    let query = "&from=2022-01-01T00:15:00Z&to=2022-01-01T23:00:00Z";
+    let mr = metrics(&mut env, query).await;
+    let buckets = mr["series"][0]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 2);
+
+    let mut untagged = metric("followers", 200, "2022-01-03T00:00:00Z");
+    untagged.as_object_mut().unwrap().remove("kind");
+    untagged.as_object_mut().unwrap().remove("schema_version");
+    let unknown = metric("views", 1, "2022-01-03T00:00:00Z");
+    let body = json!({ "data": [untagged, unknown] });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(ar["results"][0]["rejection"], "malformed");
+    assert_eq!(ar["results"][1]["rejection"], "unknown_type");
+
+    let key = env.user.key.clone();
+    for uri in [
+        "/metrics?platform=PLATFORM_1",
+        "/metrics?platform=PLATFORM_1&id=1&resolution=weekly",
+    ] {
+        let (status, mr) = env.try_get_as(&key, uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(mr["response"], "ERROR");
+    }
+
+    env.cleanup().await;
+}
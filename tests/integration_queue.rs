@@ -0,0 +1,339 @@
+//! Tests for the queue.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hyper::Body;
+use hyper::Request;
+use std::collections::HashMap;
+use tower::Service;
+
+fn meta(id: &str, username: &str, at: DateTime<Utc>) -> Data {
+    Data::Meta {
+        id: id.to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: username.to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: at,
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn content(id: &str, created_at: DateTime<Utc>) -> Data {
+    Data::Content {
+        id: id.to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc::now(),
+        content_id: format!("{id}_{}", created_at.timestamp()),
+        deleted: None,
+        retrieved_from: None,
+        created_at: Some(created_at),
+        body: None,
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+async fn queue(env: &mut Environment) -> serde_json::Value {
+    env.get("/queue?platforms=[PLATFORM_1]").await
+}
+
+/// test_queue_most_recent_username tests:
+/// - Jobs are handed out with the most recently seen username, regardless of
+///   the order metadata was submitted in.
+#[tokio::test]
+async fn test_queue_most_recent_username() {
+    use instrumentality::response::QueueResponse;
+
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let t = |h| Utc.with_ymd_and_hms(2022, 1, 1, h, 0, 0).unwrap();
+    env.add(vec![meta("1", "new_name", t(2))]).await;
+    env.add(vec![meta("1", "old_name", t(1))]).await;
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.create_subject("test", profiles).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method("GET")
+                .header("X-API-KEY", &env.user.key)
+                .uri("/queue?platforms=[PLATFORM_1]")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(qr.platform_id, "1");
+    assert_eq!(qr.username, "new_name");
+
+    env.cleanup().await;
+}
+
+/// test_queue_hot_promotion tests:
+/// - Processed profiles are not handed out again until they are due.
+/// - New content makes its profile and the other profiles of its subject hot
+///   and brings them forward.
+/// - Old content and profiles of other subjects are not promoted.
+#[tokio::test]
+async fn test_queue_hot_promotion() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let config = env.config.queue.clone();
+
+    let mut profiles = HashMap::new();
+    profiles.insert(
+        "PLATFORM_1".to_string(),
+        vec!["1".to_string(), "2".to_string()],
+    );
+    env.create_subject("hot", profiles).await;
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["3".to_string()]);
+    env.create_subject("cold", profiles).await;
+
+    let mut queue_ids = HashMap::new();
+    for _ in 0..3 {
+        let job = queue(&mut env).await;
+        let platform_id = job["platform_id"].as_str().unwrap().to_string();
+        let queue_id = job["queue_id"].as_str().unwrap().to_string();
+        let now = Utc::now();
+        let completed = env
+            .store
+            .complete_queue_item(
+                &queue_id,
+                &env.user.uuid,
+                now + Duration::seconds(config.hot_interval),
+                now + Duration::seconds(config.max_staleness),
+            )
+            .await
+            .unwrap();
+        assert!(completed);
+        queue_ids.insert(platform_id, queue_id);
+    }
+    assert!(queue(&mut env).await.get("queue_id").is_none());
+
+    let old = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+    env.add(vec![content("1", Utc::now()), content("3", old)])
+        .await;
+
+    let cold_due_at = Utc::now() + Duration::seconds(config.max_staleness / 2);
+    for (platform_id, hot) in [("1", true), ("2", true), ("3", false)] {
+        let q_item = env
+            .store
+            .queue_item(&queue_ids[platform_id])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(q_item.hot_until.is_some(), hot);
+        assert_eq!(q_item.due_at < cold_due_at, hot);
+    }
+
+    env.cleanup().await;
+}
+
+/// test_queue_username_job tests:
+/// - Completing a job for a profile known only by its username credits the
+///   data provider.
+/// - The profile's queue item is swapped for one with its platform ID, due
+///   when the completed job would next have been.
+#[tokio::test]
+async fn test_queue_username_job() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let config = env.config.queue.clone();
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["user1".to_string()]);
+    env.create_subject("test", profiles).await;
+
+    let job = queue(&mut env).await;
+    assert_eq!(job["platform_id"], "user1");
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+    let key = env.user.key.clone();
+    let before = Utc::now();
+    env.add_as(&key, vec![meta("1", "user1", Utc::now())], Some(queue_id))
+        .await;
+
+    let lr = env.get("/leaderboard").await;
+    assert_eq!(lr["entries"][0]["jobs"], 1);
+
+    let mut profiles = HashMap::new();
+    profiles.insert(
+        "PLATFORM_1".to_string(),
+        vec!["1".to_string(), "user1".to_string()],
+    );
+    let q_items = env.store.queue_items(&profiles).await.unwrap();
+    assert_eq!(q_items.len(), 1);
+    assert_eq!(q_items[0].platform_id, "1");
+    assert!(q_items[0].last_processed >= before);
+    assert!(
+        q_items[0].due_at >= before + Duration::seconds(config.max_staleness)
+    );
+    assert!(queue(&mut env).await.get("queue_id").is_none());
+
+    env.cleanup().await;
+}
+
+/// test_queue_lease_expiry tests:
+/// - Jobs are leased for the time configured for their platform.
+/// - A leased job is not handed out again until its lease expires.
+#[tokio::test]
+async fn test_queue_lease_expiry() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let lease = env.config.queue.lease("PLATFORM_1");
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.create_subject("test", profiles).await;
+
+    let before = Utc::now();
+    let job = queue(&mut env).await;
+    let queue_id = job["queue_id"].as_str().unwrap();
+    let q_item = env.store.queue_item(queue_id).await.unwrap().unwrap();
+    let expires_at = q_item.lock_expires_at.unwrap();
+    assert!(expires_at >= before + lease);
+    assert!(expires_at <= Utc::now() + lease);
+    assert!(queue(&mut env).await.get("queue_id").is_none());
+
+    assert_eq!(env.store.expire_locks(Utc::now()).await.unwrap(), 0);
+    let later = expires_at + Duration::seconds(1);
+    assert_eq!(env.store.expire_locks(later).await.unwrap(), 1);
+    assert_eq!(queue(&mut env).await["queue_id"], queue_id);
+
+    env.cleanup().await;
+}
+
+/// test_queue_batch tests:
+/// - Several jobs can be taken at once, each with its own lease.
+/// - No job is handed out twice.
+/// - Batches must be between 1 and max_batch jobs.
+#[tokio::test]
+async fn test_queue_batch() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let ids = ["1", "2", "3"].iter().map(|id| id.to_string()).collect();
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), ids);
+    env.create_subject("test", profiles).await;
+
+    let batch = env.get("/queue?platforms=[PLATFORM_1]&count=2").await;
+    let jobs = batch["jobs"].as_array().unwrap();
+    assert_eq!(jobs.len(), 2);
+    assert_ne!(jobs[0]["queue_id"], jobs[1]["queue_id"]);
+    assert!(jobs.iter().all(|j| j["lease_expires_at"].is_string()));
+
+    let rest = env.get("/queue?platforms=[PLATFORM_1]&count=2").await;
+    let rest = rest["jobs"].as_array().unwrap();
+    assert_eq!(rest.len(), 1);
+    assert!(!jobs.contains(&rest[0]));
+    assert!(queue(&mut env).await.get("queue_id").is_none());
+
+    for count in [0, env.config.queue.max_batch + 1] {
+        let res = env
+            .app
+            .call(
+                Request::builder()
+                    .method("GET")
+                    .header("X-API-KEY", &env.user.key)
+                    .uri(format!("/queue?platforms=[PLATFORM_1]&count={count}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    env.cleanup().await;
+}
+
+/// test_queue_wait tests:
+/// - Without a job available, NO_JOBS is returned once the wait is up.
+/// - A waiting request is handed a job as soon as one is released.
+#[tokio::test]
+async fn test_queue_wait() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    env.create_subject("test", profiles).await;
+    let job = queue(&mut env).await;
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+
+    let start = std::time::Instant::now();
+    let none = env.get("/queue?platforms=[PLATFORM_1]&wait=1").await;
+    assert_eq!(none["response"], "NO_JOBS");
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+
+    let mut app = env.app.clone();
+    let key = env.user.key.clone();
+    let waiting = tokio::spawn(async move {
+        let start = std::time::Instant::now();
+        let res = app
+            .call(
+                Request::builder()
+                    .method("GET")
+                    .header("X-API-KEY", &key)
+                    .uri("/queue?platforms=[PLATFORM_1]&wait=10")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        (job, start.elapsed())
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method("POST")
+                .header("X-API-KEY", &env.user.key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/queue/release")
+                .body(Body::from(
+                    serde_json::json!({ "queue_id": queue_id }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let (job, waited) = waiting.await.unwrap();
+    assert_eq!(job["queue_id"], queue_id.as_str());
+    assert!(waited < std::time::Duration::from_secs(5));
+
+    env.cleanup().await;
+}
@@ -0,0 +1,171 @@
+//! Tests for relationships and the changes seen between snapshots of them.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::server;
+use instrumentality::user::User;
+
+use axum::http::StatusCode;
+use hyper::{Body, Request};
+use serde_json::{json, Value};
+
+fn follows(to_id: &str, observed_at: &str) -> Value {
+    json!({
+        "kind": "relationship",
+        "schema_version": 1,
+        "from_id": "1",
+        "to_id": to_id,
+        "platform": "PLATFORM_1",
+        "relation_type": "follows",
+        "observed_at": observed_at,
+    })
+}
+
+// Adds some of who the profile follows.
+async fn partial(env: &mut Environment, to_ids: &[&str], observed_at: &str) {
+    let data: Vec<Value> =
+        to_ids.iter().map(|t| follows(t, observed_at)).collect();
+    let (status, _) = env.add_json(&json!({ "data": data })).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// Posts the complete list of who the profile follows as the user with `key`.
+async fn snapshot(
+    env: &mut Environment,
+    key: &str,
+    to_ids: &[&str],
+    observed_at: &str,
+) -> (StatusCode, Value) {
+    let body = json!({
+        "platform": "PLATFORM_1",
+        "from_id": "1",
+        "relation_type": "follows",
+        "observed_at": observed_at,
+        "to_ids": to_ids,
+    });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/relationships")
+        .header("X-API-KEY", key)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            mime::APPLICATION_JSON.as_ref(),
+        )
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    env.call(request).await
+}
+
+async fn relationships(env: &mut Environment, query: &str) -> Value {
+    env.get(&format!("/relationships?platform=PLATFORM_1&id=1{query}"))
+        .await
+}
+
+fn current(rr: &Value) -> Vec<&str> {
+    let mut current: Vec<&str> = rr["current"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["to_id"].as_str().unwrap())
+        .collect();
+    current.sort_unstable();
+    current
+}
+
+/// test_relationships tests:
+/// - Relationships seen before the first snapshot have no known start.
+/// - Relationships added to /add only start or refresh edges, never end them.
+/// - Relationships missing from the next snapshot are seen stopping, and new
+///   ones starting, between the two, and an empty snapshot ends them all.
+/// - Snapshots older than the latest change nothing.
+/// - Only changes that could have happened in the range are returned.
+/// - Relationships seen by untrusted data providers are held back.
+/// - Untagged relationships and unknown relation types are rejected.
+#[tokio::test]
+async fn test_relationships() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    env.config.reputation.min_assessed = 1;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+    let key = env.user.key.clone();
+
+    partial(&mut env, &["2", "3"], "2022-01-01T00:00:00Z").await;
+    partial(&mut env, &["3"], "2022-01-01T12:00:00Z").await;
+    let rr = relationships(&mut env, "").await;
+    assert_eq!(current(&rr), vec!["2", "3"]);
+    assert!(rr["current"][0]["started_after"].is_null());
+    assert!(rr["changes"].as_array().unwrap().is_empty());
+
+    let (status, _) =
+        snapshot(&mut env, &key, &["3", "4"], "2022-01-02T00:00:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) =
+        snapshot(&mut env, &key, &["2"], "2021-12-31T00:00:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+    let rr = relationships(&mut env, "").await;
+    assert_eq!(current(&rr), vec!["3", "4"]);
+
+    let mut changes = rr["changes"].as_array().unwrap().clone();
+    changes.sort_by_key(|c| c["to_id"].as_str().unwrap().to_string());
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["to_id"], "2");
+    assert_eq!(changes[0]["change"], "stopped");
+    assert_eq!(changes[0]["relation_type"], "follows");
+    assert_eq!(changes[0]["after"], "2022-01-01T00:00:00Z");
+    assert_eq!(changes[0]["before"], "2022-01-02T00:00:00Z");
+    assert_eq!(changes[0]["added_by"], env.user.uuid.as_str());
+
+    // A new relationship after the snapshot starts between the two.
+    partial(&mut env, &["5"], "2022-01-03T00:00:00Z").await;
+    let rr = relationships(&mut env, "&from=2022-01-02T12:00:00Z").await;
+    assert_eq!(current(&rr), vec!["3", "4", "5"]);
+    let changes = rr["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["to_id"], "5");
+    assert_eq!(changes[0]["change"], "started");
+    assert_eq!(changes[0]["after"], "2022-01-02T00:00:00Z");
+    assert_eq!(changes[0]["before"], "2022-01-03T00:00:00Z");
+
+    let (status, _) =
+        snapshot(&mut env, &key, &[], "2022-01-04T00:00:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+    let rr = relationships(&mut env, "&from=2022-01-03T12:00:00Z").await;
+    assert!(current(&rr).is_empty());
+    assert_eq!(rr["changes"].as_array().unwrap().len(), 3);
+    let rr = relationships(&mut env, "&to=2022-01-01T12:00:00Z").await;
+    assert_eq!(rr["changes"].as_array().unwrap().len(), 1);
+
+    let lazy = User::new("lazy");
+    env.store.insert_user(&lazy).await.unwrap();
+    env.store.record_reputation(&lazy.uuid, 3, 0).await.unwrap();
+    let (status, _) =
+        snapshot(&mut env, &lazy.key, &["6"], "2022-01-05T00:00:00Z").await;
+    assert_eq!(status, StatusCode::OK);
+    let rr = relationships(&mut env, "").await;
+    assert!(current(&rr).is_empty());
+    assert_eq!(rr["held_back"], 1);
+    let rr = relationships(&mut env, "&untrusted=true").await;
+    assert_eq!(current(&rr), vec!["6"]);
+    assert_eq!(rr["held_back"], 0);
+
+    let (status, _) = snapshot(&mut env, &key, &["7"], "not a timestamp").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = env
+        .try_get_as(&key, "/relationships?platform=PLATFORM_1")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut untagged = follows("5", "2022-01-03T00:00:00Z");
+    untagged.as_object_mut().unwrap().remove("kind");
+    untagged.as_object_mut().unwrap().remove("schema_version");
+    let mut unknown = follows("5", "2022-01-03T00:00:00Z");
+    unknown["relation_type"] = json!("blocks");
+    let body = json!({ "data": [untagged, unknown] });
+    let (status, ar) = env.add_json(&body).await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(ar["results"][0]["rejection"], "malformed");
+    assert_eq!(ar["results"][1]["rejection"], "unknown_type");
+
+    env.cleanup().await;
+}
@@ -0,0 +1,105 @@
+//! Tests for data provider reputation.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::server;
+use instrumentality::user::User;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::HashMap;
+
+fn meta(retrieved_at: DateTime<Utc>, complete: bool) -> Data {
+    let field = |value: &str| complete.then(|| value.to_string());
+    Data::Meta {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: "user1".to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at,
+        display_name: field("User One"),
+        profile_picture: None,
+        bio: field("Hello."),
+        verified: None,
+        references: None,
+        link: field("https://example.com/user1"),
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+/// test_reputation tests:
+/// - Data providers that leave out fields others provide become untrusted.
+/// - Data from untrusted data providers is held back from /view unless asked
+///   for.
+/// - Jobs completed by untrusted data providers are due again soon.
+#[tokio::test]
+async fn test_reputation() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    env.config.reputation.min_assessed = 2;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let lazy = User::new("lazy");
+    env.store.insert_user(&lazy).await.unwrap();
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+
+    let t = |h| Utc.with_ymd_and_hms(2022, 1, 1, h, 0, 0).unwrap();
+    env.add(vec![meta(t(1), true)]).await;
+    env.add_as(&lazy.key, vec![meta(t(2), false)], None).await;
+
+    let reputations = env
+        .store
+        .reputations(std::slice::from_ref(&lazy.uuid))
+        .await;
+    let reputation = &reputations.unwrap()[0];
+    assert_eq!((reputation.expected, reputation.provided), (3, 0));
+    let view = format!("subjects={subject}");
+    let profile = env.view_profile(&view).await;
+    assert_eq!(profile["held_back"], 0);
+
+    // The lazy data provider is judged from here on.
+    let job = env.get_as(&lazy.key, "/queue?platforms=[PLATFORM_1]").await;
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+    env.add_as(&lazy.key, vec![meta(t(3), false)], Some(queue_id.clone()))
+        .await;
+    env.add_as(&lazy.key, vec![meta(t(4), false)], None).await;
+
+    let profile = env.view_profile(&view).await;
+    assert_eq!(profile["held_back"], 3);
+    assert_eq!(profile["meta"]["display_name"], "User One");
+
+    let profile = env.view_profile(&format!("{view}&untrusted=true")).await;
+    assert_eq!(profile["held_back"], 0);
+
+    // The job was completed before the data provider was judged.
+    let q_item = env.store.queue_item(&queue_id).await.unwrap().unwrap();
+    let max_staleness = Duration::seconds(env.config.queue.max_staleness);
+    assert!(q_item.due_at > Utc::now() + max_staleness / 2);
+
+    let job = env.get_as(&lazy.key, "/queue?platforms=[PLATFORM_1]").await;
+    assert!(job.get("queue_id").is_none());
+    env.store
+        .promote_queue_items(
+            &HashMap::from([("PLATFORM_1".to_string(), vec!["1".to_string()])]),
+            Utc::now(),
+            Duration::zero(),
+        )
+        .await
+        .unwrap();
+    let job = env.get_as(&lazy.key, "/queue?platforms=[PLATFORM_1]").await;
+    assert_eq!(job["queue_id"], queue_id.as_str());
+    env.add_as(&lazy.key, vec![meta(t(5), false)], Some(queue_id.clone()))
+        .await;
+
+    let q_item = env.store.queue_item(&queue_id).await.unwrap().unwrap();
+    let hot_interval = Duration::seconds(env.config.queue.hot_interval);
+    assert!(q_item.due_at <= Utc::now() + hot_interval);
+
+    env.cleanup().await;
+}
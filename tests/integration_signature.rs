@@ -0,0 +1,144 @@
+//! Tests for signed submissions.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::{Data, Datas};
+use instrumentality::signature;
+
+use axum::http::StatusCode;
+use chrono::{TimeZone, Utc};
+use hyper::Body;
+use hyper::Request;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde_json::{json, Value};
+
+fn content() -> Data {
+    Data::Content {
+        id: "123456789".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+        content_id: "987654321".to_string(),
+        deleted: None,
+        retrieved_from: None,
+        created_at: None,
+        body: Some("Hello.".to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+async fn set_public_key(env: &mut Environment, public_key: &str) -> StatusCode {
+    env.post("/publickey", &json!({ "public_key": public_key }))
+        .await
+        .0
+}
+
+async fn add_signed(
+    env: &mut Environment,
+    body: &[u8],
+    signature: &str,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/add")
+        .header("X-API-KEY", &env.user.key)
+        .header("X-SIGNATURE", signature)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            mime::APPLICATION_JSON.as_ref(),
+        )
+        .body(Body::from(body.to_vec()))
+        .unwrap();
+    env.call(request).await
+}
+
+/// test_signed_submission tests:
+/// - Only hex encoded Ed25519 public keys can be registered.
+/// - Signatures are rejected until a public key is registered.
+/// - Invalid signatures are rejected and nothing is stored.
+/// - Data from a signed submission refers to it, and the submission can be
+///   fetched and verified independently.
+/// - Unsigned data that changes nothing leaves the submission in place, but
+///   merging in anything new from it stops the content referring to it.
+/// - Fetching a submission without a UUID is rejected.
+#[tokio::test]
+async fn test_signed_submission() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let public_key = hex::encode(key_pair.public_key());
+
+    let datas = Datas {
+        data: vec![content()],
+        queue_id: None,
+    };
+    let body = serde_json::to_vec(&datas).unwrap();
+    let signature = hex::encode(key_pair.sign(&body));
+
+    let (status, _) = add_signed(&mut env, &body, &signature).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(
+        set_public_key(&mut env, "not a key").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(set_public_key(&mut env, &public_key).await, StatusCode::OK);
+
+    let forged = hex::encode(key_pair.sign(b"Something else."));
+    let (status, _) = add_signed(&mut env, &body, &forged).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    assert!(stored.unwrap().is_empty());
+
+    let (status, ar) = add_signed(&mut env, &body, &signature).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ar["response"], "OK");
+
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    let uuid = match &stored.unwrap()[0] {
+        Data::Content { submission, .. } => submission.clone().unwrap(),
+        _ => panic!("Expected Data::Content."),
+    };
+
+    let sr = env.get(&format!("/submission?uuid={uuid}")).await;
+    let submission = &sr["submission"];
+    assert_eq!(submission["added_by"], env.user.uuid.as_str());
+    assert_eq!(submission["public_key"], public_key.as_str());
+
+    let signed = submission["body"].as_str().unwrap();
+    let signature = submission["signature"].as_str().unwrap();
+    assert!(signature::verify(&public_key, signed.as_bytes(), signature));
+    let signed: Datas = serde_json::from_str(signed).unwrap();
+    assert_eq!(signed.data, vec![content()]);
+
+    let submission_of = |stored: Vec<Data>| match &stored[0] {
+        Data::Content { submission, .. } => submission.clone(),
+        _ => panic!("Expected Data::Content."),
+    };
+    env.add(vec![content()]).await;
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    assert_eq!(submission_of(stored.unwrap()), Some(uuid));
+
+    let mut unsigned = content();
+    if let Data::Content { media, .. } = &mut unsigned {
+        *media = Some(vec!["https://example.com/1.png".to_string()]);
+    }
+    env.add(vec![unsigned]).await;
+    let stored = env.store.content("123456789", "PLATFORM_1", 10).await;
+    assert_eq!(submission_of(stored.unwrap()), None);
+
+    let key = env.user.key.clone();
+    let (status, sr) = env.try_get_as(&key, "/submission").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(sr["response"], "ERROR");
+
+    env.cleanup().await;
+}
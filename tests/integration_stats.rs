@@ -0,0 +1,107 @@
+//! Tests for queue statistics.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::group::Group;
+use instrumentality::user::User;
+
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+
+/// test_queue_stats tests:
+/// - Statistics are reported per platform for the whole queue.
+/// - Never processed profiles are counted apart from the staleness.
+/// - Statistics can be scoped to subjects and groups, listing the freshness
+///   of each tracked profile.
+/// - Subjects and groups created by other users are ignored.
+/// - Malformed queries are rejected.
+#[tokio::test]
+async fn test_queue_stats() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+
+    let ids = ["1", "2", "3"].iter().map(|id| id.to_string()).collect();
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), ids);
+    let first = env.create_subject("first", profiles).await;
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_2".to_string(), vec!["4".to_string()]);
+    let second = env.create_subject("second", profiles).await;
+
+    let group = Group {
+        uuid: uuid::Uuid::new_v4().to_string(),
+        created_at: Utc::now(),
+        created_by: env.user.uuid.clone(),
+        name: "group".to_string(),
+        subjects: vec![second],
+        description: None,
+    };
+    env.store.insert_group(&group).await.unwrap();
+
+    // Process one profile and leave another locked.
+    let mut leases = HashMap::new();
+    leases.insert("PLATFORM_1".to_string(), Utc::now() + Duration::hours(1));
+    let processed = env
+        .store
+        .lock_queue_item(&leases, &env.user.uuid)
+        .await
+        .unwrap()
+        .unwrap();
+    let later = Utc::now() + Duration::hours(1);
+    env.store
+        .complete_queue_item(&processed.queue_id, &env.user.uuid, later, later)
+        .await
+        .unwrap();
+    env.store
+        .lock_queue_item(&leases, &env.user.uuid)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let sr = env.get("/queue/stats").await;
+    assert!(sr.get("profiles").is_none());
+    let platforms = sr["platforms"].as_array().unwrap();
+    assert_eq!(platforms.len(), 2);
+    assert_eq!(platforms[0]["platform"], "PLATFORM_1");
+    assert_eq!(platforms[0]["total"], 3);
+    assert_eq!(platforms[0]["locked"], 1);
+    assert_eq!(platforms[0]["unconfirmed"], 3);
+    assert_eq!(platforms[0]["never_processed"], 2);
+    assert_eq!(platforms[0]["staleness"]["max"], 0);
+    let unprocessed = platforms[0]["oldest_never_processed"].as_array();
+    assert_eq!(unprocessed.unwrap().len(), 2);
+    assert_eq!(platforms[1]["platform"], "PLATFORM_2");
+    assert!(platforms[1]["staleness"].is_null());
+
+    let sr = env.get(&format!("/queue/stats?subjects=[{first}]")).await;
+    assert_eq!(sr["platforms"].as_array().unwrap().len(), 1);
+    let profiles = sr["profiles"].as_array().unwrap();
+    assert_eq!(profiles.len(), 3);
+    assert!(profiles[0]["last_processed"].is_null());
+    assert!(profiles[1]["last_processed"].is_null());
+    assert_eq!(profiles[2]["queue_id"], processed.queue_id.as_str());
+    assert_eq!(profiles[2]["staleness"], 0);
+
+    let sr = env
+        .get(&format!("/queue/stats?groups=[{}]", group.uuid))
+        .await;
+    assert_eq!(sr["platforms"][0]["platform"], "PLATFORM_2");
+    assert_eq!(sr["profiles"][0]["platform_id"], "4");
+
+    let other = User::new("other");
+    env.store.insert_user(&other).await.unwrap();
+    let uri =
+        format!("/queue/stats?subjects=[{first}]&groups=[{}]", group.uuid);
+    let sr = env.get_as(&other.key, &uri).await;
+    assert!(sr["platforms"].as_array().unwrap().is_empty());
+    assert!(sr["profiles"].as_array().unwrap().is_empty());
+
+    let uri = format!("/queue/stats?subjects=[{first}]&subjects=[{first}]");
+    let (status, sr) = env.try_get_as(&other.key, &uri).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(sr["response"], "ERROR");
+
+    env.cleanup().await;
+}
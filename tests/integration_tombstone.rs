@@ -0,0 +1,213 @@
+//! Tests for detecting deleted content from repeated profile fetches.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::server;
+use instrumentality::user::User;
+
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+
+fn meta() -> Data {
+    Data::Meta {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: "user1".to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: Utc::now(),
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn post(day: u32) -> Data {
+    Data::Content {
+        id: "1".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc::now(),
+        content_id: format!("post_{day}"),
+        deleted: None,
+        retrieved_from: None,
+        created_at: Some(Utc.with_ymd_and_hms(2022, 1, day, 0, 0, 0).unwrap()),
+        body: Some("Hello.".to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+// Takes the profile's job as the user with `key` and completes it with
+// `days` worth of posts.
+async fn fetch(env: &mut Environment, key: &str, days: &[u32]) -> String {
+    let job = env.get_as(key, "/queue?platforms=[PLATFORM_1]").await;
+    let queue_id = job["queue_id"].as_str().unwrap().to_string();
+
+    let mut data = vec![meta()];
+    data.extend(days.iter().map(|d| post(*d)));
+    env.add_as(key, data, Some(queue_id.clone())).await;
+    queue_id
+}
+
+/// test_tombstones tests:
+/// - Content missing from within the window of a later job is suspected
+///   deleted and its profile becomes hot.
+/// - Content outside the window is not suspected.
+/// - Content that reappears clears an unconfirmed tombstone.
+/// - A later job finding it missing confirms the tombstone and marks the
+///   content deleted, even when completed by the data provider that raised
+///   the suspicion. The content no longer refers to the signed submission it
+///   was stored from.
+/// - /view lists tombstones and can show only deleted or suspected deleted
+///   content, however old.
+/// - Suspicions raised by untrusted data providers are held back.
+#[tokio::test]
+async fn test_tombstones() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    // Every job is due again as soon as it is completed.
+    env.config.queue.max_staleness = 0;
+    env.config.queue.hot_interval = 0;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+    let query = format!("subjects={subject}");
+
+    // post_3 was stored from a signed submission.
+    let mut signed = post(3);
+    if let Data::Content {
+        added_by,
+        submission,
+        ..
+    } = &mut signed
+    {
+        *added_by = Some(env.user.uuid.clone());
+        *submission = Some("submission".to_string());
+    }
+    env.store.insert_data(&[signed]).await.unwrap();
+
+    let key = env.user.key.clone();
+    let queue_id = fetch(&mut env, &key, &[1, 2, 3, 4]).await;
+    let q_item = env.store.queue_item(&queue_id).await.unwrap().unwrap();
+    assert!(q_item.hot_until.is_none());
+
+    fetch(&mut env, &key, &[2, 4]).await;
+    let q_item = env.store.queue_item(&queue_id).await.unwrap().unwrap();
+    assert!(q_item.hot_until.is_some());
+    let profile = env.view_profile(&query).await;
+    let tombstones = profile["tombstones"].as_array().unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert_eq!(tombstones[0]["content_id"], "post_3");
+    assert_eq!(tombstones[0]["suspected_by"], env.user.uuid.as_str());
+    assert!(tombstones[0]["confirmed_at"].is_null());
+
+    fetch(&mut env, &key, &[2, 3, 4]).await;
+    let profile = env.view_profile(&query).await;
+    assert!(profile.get("tombstones").is_none());
+
+    fetch(&mut env, &key, &[2, 4]).await;
+    let profile = env.view_profile(&query).await;
+    assert!(profile["tombstones"][0]["confirmed_at"].is_null());
+    let profile = env.view_profile(&format!("{query}&deleted=true")).await;
+    let content = profile["content"].as_array().unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0]["content_id"], "post_3");
+    assert_ne!(content[0]["deleted"], true);
+    assert_eq!(content[0]["submission"], "submission");
+
+    fetch(&mut env, &key, &[2, 4]).await;
+    let profile = env.view_profile(&query).await;
+    let tombstones = profile["tombstones"].as_array().unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert!(tombstones[0]["confirmed_at"].is_string());
+    assert_eq!(tombstones[0]["confirmed_by"], env.user.uuid.as_str());
+    let profile = env.view_profile(&format!("{query}&deleted=true")).await;
+    assert_eq!(profile["content"][0]["deleted"], true);
+    assert!(profile["content"][0]["submission"].is_null());
+
+    // Suspicions are held back once their data provider is untrusted.
+    let lazy = User::new("lazy");
+    env.store.insert_user(&lazy).await.unwrap();
+    fetch(&mut env, &lazy.key, &[1, 4]).await;
+    let profile = env.view_profile(&query).await;
+    assert_eq!(profile["tombstones"].as_array().unwrap().len(), 2);
+    env.config.reputation.min_assessed = 1;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+    env.store
+        .record_reputation(&lazy.uuid, 10, 0)
+        .await
+        .unwrap();
+    let profile = env.view_profile(&query).await;
+    let tombstones = profile["tombstones"].as_array().unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert_eq!(tombstones[0]["content_id"], "post_3");
+
+    // Deleted content stays listed behind any amount of newer content.
+    let newer: Vec<Data> = (0..100)
+        .map(|i| {
+            let mut p = post(5);
+            if let Data::Content { content_id, .. } = &mut p {
+                *content_id = format!("newer_{i}");
+            }
+            p
+        })
+        .collect();
+    env.add(newer).await;
+    let profile = env.view_profile(&format!("{query}&deleted=true")).await;
+    let content = profile["content"].as_array().unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0]["content_id"], "post_3");
+    assert_eq!(content[0]["deleted"], true);
+
+    env.cleanup().await;
+}
+
+/// test_tombstones_independent_confirmation tests:
+/// - With independent confirmation, the data provider that raised a suspicion
+///   can't confirm it, but anyone else can.
+#[tokio::test]
+async fn test_tombstones_independent_confirmation() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    env.config.queue.max_staleness = 0;
+    env.config.queue.hot_interval = 0;
+    env.config.queue.independent_confirmation = true;
+    env.app = server::build_app(env.config.clone(), env.store.clone());
+
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["1".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+    let query = format!("subjects={subject}");
+
+    let key = env.user.key.clone();
+    let other = User::new("other");
+    env.store.insert_user(&other).await.unwrap();
+
+    fetch(&mut env, &key, &[1, 2, 3]).await;
+    fetch(&mut env, &key, &[1, 3]).await;
+    fetch(&mut env, &key, &[1, 3]).await;
+    let profile = env.view_profile(&query).await;
+    assert!(profile["tombstones"][0]["confirmed_at"].is_null());
+
+    fetch(&mut env, &other.key, &[1, 3]).await;
+    let profile = env.view_profile(&query).await;
+    let tombstones = profile["tombstones"].as_array().unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert_eq!(tombstones[0]["content_id"], "post_2");
+    assert_eq!(tombstones[0]["confirmed_by"], other.uuid.as_str());
+
+    env.cleanup().await;
+}
@@ -5,19 +5,30 @@ mod common;
 use common::Environment;
 use common::TEST_ENVIRONMENT_CONFIG;
 
+use instrumentality::server;
+
 use axum::http::StatusCode;
 use hyper::Body;
 use hyper::Request;
+use std::collections::HashMap;
 use tower::Service;
 
 /// test_types tests:
 /// - /types serves an OK response.
 /// - the response corresponds with the given configuration.
+/// - rules declared for types are published.
 #[tokio::test]
 async fn test_types() {
     use instrumentality::response::TypesResponse;
 
     let mut env: Environment = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let schema = toml::from_str(r#"required = ["body"]"#).unwrap();
+    let mut schemas = HashMap::new();
+    schemas.insert("post".to_string(), schema);
+    env.config
+        .content_schemas
+        .insert("PLATFORM_1".to_string(), schemas);
+    env.app = server::build_app(env.config.clone(), env.store.clone());
 
     let res = env
         .app
@@ -39,6 +50,12 @@ async fn test_types() {
     assert_eq!(tr.response, "OK");
     assert_eq!(tr.content_types, env.config.content_types);
     assert_eq!(tr.presence_types, env.config.presence_types);
+    assert_eq!(tr.relationship_types, env.config.relationship_types);
+    assert_eq!(tr.metric_types, env.config.metric_types);
+    let schema = &tr.content_schemas["PLATFORM_1"]["post"];
+    assert_eq!(schema.required, vec!["body".to_string()]);
+    assert!(schema.optional.is_none());
+    assert!(tr.presence_schemas.is_empty());
 
     env.cleanup().await;
 }
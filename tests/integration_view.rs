@@ -0,0 +1,158 @@
+//! Tests for viewing data about subjects.
+
+mod common;
+use common::Environment;
+use common::TEST_ENVIRONMENT_CONFIG;
+
+use instrumentality::data::Data;
+use instrumentality::subject::Subject;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::HashMap;
+
+fn content(body: &str, retrieved_at: DateTime<Utc>) -> Data {
+    Data::Content {
+        id: "123456789".to_string(),
+        platform: "PLATFORM_1".to_string(),
+        content_type: "post".to_string(),
+        retrieved_at,
+        content_id: "987654321".to_string(),
+        deleted: None,
+        retrieved_from: None,
+        created_at: None,
+        body: Some(body.to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+fn presence(retrieved_at: DateTime<Utc>) -> Data {
+    Data::Presence {
+        id: "123456789".to_string(),
+        platform: "PLATFORM_3".to_string(),
+        presence_type: "streaming".to_string(),
+        retrieved_at,
+        added_by: None,
+        added_at: None,
+        submission: None,
+    }
+}
+
+async fn subject(env: &Environment) -> Subject {
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), vec!["123456789".to_string()]);
+    let subject = Subject {
+        uuid: uuid::Uuid::new_v4().to_string(),
+        created_at: Utc::now(),
+        created_by: env.user.uuid.clone(),
+        name: "test".to_string(),
+        profiles,
+        description: None,
+    };
+    env.store.insert_subject(&subject).await.unwrap();
+    subject
+}
+
+/// test_view_revisions tests:
+/// - Every distinct body is kept as a revision, oldest first.
+/// - Observing a known version again widens its data provider's observation
+///   rather than adding another.
+/// - Revisions are only returned when asked for.
+#[tokio::test]
+async fn test_view_revisions() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let subject = subject(&env).await;
+
+    let t = |h| Utc.with_ymd_and_hms(2022, 1, 1, h, 0, 0).unwrap();
+    env.add(vec![content("Edited.", t(2))]).await;
+    env.add(vec![content("Hello.", t(1))]).await;
+    env.add(vec![content("Edited.", t(3))]).await;
+
+    let vr = env.get(&format!("/view?subjects={}", subject.uuid)).await;
+    let profile =
+        &vr["view_data"]["subject_data"][0]["platforms"][0]["profiles"][0];
+    assert_eq!(profile["content"].as_array().unwrap().len(), 1);
+    assert!(profile.get("revisions").is_none());
+
+    let vr = env
+        .get(&format!("/view?subjects={}&revisions=true", subject.uuid))
+        .await;
+    let profile =
+        &vr["view_data"]["subject_data"][0]["platforms"][0]["profiles"][0];
+    let revisions = &profile["revisions"][0]["revisions"];
+    assert_eq!(profile["revisions"][0]["content_id"], "987654321");
+    assert_eq!(revisions.as_array().unwrap().len(), 2);
+    assert_eq!(revisions[0]["body"], "Hello.");
+    assert_eq!(revisions[0]["observations"].as_array().unwrap().len(), 1);
+    assert_eq!(revisions[1]["body"], "Edited.");
+    let observations = revisions[1]["observations"].as_array().unwrap();
+    assert_eq!(observations.len(), 1);
+    assert_eq!(observations[0]["added_by"], env.user.uuid.as_str());
+    assert_eq!(observations[0]["first_seen"], "2022-01-01T02:00:00Z");
+    assert_eq!(observations[0]["last_seen"], "2022-01-01T03:00:00Z");
+
+    env.cleanup().await;
+}
+
+/// test_view_sessions tests:
+/// - Presence observations within the gap are joined into one session.
+/// - Sessions are bounded by the gap either side of them.
+/// - Only sessions that could have taken place in the range asked for are
+///   returned, however much presence has been observed since.
+#[tokio::test]
+async fn test_view_sessions() {
+    let mut env = Environment::new(TEST_ENVIRONMENT_CONFIG).await;
+    let mut profiles = HashMap::new();
+    profiles.insert("PLATFORM_3".to_string(), vec!["123456789".to_string()]);
+    let subject = env.create_subject("test", profiles).await;
+
+    let t = |d, h, m| Utc.with_ymd_and_hms(2022, 1, d, h, m, 0).unwrap();
+    let observations = [t(1, 20, 0), t(1, 20, 10), t(1, 20, 20), t(8, 18, 0)];
+    env.add(observations.into_iter().map(presence).collect())
+        .await;
+
+    let sessions = |vr: &serde_json::Value| {
+        vr["view_data"]["subject_data"][0]["platforms"][0]["profiles"][0]
+            ["sessions"]
+            .clone()
+    };
+
+    let vr = env.get(&format!("/view?subjects={subject}")).await;
+    let all = sessions(&vr);
+    assert_eq!(all.as_array().unwrap().len(), 2);
+    assert_eq!(all[0]["presence_type"], "streaming");
+    assert_eq!(all[0]["observations"], 3);
+    assert_eq!(all[0]["start"], "2022-01-01T20:00:00Z");
+    assert_eq!(all[0]["end"], "2022-01-01T20:20:00Z");
+    assert_eq!(all[0]["earliest_start"], "2022-01-01T19:45:00Z");
+    assert_eq!(all[0]["latest_end"], "2022-01-01T20:35:00Z");
+
+    let query = format!(
+        "subjects={subject}&from=2022-01-01T00:00:00Z&to=2022-01-07T00:00:00Z"
+    );
+    let vr = env.get(&format!("/view?{query}")).await;
+    let week = sessions(&vr);
+    assert_eq!(week.as_array().unwrap().len(), 1);
+    assert_eq!(week[0]["start"], "2022-01-01T20:00:00Z");
+
+    let later = (0..1000).map(|m| t(9, 0, 0) + Duration::minutes(m * 20));
+    env.add(later.map(presence).collect()).await;
+    let vr = env.get(&format!("/view?{query}")).await;
+    assert_eq!(sessions(&vr), week);
+
+    // The last observation is before the range, but within a gap of it. Those
+    // further out are left out, cutting the session short.
+    let query = format!(
+        "subjects={subject}&from=2022-01-01T20:30:00Z&to=2022-01-02T00:00:00Z"
+    );
+    let vr = env.get(&format!("/view?{query}")).await;
+    let late = sessions(&vr);
+    assert_eq!(late.as_array().unwrap().len(), 1);
+    assert_eq!(late[0]["start"], "2022-01-01T20:20:00Z");
+    assert_eq!(late[0]["end"], "2022-01-01T20:20:00Z");
+
+    env.cleanup().await;
+}